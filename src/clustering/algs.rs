@@ -2,7 +2,7 @@
 
 use rand::Rng;
 use rayon::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::BTreeMap,
     mem::take,
@@ -10,9 +10,9 @@ use std::{
 };
 use strum_macros::EnumIter;
 
-use crate::{ImgsimImage, ImgsimOptions, MatchEnumAsStr};
+use crate::{DescribeAlg, ImgsimImage, ImgsimOptions, MatchEnumAsStr};
 
-#[derive(Debug, Deserialize, EnumIter)]
+#[derive(Debug, Deserialize, Serialize, EnumIter)]
 /// Denotes the type of pixel clustering algorithm being utilised.
 pub enum ClusteringAlg {
     #[serde(alias = "kmeans")]
@@ -22,7 +22,32 @@ pub enum ClusteringAlg {
     /// Agglomerative clustering: More info at <https://github.com/maxgmr/imgsim>
     Agglomerative,
 }
-impl MatchEnumAsStr for ClusteringAlg {}
+impl MatchEnumAsStr for ClusteringAlg {
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            ClusteringAlg::KMeans => &["kmeans"],
+            ClusteringAlg::Agglomerative => &["agglo", "agglomerative", "agg"],
+        }
+    }
+}
+impl DescribeAlg for ClusteringAlg {
+    fn description(&self) -> &'static str {
+        match self {
+            ClusteringAlg::KMeans => "Splits the pixels into the number of clusters, up to max_k, that best separates them. Slow.",
+            ClusteringAlg::Agglomerative => "Merges adjacent pixels of similar colour into the same cluster.",
+        }
+    }
+
+    fn tunable_options(&self) -> &'static [&'static str] {
+        match self {
+            ClusteringAlg::KMeans => &[
+                "kmeans_options.max_k",
+                "kmeans_options.silhouette_threshold",
+            ],
+            ClusteringAlg::Agglomerative => &["agglomerative_options.tolerance"],
+        }
+    }
+}
 
 /// Take [ImgsimImage], build pixel clusters, then return
 pub fn get_clusters(imgsim_image: &mut ImgsimImage, imgsim_options: &ImgsimOptions) {
@@ -216,13 +241,6 @@ pub fn k_means(imgsim_image: &mut ImgsimImage, imgsim_options: &ImgsimOptions) {
     // This replaces the placeholder pixel_clusters
     let mut new_pixel_clusters: BTreeMap<usize, Vec<(u32, u32)>> = BTreeMap::new();
 
-    // Current best lookup table
-    let mut best_cluster_lookup: BTreeMap<(u32, u32), usize> = BTreeMap::new();
-    // Current best pixel clusters
-    let mut best_pixel_clusters: BTreeMap<usize, Vec<(u32, u32)>> = BTreeMap::new();
-
-    let mut silhouette: Vec<f32> = Vec::with_capacity(imgsim_options.max_k());
-
     // Iterate through possible k numbers until a reasonable silhouette is achieved
     for k in 2..(imgsim_options.max_k() + 1) {
         // Reset new_cluster_lookup and new_pixel_clusters from last iteration
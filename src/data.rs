@@ -1,3 +1,8 @@
+pub mod cluster_shape;
 pub mod helpers;
+pub mod image_id;
 pub mod imgsim_image;
+/// A k-d tree over sRGB colour space. Not yet in use.
 pub mod kd_tree;
+pub mod metric_index;
+pub mod transform;
@@ -0,0 +1,223 @@
+#![warn(missing_docs)]
+//! Shape descriptors of pixel clusters, built from image moments: <https://en.wikipedia.org/wiki/Image_moment>
+
+/// Descriptors of a single cluster's shape, independent of its colour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterShape {
+    cluster_id: usize,
+    size: usize,
+    bounding_box: (u32, u32, u32, u32),
+    extent: f32,
+    centroid: (f32, f32),
+    hu_moments: [f32; 7],
+    eccentricity: f32,
+    orientation: f32,
+}
+impl ClusterShape {
+    /// Builds the shape descriptors of the cluster made up of the given pixel coordinates, within an image of the given dimensions.
+    ///
+    /// Returns [Option::None] if the cluster has no pixels.
+    pub fn new(
+        cluster_id: usize,
+        pixels: &[(u32, u32)],
+        (width, height): (u32, u32),
+    ) -> Option<ClusterShape> {
+        if pixels.is_empty() {
+            return None;
+        }
+        let size = pixels.len();
+
+        let (mut left_x, mut top_y, mut right_x, mut bottom_y) = (u32::MAX, u32::MAX, 0, 0);
+        let (mut sum_x, mut sum_y) = (0.0_f64, 0.0_f64);
+        pixels.iter().for_each(|(x, y)| {
+            left_x = left_x.min(*x);
+            top_y = top_y.min(*y);
+            right_x = right_x.max(*x);
+            bottom_y = bottom_y.max(*y);
+            sum_x += *x as f64;
+            sum_y += *y as f64;
+        });
+        let (mean_x, mean_y) = (sum_x / size as f64, sum_y / size as f64);
+        let bounding_box_area = (right_x - left_x + 1) as f64 * (bottom_y - top_y + 1) as f64;
+
+        // Central moments up to the third order
+        let mut mu = [[0.0_f64; 4]; 4];
+        pixels.iter().for_each(|(x, y)| {
+            let dx = *x as f64 - mean_x;
+            let dy = *y as f64 - mean_y;
+            for (p, row) in mu.iter_mut().enumerate() {
+                for (q, moment) in row.iter_mut().enumerate().take(4 - p) {
+                    *moment += dx.powi(p as i32) * dy.powi(q as i32);
+                }
+            }
+        });
+
+        // Scale-invariant normalised central moments. Each pixel is also given its own area so single pixels and
+        // thin lines keep a non-zero second moment.
+        let mu_00 = size as f64;
+        let eta = |p: usize, q: usize| -> f64 {
+            let moment = if p + q == 2 && p != 1 {
+                mu[p][q] + mu_00 / 12.0
+            } else {
+                mu[p][q]
+            };
+            moment / mu_00.powf(1.0 + (p + q) as f64 / 2.0)
+        };
+        let (n20, n02, n11) = (eta(2, 0), eta(0, 2), eta(1, 1));
+        let (n30, n03, n21, n12) = (eta(3, 0), eta(0, 3), eta(2, 1), eta(1, 2));
+
+        let hu_moments = [
+            n20 + n02,
+            (n20 - n02).powi(2) + 4.0 * n11.powi(2),
+            (n30 - 3.0 * n12).powi(2) + (3.0 * n21 - n03).powi(2),
+            (n30 + n12).powi(2) + (n21 + n03).powi(2),
+            (n30 - 3.0 * n12) * (n30 + n12) * ((n30 + n12).powi(2) - 3.0 * (n21 + n03).powi(2))
+                + (3.0 * n21 - n03)
+                    * (n21 + n03)
+                    * (3.0 * (n30 + n12).powi(2) - (n21 + n03).powi(2)),
+            (n20 - n02) * ((n30 + n12).powi(2) - (n21 + n03).powi(2))
+                + 4.0 * n11 * (n30 + n12) * (n21 + n03),
+            (3.0 * n21 - n03) * (n30 + n12) * ((n30 + n12).powi(2) - 3.0 * (n21 + n03).powi(2))
+                - (n30 - 3.0 * n12)
+                    * (n21 + n03)
+                    * (3.0 * (n30 + n12).powi(2) - (n21 + n03).powi(2)),
+        ]
+        .map(|moment| moment as f32);
+
+        // Eigenvalues of the covariance matrix give the lengths of the cluster's major and minor axes
+        let common = ((n20 - n02).powi(2) + 4.0 * n11.powi(2)).sqrt();
+        let major = (n20 + n02 + common) / 2.0;
+        let minor = (n20 + n02 - common) / 2.0;
+        let eccentricity = if major > 0.0 {
+            (1.0 - (minor / major).max(0.0)).sqrt()
+        } else {
+            0.0
+        };
+        let orientation = 0.5 * (2.0 * n11).atan2(n20 - n02);
+
+        Some(ClusterShape {
+            cluster_id,
+            size,
+            bounding_box: (left_x, top_y, right_x, bottom_y),
+            extent: (size as f64 / bounding_box_area) as f32,
+            centroid: (
+                (mean_x / width as f64) as f32,
+                (mean_y / height as f64) as f32,
+            ),
+            hu_moments,
+            eccentricity: eccentricity as f32,
+            orientation: orientation as f32,
+        })
+    }
+
+    /// Returns the ID of the cluster.
+    pub fn cluster_id(&self) -> usize {
+        self.cluster_id
+    }
+
+    /// Returns the number of pixels in the cluster.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the cluster's bounding box as `(left_x, top_y, right_x, bottom_y)`, inclusive.
+    pub fn bounding_box(&self) -> (u32, u32, u32, u32) {
+        self.bounding_box
+    }
+
+    /// Returns the proportion of the cluster's bounding box covered by the cluster, from 0 to 1.
+    pub fn extent(&self) -> f32 {
+        self.extent
+    }
+
+    /// Returns the cluster's centre of mass as a proportion of the image's width and height.
+    pub fn centroid(&self) -> (f32, f32) {
+        self.centroid
+    }
+
+    /// Returns the cluster's seven Hu moments, which are invariant to translation, scale and rotation.
+    pub fn hu_moments(&self) -> &[f32; 7] {
+        &self.hu_moments
+    }
+
+    /// Returns how elongated the cluster is, from 0 (no preferred direction, e.g. a circle or square) to 1 (a line).
+    pub fn eccentricity(&self) -> f32 {
+        self.eccentricity
+    }
+
+    /// Returns the angle of the cluster's major axis in radians, from -π/2 to π/2, measured clockwise from the x-axis.
+    pub fn orientation(&self) -> f32 {
+        self.orientation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn rectangle(left: u32, top: u32, width: u32, height: u32) -> Vec<(u32, u32)> {
+        (top..top + height)
+            .flat_map(|y| (left..left + width).map(move |x| (x, y)))
+            .collect()
+    }
+
+    fn round(val: f32) -> f32 {
+        (val * 1000.0).round() / 1000.0
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(ClusterShape::new(0, &[], (10, 10)), None);
+    }
+
+    #[test]
+    fn filled_rectangle() {
+        let shape = ClusterShape::new(3, &rectangle(2, 4, 6, 2), (20, 10)).unwrap();
+        assert_eq!(shape.size(), 12);
+        assert_eq!(shape.bounding_box(), (2, 4, 7, 5));
+        assert_eq!(shape.extent(), 1.0);
+        assert_eq!(shape.centroid(), (0.225, 0.45));
+        assert_eq!(round(shape.orientation()), 0.0);
+    }
+
+    #[test]
+    fn l_shape() {
+        let mut pixels = rectangle(0, 0, 2, 8);
+        pixels.extend(rectangle(2, 6, 6, 2));
+        let shape = ClusterShape::new(0, &pixels, (8, 8)).unwrap();
+        assert_eq!(shape.extent(), 0.4375);
+    }
+
+    #[test]
+    fn square_eccentricity() {
+        let shape = ClusterShape::new(0, &rectangle(0, 0, 5, 5), (5, 5)).unwrap();
+        assert_eq!(round(shape.eccentricity()), 0.0);
+    }
+
+    #[test]
+    fn line_orientation() {
+        let shape = ClusterShape::new(0, &rectangle(0, 0, 1, 30), (30, 30)).unwrap();
+        assert!(shape.eccentricity() > 0.99);
+        assert_eq!(
+            round(shape.orientation().abs()),
+            round(std::f32::consts::FRAC_PI_2)
+        );
+    }
+
+    #[test]
+    fn hu_invariant() {
+        let mut pixels = rectangle(0, 0, 2, 8);
+        pixels.extend(rectangle(2, 6, 6, 2));
+        let rotated: Vec<(u32, u32)> = pixels.iter().map(|(x, y)| (7 - y, *x)).collect();
+        let hu = ClusterShape::new(0, &pixels, (8, 8))
+            .unwrap()
+            .hu_moments()
+            .map(round);
+        let hu_rotated = ClusterShape::new(0, &rotated, (8, 8))
+            .unwrap()
+            .hu_moments()
+            .map(round);
+        assert_eq!(hu, hu_rotated);
+    }
+}
@@ -18,6 +18,42 @@ pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
     (f(0, h, l, a), f(8, h, l, a), f(4, h, l, a))
 }
 
+/// Return the number of single-character insertions, deletions and substitutions needed to turn one string into the
+/// other: <https://en.wikipedia.org/wiki/Levenshtein_distance>
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut distances: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = distances[0];
+        distances[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != *b_char);
+            diagonal = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(diagonal + 1);
+        }
+    }
+    distances[b.len()]
+}
+
+/// Return the choices close enough to the given string to be a likely misspelling of it (case-insensitive), closest
+/// first.
+pub fn closest_matches<'a>(string: &str, choices: &'a [String]) -> Vec<&'a str> {
+    let string = string.to_lowercase();
+    let max_distance = (string.chars().count() / 3).max(1);
+    let mut matches: Vec<(usize, &str)> = choices
+        .iter()
+        .map(|choice| {
+            (
+                edit_distance(&string, &choice.to_lowercase()),
+                choice.as_str(),
+            )
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    matches.sort_by_key(|(distance, _)| *distance);
+    matches.into_iter().map(|(_, choice)| choice).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -27,4 +63,25 @@ mod tests {
     fn htr() {
         assert_eq!(hsl_to_rgb(60.0, 0.8182, 0.4314), (200, 200, 20));
     }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("texture", "texture"), 0);
+    }
+
+    #[test]
+    fn close_matches() {
+        let choices: Vec<String> = ["KMeans", "Agglomerative", "Texture"]
+            .iter()
+            .map(|choice| String::from(*choice))
+            .collect();
+        assert_eq!(
+            closest_matches("agglomerativ", &choices),
+            vec!["Agglomerative"]
+        );
+        assert_eq!(closest_matches("kmean", &choices), vec!["KMeans"]);
+        assert!(closest_matches("foo", &choices).is_empty());
+    }
 }
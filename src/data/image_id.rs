@@ -0,0 +1,168 @@
+#![warn(missing_docs)]
+//! Stable identities for images, so that images with the same file name in different places are never confused.
+
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+/// Identifies an image by its index in the list of images it was loaded into, along with its canonical path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ImageId {
+    index: usize,
+    path: PathBuf,
+}
+impl ImageId {
+    /// Creates a new [ImageId] out of an index and a path. The path should be canonical.
+    pub fn new(index: usize, path: PathBuf) -> ImageId {
+        ImageId { index, path }
+    }
+
+    /// Returns the index of the image in the list of images it was loaded into.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the canonical path of the image.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns a copy of this [ImageId] with a different index.
+    pub(crate) fn with_index(&self, index: usize) -> ImageId {
+        ImageId {
+            index,
+            path: self.path.clone(),
+        }
+    }
+}
+
+/// Derive a unique display name for each image from its preferred name.
+///
+/// Names shared by several images are replaced with the shortest trailing part of each image's path that tells them
+/// apart, such as `holiday/photo.jpg` and `work/photo.jpg`. Images with the same path are told apart by their index.
+pub fn display_names(ids: &[ImageId], names: &[String]) -> Vec<String> {
+    let mut display_names: Vec<String> = names.to_vec();
+    let mut name_counts: HashMap<&str, usize> = HashMap::new();
+    names
+        .iter()
+        .for_each(|name| *name_counts.entry(name).or_default() += 1);
+    let shared: Vec<usize> = (0..names.len())
+        .filter(|index| name_counts[names[*index].as_str()] > 1)
+        .collect();
+    if shared.is_empty() {
+        return display_names;
+    }
+
+    // Lengthen each shared name's path suffix until no other shared name has the same suffix
+    let components: Vec<Vec<String>> = shared
+        .iter()
+        .map(|index| {
+            ids[*index]
+                .path()
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect()
+        })
+        .collect();
+    let suffix = |position: usize, length: usize| {
+        let parts = &components[position];
+        parts[parts.len().saturating_sub(length)..].join("/")
+    };
+    let mut lengths: Vec<usize> = vec![1; shared.len()];
+    loop {
+        let mut suffix_counts: HashMap<String, usize> = HashMap::new();
+        (0..shared.len()).for_each(|position| {
+            *suffix_counts
+                .entry(suffix(position, lengths[position]))
+                .or_default() += 1
+        });
+        let mut lengthened = false;
+        (0..shared.len()).for_each(|position| {
+            if suffix_counts[&suffix(position, lengths[position])] > 1
+                && lengths[position] < components[position].len()
+            {
+                lengths[position] += 1;
+                lengthened = true;
+            }
+        });
+        if !lengthened {
+            break;
+        }
+    }
+
+    let mut suffix_counts: HashMap<String, usize> = HashMap::new();
+    (0..shared.len()).for_each(|position| {
+        *suffix_counts
+            .entry(suffix(position, lengths[position]))
+            .or_default() += 1
+    });
+    shared.iter().enumerate().for_each(|(position, index)| {
+        let name = suffix(position, lengths[position]);
+        display_names[*index] = if suffix_counts[&name] > 1 {
+            format!("{}#{}", name, ids[*index].index())
+        } else {
+            name
+        };
+    });
+    display_names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn ids(paths: &[&str]) -> Vec<ImageId> {
+        paths
+            .iter()
+            .enumerate()
+            .map(|(index, path)| ImageId::new(index, PathBuf::from(path)))
+            .collect()
+    }
+
+    fn file_names(paths: &[&str]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.rsplit('/').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn unique_names_kept() {
+        let paths = ["/a/x.png", "/b/y.png"];
+        assert_eq!(
+            display_names(&ids(&paths), &file_names(&paths)),
+            vec!["x.png", "y.png"]
+        );
+    }
+
+    #[test]
+    fn shared_names_lengthened() {
+        let paths = [
+            "/p/holiday/photo.jpg",
+            "/p/work/photo.jpg",
+            "/q/work/photo.jpg",
+            "/p/other.jpg",
+        ];
+        assert_eq!(
+            display_names(&ids(&paths), &file_names(&paths)),
+            vec![
+                "holiday/photo.jpg",
+                "p/work/photo.jpg",
+                "q/work/photo.jpg",
+                "other.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn same_path_uses_index() {
+        let paths = ["/a/x.png", "/a/x.png"];
+        assert_eq!(
+            display_names(&ids(&paths), &file_names(&paths)),
+            vec!["a/x.png#0", "a/x.png#1"]
+        );
+    }
+}
@@ -5,16 +5,22 @@ use rayon::prelude::*;
 use regex::Regex;
 use std::{collections::BTreeMap, path::PathBuf, time::Instant};
 
-use crate::{get_clusters, get_pixeldist, helpers::hsl_to_rgb, ImgsimOptions};
+use crate::{
+    get_clusters, get_pixeldist, helpers::hsl_to_rgb, ClusterShape, ImageId, ImgsimOptions,
+    Transform,
+};
 
 /// An [image::RgbaImage] with metadata, similarity factors, and clusters.
 pub struct ImgsimImage {
+    id: ImageId,
     name: String,
     path: PathBuf,
     rgba_image: RgbaImage,
     pixeldist_factors: Vec<PixeldistFactor>,
     cluster_lookup: BTreeMap<(u32, u32), usize>,
     pixel_clusters: BTreeMap<usize, Vec<(u32, u32)>>,
+    cluster_shapes: BTreeMap<usize, ClusterShape>,
+    transform: Transform,
 }
 impl ImgsimImage {
     /// Creates a new [ImgsimImage] by loading the image at the given file path.
@@ -43,6 +49,10 @@ impl ImgsimImage {
                     let image_width = rgba_image.width();
                     let image_height = rgba_image.height();
                     Some(ImgsimImage {
+                        id: ImageId::new(
+                            0,
+                            image_path.canonicalize().unwrap_or(image_path.clone()),
+                        ),
                         name: String::from(name),
                         path: image_path,
                         rgba_image,
@@ -51,6 +61,8 @@ impl ImgsimImage {
                         ),
                         cluster_lookup: BTreeMap::new(),
                         pixel_clusters: BTreeMap::new(),
+                        cluster_shapes: BTreeMap::new(),
+                        transform: Transform::Identity,
                     })
                 } else {
                     eprintln!(
@@ -152,10 +164,18 @@ impl ImgsimImage {
         }
     }
 
-    /// Group the image into clusters, filling out the `cluster_lookup` and `pixel_clusters` properties.
+    /// Group the image into clusters, filling out the `cluster_lookup`, `pixel_clusters` and `cluster_shapes` properties.
     pub fn build_clusters(&mut self, imgsim_options: &ImgsimOptions) {
         let start_time = Instant::now();
         get_clusters(self, imgsim_options);
+        let dimensions = self.rgba_image.dimensions();
+        self.cluster_shapes = self
+            .pixel_clusters
+            .par_iter()
+            .filter_map(|(cluster_id, pixels)| {
+                ClusterShape::new(*cluster_id, pixels, dimensions).map(|shape| (*cluster_id, shape))
+            })
+            .collect();
         let elapsed_time = start_time.elapsed();
         if imgsim_options.debug() || imgsim_options.verbose() {
             println!(
@@ -171,6 +191,51 @@ impl ImgsimImage {
         }
     }
 
+    /// Returns a flipped or rotated copy of the image, with its factors and clusters carried over to their new coordinates.
+    pub fn transformed(&self, transform: &Transform) -> ImgsimImage {
+        let dimensions = self.rgba_image.dimensions();
+        let move_coords = |coords: &(u32, u32)| transform.apply_coords(*coords, dimensions);
+        let rgba_image = transform.apply_image(&self.rgba_image);
+        let pixel_clusters: BTreeMap<usize, Vec<(u32, u32)>> = self
+            .pixel_clusters
+            .iter()
+            .map(|(cluster_id, pixels)| (*cluster_id, pixels.iter().map(move_coords).collect()))
+            .collect();
+        let new_dimensions = rgba_image.dimensions();
+        let cluster_shapes = pixel_clusters
+            .par_iter()
+            .filter_map(|(cluster_id, pixels)| {
+                ClusterShape::new(*cluster_id, pixels, new_dimensions)
+                    .map(|shape| (*cluster_id, shape))
+            })
+            .collect();
+        ImgsimImage {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            path: self.path.clone(),
+            rgba_image,
+            pixeldist_factors: self
+                .pixeldist_factors
+                .iter()
+                .map(|factor| {
+                    PixeldistFactor::new(
+                        move_coords(factor.a_coords()),
+                        move_coords(factor.b_coords()),
+                        factor.distance(),
+                    )
+                })
+                .collect(),
+            cluster_lookup: self
+                .cluster_lookup
+                .iter()
+                .map(|(coords, cluster_id)| (move_coords(coords), *cluster_id))
+                .collect(),
+            pixel_clusters,
+            cluster_shapes,
+            transform: *transform,
+        }
+    }
+
     /// Saves a visualisation of the image's clusters to the output directory specified in config.toml.
     pub fn save_cluster_image(&self, imgsim_options: &ImgsimOptions) {
         if let Some(output_dir) = imgsim_options.output_dir() {
@@ -193,13 +258,32 @@ impl ImgsimImage {
             let mut save_path = PathBuf::from(output_dir);
 
             let ext_regex = Regex::new(r"\.(?:bmp|dib|dds|gif|hdr|ico|jpg|jpeg|tiff)$").unwrap();
-            let filename = ext_regex.replace(self.name(), ".png");
+            // Images found in subdirectories are named by their relative path
+            let flat_name = self.name().replace('/', "_");
+            let filename = ext_regex.replace(&flat_name, ".png");
 
             save_path.push(format!("clusters-{}", filename));
             cluster_diagram.save(save_path).unwrap();
         }
     }
 
+    /// Replaces the index of the image's [ImageId], once its place in the list of loaded images is known.
+    pub(crate) fn with_index(mut self, index: usize) -> ImgsimImage {
+        self.id = self.id.with_index(index);
+        self
+    }
+
+    /// Replaces the name of the image, such as with its path relative to the directory it was found in.
+    pub(crate) fn with_name(mut self, name: String) -> ImgsimImage {
+        self.name = name;
+        self
+    }
+
+    /// Returns the identity of the image.
+    pub fn id(&self) -> &ImageId {
+        &self.id
+    }
+
     /// Returns the name of the image.
     pub fn name(&self) -> &str {
         &self.name
@@ -239,6 +323,16 @@ impl ImgsimImage {
     pub fn pixel_clusters_mut(&mut self) -> &mut BTreeMap<usize, Vec<(u32, u32)>> {
         &mut self.pixel_clusters
     }
+
+    /// Returns a reference to the shape descriptors of each of the image's non-empty clusters.
+    pub fn cluster_shapes(&self) -> &BTreeMap<usize, ClusterShape> {
+        &self.cluster_shapes
+    }
+
+    /// Returns the transform applied to the image, or [Transform::Identity] if it was loaded as-is.
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
 }
 
 /// A factor between two pixels denoting the colour distance between them.
@@ -0,0 +1,360 @@
+#![warn(missing_docs)]
+//! Metric indices which find the items within a distance of a query, or nearest to it, without comparing every item.
+//!
+//! Both indices rely on the distance obeying the triangle inequality. A [VpTree] suits real-valued distances such as
+//! those between feature vectors, and a [BkTree] suits integer distances such as the Hamming distance between hashes.
+
+use std::collections::HashMap;
+
+/// Return the number of bits which differ between two hashes.
+pub fn hamming_distance(hash_a: &u64, hash_b: &u64) -> u32 {
+    (hash_a ^ hash_b).count_ones()
+}
+
+// Keep the `k` nearest items found so far, sorted from nearest to furthest.
+fn insert_nearest<N: PartialOrd + Copy>(
+    nearest: &mut Vec<(usize, N)>,
+    k: usize,
+    candidate: (usize, N),
+) {
+    if nearest.len() == k && nearest.last().is_some_and(|(_, d)| candidate.1 >= *d) {
+        return;
+    }
+    let position = nearest.partition_point(|(_, d)| *d <= candidate.1);
+    nearest.insert(position, candidate);
+    nearest.truncate(k);
+}
+
+#[derive(Debug)]
+struct VpNode {
+    item: usize,
+    // Items nearer than this to the vantage point are inside, the rest are outside
+    radius: f32,
+    inside: Option<usize>,
+    outside: Option<usize>,
+}
+
+/// A vantage-point tree over items with a real-valued metric distance.
+///
+/// Each node splits the items beneath it by their distance to the node's item, so whole subtrees can be skipped when
+/// searching.
+pub struct VpTree<T, D: Fn(&T, &T) -> f32> {
+    items: Vec<T>,
+    nodes: Vec<VpNode>,
+    root: Option<usize>,
+    distance: D,
+}
+impl<T, D: Fn(&T, &T) -> f32> VpTree<T, D> {
+    /// Builds a [VpTree] out of the given items and metric distance. Items are referred to by their index in `items`.
+    pub fn new(items: Vec<T>, distance: D) -> VpTree<T, D> {
+        let mut tree = VpTree {
+            nodes: Vec::with_capacity(items.len()),
+            root: None,
+            items,
+            distance,
+        };
+        let indices: Vec<usize> = (0..tree.items.len()).collect();
+        tree.root = tree.build(indices);
+        tree
+    }
+
+    fn build(&mut self, mut indices: Vec<usize>) -> Option<usize> {
+        let vantage_point = indices.pop()?;
+        let mut distances: Vec<(usize, f32)> = indices
+            .into_iter()
+            .map(|index| {
+                (
+                    index,
+                    (self.distance)(&self.items[vantage_point], &self.items[index]),
+                )
+            })
+            .collect();
+        let median = distances.len() / 2;
+        let radius = if distances.is_empty() {
+            0.0
+        } else {
+            distances.select_nth_unstable_by(median, |(_, a), (_, b)| a.total_cmp(b));
+            distances[median].1
+        };
+        let outside: Vec<usize> = distances
+            .split_off(median)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        let inside: Vec<usize> = distances.into_iter().map(|(index, _)| index).collect();
+
+        let node = self.nodes.len();
+        self.nodes.push(VpNode {
+            item: vantage_point,
+            radius,
+            inside: None,
+            outside: None,
+        });
+        self.nodes[node].inside = self.build(inside);
+        self.nodes[node].outside = self.build(outside);
+        Some(node)
+    }
+
+    /// Returns the number of items in the tree.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns true if the tree has no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the items of the tree, in the order they were given.
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Return the index and distance of every item within `radius` of the query, in no particular order.
+    pub fn within(&self, query: &T, radius: f32) -> Vec<(usize, f32)> {
+        let mut found = Vec::new();
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let distance = (self.distance)(query, &self.items[node.item]);
+            if distance <= radius {
+                found.push((node.item, distance));
+            }
+            if distance - radius <= node.radius {
+                stack.extend(node.inside);
+            }
+            if distance + radius >= node.radius {
+                stack.extend(node.outside);
+            }
+        }
+        found
+    }
+
+    /// Return the index and distance of the `k` items nearest to the query, from nearest to furthest.
+    pub fn nearest(&self, query: &T, k: usize) -> Vec<(usize, f32)> {
+        let mut nearest = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.search_nearest(self.root, query, k, &mut nearest);
+        }
+        nearest
+    }
+
+    fn search_nearest(
+        &self,
+        node: Option<usize>,
+        query: &T,
+        k: usize,
+        nearest: &mut Vec<(usize, f32)>,
+    ) {
+        let Some(node) = node else {
+            return;
+        };
+        let node = &self.nodes[node];
+        let distance = (self.distance)(query, &self.items[node.item]);
+        insert_nearest(nearest, k, (node.item, distance));
+
+        // The furthest distance still worth searching
+        let tau = |nearest: &Vec<(usize, f32)>| {
+            if nearest.len() < k {
+                f32::INFINITY
+            } else {
+                nearest.last().map_or(f32::INFINITY, |(_, d)| *d)
+            }
+        };
+        if distance < node.radius {
+            self.search_nearest(node.inside, query, k, nearest);
+            if distance + tau(nearest) >= node.radius {
+                self.search_nearest(node.outside, query, k, nearest);
+            }
+        } else {
+            self.search_nearest(node.outside, query, k, nearest);
+            if distance - tau(nearest) <= node.radius {
+                self.search_nearest(node.inside, query, k, nearest);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BkNode {
+    item: usize,
+    children: HashMap<u32, usize>,
+}
+
+/// A Burkhard-Keller tree over items with an integer metric distance, such as [hamming_distance].
+///
+/// Each child of a node holds the items at one exact distance from the node's item.
+pub struct BkTree<T, D: Fn(&T, &T) -> u32> {
+    items: Vec<T>,
+    nodes: Vec<BkNode>,
+    distance: D,
+}
+impl<T, D: Fn(&T, &T) -> u32> BkTree<T, D> {
+    /// Builds a [BkTree] out of the given items and metric distance. Items are referred to by their index in `items`.
+    pub fn new(items: Vec<T>, distance: D) -> BkTree<T, D> {
+        let mut tree = BkTree {
+            nodes: Vec::with_capacity(items.len()),
+            items,
+            distance,
+        };
+        for item in 0..tree.items.len() {
+            tree.insert(item);
+        }
+        tree
+    }
+
+    fn insert(&mut self, item: usize) {
+        let new_node = self.nodes.len();
+        self.nodes.push(BkNode {
+            item,
+            children: HashMap::new(),
+        });
+        if new_node == 0 {
+            return;
+        }
+        let mut node = 0;
+        loop {
+            let distance = (self.distance)(&self.items[self.nodes[node].item], &self.items[item]);
+            match self.nodes[node].children.get(&distance) {
+                Some(child) => node = *child,
+                None => {
+                    self.nodes[node].children.insert(distance, new_node);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Returns the number of items in the tree.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns true if the tree has no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the items of the tree, in the order they were given.
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Return the index and distance of every item within `radius` of the query, in no particular order.
+    pub fn within(&self, query: &T, radius: u32) -> Vec<(usize, u32)> {
+        let mut found = Vec::new();
+        let mut stack: Vec<usize> = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![0]
+        };
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let distance = (self.distance)(query, &self.items[node.item]);
+            if distance <= radius {
+                found.push((node.item, distance));
+            }
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(child_distance, _)| child_distance.abs_diff(distance) <= radius)
+                    .map(|(_, child)| *child),
+            );
+        }
+        found
+    }
+
+    /// Return the index and distance of the `k` items nearest to the query, from nearest to furthest.
+    pub fn nearest(&self, query: &T, k: usize) -> Vec<(usize, u32)> {
+        let mut nearest = Vec::with_capacity(k + 1);
+        if k == 0 || self.nodes.is_empty() {
+            return nearest;
+        }
+        let mut stack: Vec<usize> = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let distance = (self.distance)(query, &self.items[node.item]);
+            insert_nearest(&mut nearest, k, (node.item, distance));
+            let tau = if nearest.len() < k {
+                u32::MAX
+            } else {
+                nearest.last().map_or(u32::MAX, |(_, d)| *d)
+            };
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(child_distance, _)| child_distance.abs_diff(distance) <= tau)
+                    .map(|(_, child)| *child),
+            );
+        }
+        nearest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn points() -> Vec<(f32, f32)> {
+        (0..50)
+            .map(|i| (((i * 37) % 50) as f32, ((i * 11) % 13) as f32))
+            .collect()
+    }
+
+    fn euclidean(a: &(f32, f32), b: &(f32, f32)) -> f32 {
+        ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+    }
+
+    #[test]
+    fn vp_within_matches_scan() {
+        let tree = VpTree::new(points(), euclidean);
+        let query = (20.0, 6.0);
+        let mut found: Vec<usize> = tree
+            .within(&query, 7.5)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        found.sort_unstable();
+        let expected: Vec<usize> = (0..50)
+            .filter(|index| euclidean(&query, &points()[*index]) <= 7.5)
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn vp_nearest_matches_scan() {
+        let tree = VpTree::new(points(), euclidean);
+        let query = (3.5, 9.0);
+        let mut expected: Vec<(usize, f32)> = (0..50)
+            .map(|index| (index, euclidean(&query, &points()[index])))
+            .collect();
+        expected.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        expected.truncate(4);
+        assert_eq!(
+            tree.nearest(&query, 4)
+                .iter()
+                .map(|(_, d)| *d)
+                .collect::<Vec<f32>>(),
+            expected.iter().map(|(_, d)| *d).collect::<Vec<f32>>()
+        );
+    }
+
+    #[test]
+    fn bk_within_and_nearest() {
+        let hashes: Vec<u64> = vec![0b0000, 0b0001, 0b0011, 0b0111, 0b1111, 0b1000];
+        let tree = BkTree::new(hashes, hamming_distance);
+        let mut found = tree.within(&0b0000, 1);
+        found.sort_unstable();
+        assert_eq!(found, vec![(0, 0), (1, 1), (5, 1)]);
+        assert_eq!(tree.nearest(&0b0110, 1), vec![(3, 1)]);
+    }
+
+    #[test]
+    fn empty_trees() {
+        let vp_tree = VpTree::new(Vec::<(f32, f32)>::new(), euclidean);
+        assert_eq!(vp_tree.nearest(&(0.0, 0.0), 3), vec![]);
+        let bk_tree = BkTree::new(Vec::<u64>::new(), hamming_distance);
+        assert_eq!(bk_tree.within(&0, 5), vec![]);
+    }
+}
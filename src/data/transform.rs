@@ -0,0 +1,114 @@
+#![warn(missing_docs)]
+//! Flips and right-angle rotations used to compare mirrored and rotated copies of an image.
+
+use image::{imageops, RgbaImage};
+use strum_macros::EnumIter;
+
+/// A flip or rotation applied to an image before it is compared.
+///
+/// Rotations are clockwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum Transform {
+    #[default]
+    /// The image is left as-is.
+    Identity,
+    /// The image is mirrored left-to-right.
+    FlipHorizontal,
+    /// The image is mirrored top-to-bottom.
+    FlipVertical,
+    /// The image is rotated by 90°.
+    Rotate90,
+    /// The image is rotated by 180°.
+    Rotate180,
+    /// The image is rotated by 270°.
+    Rotate270,
+}
+impl Transform {
+    /// Returns the transform which undoes this one.
+    pub fn inverse(&self) -> Transform {
+        match self {
+            Transform::Rotate90 => Transform::Rotate270,
+            Transform::Rotate270 => Transform::Rotate90,
+            transform => *transform,
+        }
+    }
+
+    /// Returns the dimensions of an image of the given dimensions after the transform.
+    pub fn apply_dimensions(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            Transform::Rotate90 | Transform::Rotate270 => (height, width),
+            _ => (width, height),
+        }
+    }
+
+    /// Returns where the pixel at the given coordinates ends up after the transform, within an image of the given dimensions.
+    pub fn apply_coords(&self, (x, y): (u32, u32), (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            Transform::Identity => (x, y),
+            Transform::FlipHorizontal => (width - 1 - x, y),
+            Transform::FlipVertical => (x, height - 1 - y),
+            Transform::Rotate90 => (height - 1 - y, x),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Rotate270 => (y, width - 1 - x),
+        }
+    }
+
+    /// Returns a transformed copy of the given image.
+    pub fn apply_image(&self, rgba_image: &RgbaImage) -> RgbaImage {
+        match self {
+            Transform::Identity => rgba_image.clone(),
+            Transform::FlipHorizontal => imageops::flip_horizontal(rgba_image),
+            Transform::FlipVertical => imageops::flip_vertical(rgba_image),
+            Transform::Rotate90 => imageops::rotate90(rgba_image),
+            Transform::Rotate180 => imageops::rotate180(rgba_image),
+            Transform::Rotate270 => imageops::rotate270(rgba_image),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use strum::IntoEnumIterator;
+
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(5, 3, |x, y| image::Rgba([x as u8, y as u8, 0, 255]))
+    }
+
+    #[test]
+    fn coords_match_image() {
+        let original = gradient();
+        for transform in Transform::iter() {
+            let transformed = transform.apply_image(&original);
+            assert_eq!(
+                transformed.dimensions(),
+                transform.apply_dimensions(original.dimensions())
+            );
+            original.enumerate_pixels().for_each(|(x, y, pixel)| {
+                let (new_x, new_y) = transform.apply_coords((x, y), original.dimensions());
+                assert_eq!(
+                    transformed.get_pixel(new_x, new_y),
+                    pixel,
+                    "{:?}",
+                    transform
+                );
+            });
+        }
+    }
+
+    #[test]
+    fn inverse_undoes() {
+        let original = gradient();
+        for transform in Transform::iter() {
+            let transformed = transform.apply_image(&original);
+            assert_eq!(transform.inverse().apply_image(&transformed), original);
+        }
+    }
+
+    #[test]
+    fn rotate_90_dimensions() {
+        assert_eq!(Transform::Rotate90.apply_dimensions((5, 3)), (3, 5));
+        assert_eq!(Transform::FlipVertical.apply_dimensions((5, 3)), (5, 3));
+    }
+}
@@ -5,6 +5,7 @@
 #![warn(missing_docs)]
 
 use std::fmt::Debug;
+use strum::IntoEnumIterator;
 
 mod clustering;
 mod data;
@@ -13,21 +14,40 @@ mod pixeldist;
 mod similarity;
 
 pub use persistence::errors::PersistenceError;
-pub use persistence::load_images::load_images;
-pub use persistence::options::ImgsimOptions;
+pub use persistence::glob::Glob;
+pub use persistence::load_images::{identify_images, load_against, load_images, load_query};
+pub use persistence::options::{ImgsimOptions, ImgsimOptionsBuilder, ValueSource};
 
+pub use data::cluster_shape::ClusterShape;
 pub use data::helpers;
+pub use data::image_id::{display_names, ImageId};
 pub use data::imgsim_image::{ImgsimImage, PixeldistFactor};
 pub use data::kd_tree;
+pub use data::metric_index;
+pub use data::transform::Transform;
 
 pub use pixeldist::algs::{get_pixeldist, PixeldistAlg};
 
 pub use clustering::algs::{get_clusters, ClusteringAlg};
 
 pub use similarity::algs::{get_similarities, ImageSimilarityMatrix, SimilarityAlg};
+pub use similarity::containment::{get_containments, print_containments, Containment};
+pub use similarity::cross::{
+    get_cross_similarities, get_query_similarities, CrossSimilarityMatrix,
+};
+pub use similarity::dendrogram::{cut_to_groups, Dendrogram, Linkage, Merge};
+pub use similarity::dhash;
+pub use similarity::edge_orientation;
+pub use similarity::ensemble::{ensemble, EnsembleCombine, EnsembleMember, LogisticWeights};
 pub use similarity::errors::ImageSimilarityMatrixNoMatchError;
+pub use similarity::grouping::{group_images, print_groups, GroupingMethod, ImageGroup};
+pub use similarity::indexed::{get_nearest_similarities, group_images_indexed, supports_index};
+pub use similarity::keypoints;
+pub use similarity::region_graph::{RegionGraph, RegionNode};
+pub use similarity::texture;
 
-/// Values that implement [MatchEnumAsStr] can compare their enum values to a given `&str` (case-insensitive)
+/// Values that implement [MatchEnumAsStr] can compare their enum values, or their aliases, to a given `&str`
+/// (case-insensitive)
 ///
 /// See function [MatchEnumAsStr::match_enum_as_str]
 pub trait MatchEnumAsStr: Debug {
@@ -63,6 +83,43 @@ pub trait MatchEnumAsStr: Debug {
     /// assert_eq![Colour::Blue.match_enum_as_str("yellow"), false]
     /// ```
     fn match_enum_as_str(&self, string: &str) -> bool {
-        format!("{:?}", &self).to_lowercase() == string
+        format!("{:?}", &self).eq_ignore_ascii_case(string)
+            || self
+                .aliases()
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(string))
+    }
+
+    /// Return the other names the enum value is known by, the same as its `#[serde(alias)]`es in config.toml.
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Return the enum value matching the given string or one of its aliases (case-insensitive).
+    ///
+    /// Returns [PersistenceError::UnknownChoiceError], listing every valid value, if none match.
+    fn from_enum_str(string: &str) -> Result<Self, PersistenceError>
+    where
+        Self: IntoEnumIterator,
+    {
+        Self::iter()
+            .find(|value| value.match_enum_as_str(string))
+            .ok_or_else(|| {
+                PersistenceError::UnknownChoiceError(
+                    String::from(string),
+                    Self::iter().map(|value| format!("{:?}", value)).collect(),
+                )
+            })
+    }
+}
+
+/// Values that implement [DescribeAlg] can describe what they do and which options tune them.
+pub trait DescribeAlg {
+    /// Return a short description of the algorithm.
+    fn description(&self) -> &'static str;
+
+    /// Return the dotted config key paths of the options which tune the algorithm, e.g. `kmeans_options.max_k`.
+    fn tunable_options(&self) -> &'static [&'static str] {
+        &[]
     }
 }
@@ -1,15 +1,46 @@
-use clap::{command, Arg};
-use std::{path::PathBuf, process};
+use clap::{command, Arg, ArgMatches, Command};
+use std::{path::PathBuf, process, slice};
+use strum::IntoEnumIterator;
 
-use imgsim::{get_similarities, load_images, ImgsimOptions};
+use imgsim::{
+    cut_to_groups, get_containments, get_cross_similarities, get_nearest_similarities,
+    get_query_similarities, get_similarities, group_images, group_images_indexed, load_against,
+    load_images, load_query, print_containments, print_groups, ClusteringAlg, Dendrogram,
+    DescribeAlg, ImgsimImage, ImgsimOptions, ImgsimOptionsBuilder, Linkage, MatchEnumAsStr,
+    PersistenceError, PixeldistAlg, SimilarityAlg, ValueSource,
+};
 
 fn main() {
     let match_result = command!()
         .about("A tool that finds similar images through various methods.")
         .arg(
-            Arg::new("input_dir")
+            Arg::new("paths")
+                .value_name("input_dir|files")
+                .num_args(0..)
                 .value_parser(clap::value_parser!(PathBuf))
-                .help("The path to the directory of images you wish to compare (default: working directory)"),
+                .help("The path to the directory of images you wish to compare (default: working directory), or the paths of individual images to compare"),
+        )
+        .arg(
+            Arg::new("files_from")
+                .long("files-from")
+                .value_name("file|-")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Compare the images listed in this file, one per line or NUL-separated, instead of a directory. '-' reads the list from stdin")
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("path")
+                .value_parser(clap::value_parser!(PathBuf))
+                .global(true)
+                .help("The config file to use instead of the one in the config directory. Can also be set with IMGSIM_CONFIG")
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("name")
+                .global(true)
+                .help("Use the options in the [profiles.<name>] section of the config. Built-in profiles: duplicates, palette, layout")
         )
         .arg(
             Arg::new("pixeldist_alg")
@@ -43,6 +74,115 @@ fn main() {
                 .action(clap::ArgAction::SetTrue)
                 .help("Allow imgsim to run with discouraged settings")
         )
+        .arg(
+            Arg::new("transform_invariant")
+                .short('t')
+                .long("transform-invariant")
+                .action(clap::ArgAction::SetTrue)
+                .help("Also compare flipped and 90°/180°/270° rotated copies of each image, keeping the best score")
+        )
+        .arg(
+            Arg::new("recursive")
+                .short('r')
+                .long("recursive")
+                .action(clap::ArgAction::SetTrue)
+                .help("Also search the subdirectories of the input directory for images")
+        )
+        .arg(
+            Arg::new("max_depth")
+                .long("max-depth")
+                .value_parser(clap::value_parser!(usize))
+                .help("Search at most this many levels of subdirectories for images. Implies --recursive")
+        )
+        .arg(
+            Arg::new("follow_symlinks")
+                .long("follow-symlinks")
+                .action(clap::ArgAction::SetTrue)
+                .help("Follow symbolic links to directories when searching recursively")
+        )
+        .arg(
+            Arg::new("hidden")
+                .long("hidden")
+                .action(clap::ArgAction::SetTrue)
+                .help("Include hidden files and directories, whose names start with '.'")
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .action(clap::ArgAction::Append)
+                .help("Only load images whose path relative to the input directory matches this glob pattern. Can be given more than once")
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .action(clap::ArgAction::Append)
+                .help("Skip images and directories whose path relative to the input directory matches this glob pattern. Can be given more than once")
+        )
+        .arg(
+            Arg::new("query")
+                .short('q')
+                .long("query")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Compare only this image against each image in the input directory")
+        )
+        .arg(
+            Arg::new("against")
+                .short('a')
+                .long("against")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with("query")
+                .help("Compare each image in the input directory only against the images in this directory, printing each one's best match")
+        )
+        .arg(
+            Arg::new("top")
+                .short('k')
+                .long("top")
+                .value_parser(clap::value_parser!(usize))
+                .help("Print each image's k most similar images instead of every pairing")
+        )
+        .arg(
+            Arg::new("threshold")
+                .short('g')
+                .long("threshold")
+                .value_parser(clap::value_parser!(f32))
+                .help("Print groups of images whose similarity is above this threshold (-1 to 1) instead of every pairing")
+        )
+        .arg(
+            Arg::new("dendrogram")
+                .long("dendrogram")
+                .action(clap::ArgAction::SetTrue)
+                .help("Print a tree of the images, built by hierarchical clustering, instead of every pairing")
+        )
+        .arg(
+            Arg::new("linkage")
+                .long("linkage")
+                .help("Choose the linkage used to build the dendrogram")
+        )
+        .arg(
+            Arg::new("cut_height")
+                .long("cut-height")
+                .value_parser(clap::value_parser!(f32))
+                .help("Print the groups formed by cutting the dendrogram at this height (0 to 1)")
+        )
+        .arg(
+            Arg::new("cut_count")
+                .long("cut-count")
+                .value_parser(clap::value_parser!(usize))
+                .help("Print the groups formed by cutting the dendrogram into this many groups")
+        )
+        .arg(
+            Arg::new("newick")
+                .long("newick")
+                .action(clap::ArgAction::SetTrue)
+                .help("Print the dendrogram in Newick format")
+        )
+        .arg(
+            Arg::new("containment")
+                .long("containment")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["query", "against"])
+                .help("Also report images which are crops or scaled sub-regions of other images")
+        )
         .arg(
             Arg::new("output_dir")
                 .short('o')
@@ -50,15 +190,73 @@ fn main() {
                 .value_parser(clap::value_parser!(PathBuf))
                 .help("The directory to which debug images are saved. Leave this blank to not save any debug images.")
         )
+        .arg(
+            Arg::new("list_algorithms")
+                .long("list-algorithms")
+                .action(clap::ArgAction::SetTrue)
+                .help("Print every algorithm, along with what it does and the config options which tune it, then exit")
+        )
+        .subcommand_precedence_over_arg(true)
+        .subcommand(
+            Command::new("config")
+                .about("Create, print or check a config file")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("init")
+                        .about("Write the default config to the config directory, or to the path given with --config or IMGSIM_CONFIG")
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .action(clap::ArgAction::SetTrue)
+                                .help("Overwrite the config if it already exists")
+                        )
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Print the config in use as TOML, after merging it over the defaults and applying any profile and environment variables")
+                )
+                .subcommand(
+                    Command::new("validate")
+                        .about("Check the given config file and each of its profiles for unknown keys and out-of-range values")
+                        .arg(
+                            Arg::new("file")
+                                .required(true)
+                                .value_parser(clap::value_parser!(PathBuf))
+                                .help("The config file to check")
+                        )
+                )
+        )
         .get_matches();
 
-    let imgsim_options = match ImgsimOptions::build(match_result) {
+    if let Some(("config", config_matches)) = match_result.subcommand() {
+        if let Err(persistence_error) = config_command(config_matches) {
+            eprintln!("{}", persistence_error);
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+
+    if match_result.get_flag("list_algorithms") {
+        print_algorithms();
+        process::exit(0);
+    }
+
+    let imgsim_options = match build_options(&match_result) {
         Ok(imgsim_options) => imgsim_options,
         Err(persistence_error) => {
             eprintln!("{}", persistence_error);
             process::exit(1);
         }
     };
+    print_selected_algorithms(&imgsim_options);
+
+    let mut query = match load_query(&imgsim_options) {
+        Ok(query) => query,
+        Err(persistence_error) => {
+            eprintln!("{}", persistence_error);
+            process::exit(1);
+        }
+    };
 
     let mut images = match load_images(&imgsim_options) {
         Ok(images) => images,
@@ -67,11 +265,22 @@ fn main() {
             process::exit(1);
         }
     };
+    let against_images = match load_against(&imgsim_options) {
+        Ok(against_images) => against_images,
+        Err(persistence_error) => {
+            eprintln!("{}", persistence_error);
+            process::exit(1);
+        }
+    };
     if imgsim_options.debug() || imgsim_options.verbose() {
         println!(
-            "{} images loaded from {:?}:\n{}",
+            "{} images loaded from {}:\n{}",
             images.len(),
-            imgsim_options.input_dir(),
+            if imgsim_options.uses_file_list() {
+                String::from("the given files")
+            } else {
+                format!("{:?}", imgsim_options.input_dir())
+            },
             images
                 .iter()
                 .map(|image| format!("\t{}", image.name()))
@@ -86,7 +295,371 @@ fn main() {
         image.save_cluster_image(&imgsim_options);
     });
 
-    let image_similarity_matrix = get_similarities(&images, &imgsim_options);
-    image_similarity_matrix.print();
+    // containment = true from the config can't be rejected by the cli, so say that it is skipped instead
+    if imgsim_options.containment() && (query.is_some() || against_images.is_some()) {
+        eprintln!(
+            "Warning: Containment is not checked with --query or --against, so it is skipped."
+        );
+    }
+
+    // Only the pairings between the two directories are compared, so the all-pairs outputs are skipped
+    if let Some(mut against_images) = against_images {
+        against_images.iter_mut().for_each(|image| {
+            image.build_factors(&imgsim_options);
+            image.build_clusters(&imgsim_options);
+            image.save_cluster_image(&imgsim_options);
+        });
+        // Only each image's best matches are printed, so a metric index can skip the rest when the algorithm allows it
+        let cross_similarity_matrix = get_nearest_similarities(
+            &images,
+            &against_images,
+            imgsim_options.top().unwrap_or(1),
+            &imgsim_options,
+        )
+        .unwrap_or_else(|| get_cross_similarities(&images, &against_images, &imgsim_options));
+        match imgsim_options.top() {
+            Some(k) => cross_similarity_matrix.print_top(k),
+            None => cross_similarity_matrix.print_best(),
+        }
+        process::exit(0);
+    }
+
+    // Only the query's pairings are compared, so the all-pairs outputs are skipped
+    if let Some(query) = query.as_mut() {
+        query.build_factors(&imgsim_options);
+        query.build_clusters(&imgsim_options);
+        query.save_cluster_image(&imgsim_options);
+        match imgsim_options.top() {
+            Some(k) => {
+                get_nearest_similarities(slice::from_ref(query), &images, k, &imgsim_options)
+                    .unwrap_or_else(|| get_query_similarities(query, &images, &imgsim_options))
+                    .print_top(k)
+            }
+            None => get_query_similarities(query, &images, &imgsim_options).print(),
+        }
+        process::exit(0);
+    }
+
+    // Threshold grouping only needs the pairings above the threshold, which a metric index can find without comparing every pairing
+    let indexed_groups = match (
+        imgsim_options.dendrogram(),
+        imgsim_options.grouping_threshold(),
+    ) {
+        (false, Some(threshold)) => group_images_indexed(
+            &images,
+            threshold,
+            imgsim_options.grouping_method(),
+            &imgsim_options,
+        ),
+        _ => None,
+    };
+    if let Some(groups) = indexed_groups {
+        print_groups(&groups);
+    } else {
+        print_similarities(&images, &imgsim_options);
+    }
+
+    if imgsim_options.containment() {
+        print_containments(&get_containments(&images, &imgsim_options));
+    }
     process::exit(0);
 }
+
+// Run the chosen `config` subcommand.
+fn config_command(arg_matches: &ArgMatches) -> Result<(), PersistenceError> {
+    let config_arg = arg_matches
+        .get_one::<PathBuf>("config")
+        .map(PathBuf::as_path);
+    match arg_matches.subcommand() {
+        Some(("init", init_matches)) => {
+            let config_path = ImgsimOptions::init_config_path(config_arg)
+                .ok_or(PersistenceError::WriteFileError(None))?;
+            if config_path.exists() && !init_matches.get_flag("force") {
+                return Err(PersistenceError::ConfigExistsError(config_path));
+            }
+            ImgsimOptions::write_default_config(&config_path)?;
+            println!(
+                "Wrote the default config to {}",
+                config_path.to_str().unwrap_or("config.toml")
+            );
+        }
+        Some(("show", _)) => {
+            let config_path = ImgsimOptions::find_config(config_arg)?;
+            let imgsim_options = ImgsimOptions::from_config_file(
+                config_path.as_deref(),
+                arg_matches.get_one::<String>("profile").map(String::as_str),
+            )?;
+            let problems = imgsim_options.problems();
+            if !problems.is_empty() {
+                return Err(PersistenceError::InvalidConfigError(problems));
+            }
+            match &config_path {
+                Some(config_path) => println!(
+                    "# Loaded from {}",
+                    config_path.to_str().unwrap_or("config.toml")
+                ),
+                None => println!("# Loaded from the default config"),
+            }
+            print!("{}", imgsim_options.to_toml());
+        }
+        Some(("validate", validate_matches)) => {
+            let config_path = validate_matches
+                .get_one::<PathBuf>("file")
+                .expect("file is required");
+            ImgsimOptions::validate_config_file(config_path)?;
+            println!(
+                "{} is valid.",
+                config_path.to_str().unwrap_or("config.toml")
+            );
+        }
+        _ => unreachable!("A config subcommand is required"),
+    }
+    Ok(())
+}
+
+// Load the config, then update it with any cli args given.
+fn build_options(arg_matches: &ArgMatches) -> Result<ImgsimOptions, PersistenceError> {
+    let config_path = ImgsimOptions::find_config(
+        arg_matches
+            .get_one::<PathBuf>("config")
+            .map(PathBuf::as_path),
+    )?;
+    let config_options = ImgsimOptions::from_config_file(
+        config_path.as_deref(),
+        arg_matches.get_one::<String>("profile").map(String::as_str),
+    )?;
+    if config_options.debug() {
+        match &config_path {
+            Some(config_path) => println!(
+                "imgsim_options parsed from {}:",
+                config_path.to_str().unwrap_or("config.toml")
+            ),
+            None => println!("imgsim_options parsed from the default config:"),
+        }
+        dbg!(&config_options);
+    }
+
+    // Match the value of a cli arg to an enum, failing if none match
+    fn get_cli_arg<T: IntoEnumIterator + MatchEnumAsStr>(
+        arg_matches: &ArgMatches,
+        id: &str,
+    ) -> Result<Option<T>, PersistenceError> {
+        arg_matches
+            .get_one::<String>(id)
+            .map(|val| T::from_enum_str(val))
+            .transpose()
+    }
+    let pixeldist_alg = get_cli_arg::<PixeldistAlg>(arg_matches, "pixeldist_alg")?;
+    let clustering_alg = get_cli_arg::<ClusteringAlg>(arg_matches, "clustering_alg")?;
+    let similarity_alg = get_cli_arg::<SimilarityAlg>(arg_matches, "similarity_alg")?;
+    let linkage = get_cli_arg::<Linkage>(arg_matches, "linkage")?;
+
+    // cli patterns are added to those from config.toml
+    let mut include_globs = config_options.include_globs().to_vec();
+    if let Some(include) = arg_matches.get_many::<String>("include") {
+        include_globs.extend(include.cloned());
+    }
+    let mut exclude_globs = config_options.exclude_globs().to_vec();
+    if let Some(exclude) = arg_matches.get_many::<String>("exclude") {
+        exclude_globs.extend(exclude.cloned());
+    }
+    let mut builder = ImgsimOptionsBuilder::from(config_options)
+        .include_globs(include_globs)
+        .exclude_globs(exclude_globs);
+
+    // A single directory given in the cli replaces input_dir from config.toml. Otherwise, each given path is an image file.
+    let files_from = arg_matches.get_one::<PathBuf>("files_from");
+    let paths: Vec<PathBuf> = arg_matches
+        .get_many::<PathBuf>("paths")
+        .map(|paths| paths.cloned().collect())
+        .unwrap_or_default();
+    match &paths[..] {
+        [path] if files_from.is_none() && !path.is_file() => {
+            builder = builder.input_dir(path.clone());
+        }
+        _ => builder = builder.files(paths),
+    }
+    if let Some(files_from) = files_from {
+        builder = builder.files_from(Some(files_from.clone()));
+    }
+    if let Some(output_dir) = arg_matches.get_one::<PathBuf>("output_dir") {
+        builder = builder.output_dir(Some(output_dir.clone()));
+    }
+    if let Some(against) = arg_matches.get_one::<PathBuf>("against") {
+        builder = builder.against(Some(against.clone()));
+    }
+    if let Some(query) = arg_matches.get_one::<PathBuf>("query") {
+        builder = builder.query(Some(query.clone()));
+    }
+
+    if let Some(arg) = pixeldist_alg {
+        builder = builder.pixeldist_alg(arg);
+    }
+    if let Some(arg) = clustering_alg {
+        builder = builder.clustering_alg(arg);
+    }
+    if let Some(arg) = similarity_alg {
+        builder = builder.similarity_alg(arg);
+    }
+
+    if arg_matches.get_flag("verbose") {
+        builder = builder.verbose(true);
+    }
+    if arg_matches.get_flag("force") {
+        builder = builder.force(true);
+    }
+
+    // A max depth turns on recursion
+    if arg_matches.get_flag("recursive") {
+        builder = builder.recursive(true);
+    }
+    if let Some(max_depth) = arg_matches.get_one::<usize>("max_depth") {
+        builder = builder.max_depth(Some(*max_depth)).recursive(true);
+    }
+    if arg_matches.get_flag("follow_symlinks") {
+        builder = builder.follow_symlinks(true);
+    }
+    if arg_matches.get_flag("hidden") {
+        builder = builder.hidden(true);
+    }
+
+    if arg_matches.get_flag("transform_invariant") {
+        builder = builder.transform_invariant(true);
+    }
+    if let Some(top) = arg_matches.get_one::<usize>("top") {
+        builder = builder.top(Some(*top));
+    }
+    if let Some(threshold) = arg_matches.get_one::<f32>("threshold") {
+        builder = builder.grouping_threshold(Some(*threshold));
+    }
+
+    // Any of the dendrogram options turns on the dendrogram
+    if let Some(arg) = linkage {
+        builder = builder.linkage(arg).dendrogram(true);
+    }
+    if let Some(cut_height) = arg_matches.get_one::<f32>("cut_height") {
+        builder = builder.cut_height(Some(*cut_height)).dendrogram(true);
+    }
+    if let Some(cut_count) = arg_matches.get_one::<usize>("cut_count") {
+        builder = builder.cut_count(Some(*cut_count)).dendrogram(true);
+    }
+    if arg_matches.get_flag("newick") {
+        builder = builder.newick(true).dendrogram(true);
+    }
+    if arg_matches.get_flag("dendrogram") {
+        builder = builder.dendrogram(true);
+    }
+
+    if arg_matches.get_flag("containment") {
+        builder = builder.containment(true);
+    }
+
+    let imgsim_options = builder.build()?;
+    if imgsim_options.debug() {
+        println!("imgsim_options updated by cli args:");
+        dbg!(&imgsim_options);
+        println!("=========Option Sources==========");
+        imgsim_options
+            .value_sources()
+            .iter()
+            .for_each(|(key_path, value, source)| match source {
+                ValueSource::Set => println!("{} = {} (cli)", key_path, value),
+                source => println!("{} = {} ({})", key_path, value, source),
+            });
+        println!("=================================");
+    }
+    if let Some(messages) = imgsim_options.discouraged_options() {
+        messages.iter().for_each(|message| {
+            eprintln!("Discouraged Settings Warning: {}", message);
+        });
+    }
+    Ok(imgsim_options)
+}
+
+fn print_selected_algorithms(imgsim_options: &ImgsimOptions) {
+    println!("=======Selected Algorithms=======");
+    if let Some(profile) = imgsim_options.profile() {
+        println!("Profile:          {}", profile);
+    }
+    println!(
+        "Pixel Distance:   {:?}\nPixel Clustering: {:?}\nImage Similarity: {:?}",
+        imgsim_options.pixeldist_alg(),
+        imgsim_options.clustering_alg(),
+        imgsim_options.similarity_alg()
+    );
+    if let SimilarityAlg::Ensemble = imgsim_options.similarity_alg() {
+        imgsim_options
+            .ensemble_members()
+            .iter()
+            .for_each(|member| println!("\t{:?} (weight {})", member.alg(), member.weight()));
+        println!("Ensemble Combine: {:?}", imgsim_options.ensemble_combine());
+    }
+    if imgsim_options.transform_invariant() {
+        println!("Transform Invariant: Flips & 90° Rotations");
+    }
+    println!("=================================");
+}
+
+// Print every algorithm which can be chosen, along with its description and the options which tune it.
+fn print_algorithms() {
+    fn print_algs<T: IntoEnumIterator + DescribeAlg + MatchEnumAsStr>(heading: &str) {
+        println!("{}:", heading);
+        T::iter().for_each(|alg| {
+            // Names are matched case-insensitively, so aliases which only differ in case aren't worth listing
+            let name = format!("{:?}", alg);
+            let aliases: Vec<&str> = alg
+                .aliases()
+                .iter()
+                .copied()
+                .filter(|alias| !alias.eq_ignore_ascii_case(&name))
+                .collect();
+            if aliases.is_empty() {
+                println!("\t{}: {}", name, alg.description());
+            } else {
+                println!(
+                    "\t{} (also {}): {}",
+                    name,
+                    aliases.join(", "),
+                    alg.description()
+                );
+            }
+            if !alg.tunable_options().is_empty() {
+                println!("\t\tOptions: {}", alg.tunable_options().join(", "));
+            }
+        });
+    }
+    print_algs::<PixeldistAlg>("Pixel distance algorithms (--pixeldist)");
+    print_algs::<ClusteringAlg>("Clustering algorithms (--clustering)");
+    print_algs::<SimilarityAlg>("Similarity algorithms (--similarity)");
+    print_algs::<Linkage>("Dendrogram linkages (--linkage)");
+}
+
+// Compare every pairing of images, then print them in the chosen form.
+fn print_similarities(images: &[ImgsimImage], imgsim_options: &ImgsimOptions) {
+    let image_similarity_matrix = get_similarities(images, imgsim_options);
+    if imgsim_options.dendrogram() {
+        let dendrogram = Dendrogram::new(&image_similarity_matrix, imgsim_options.linkage());
+        match (imgsim_options.cut_height(), imgsim_options.cut_count()) {
+            (Some(height), _) => print_groups(&cut_to_groups(
+                &image_similarity_matrix,
+                &dendrogram.cut_height(height),
+            )),
+            (None, Some(count)) => print_groups(&cut_to_groups(
+                &image_similarity_matrix,
+                &dendrogram.cut_count(count),
+            )),
+            (None, None) if imgsim_options.newick() => println!("\n{}", dendrogram.to_newick()),
+            (None, None) => print!("\n{}", dendrogram.to_text()),
+        }
+    } else {
+        match (imgsim_options.grouping_threshold(), imgsim_options.top()) {
+            (Some(threshold), _) => print_groups(&group_images(
+                &image_similarity_matrix,
+                threshold,
+                imgsim_options.grouping_method(),
+            )),
+            (None, Some(k)) => image_similarity_matrix.print_top(k),
+            (None, None) => image_similarity_matrix.print(),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 #![warn(missing_docs)]
 
 pub mod errors;
+pub mod glob;
 pub mod load_images;
 pub mod options;
+pub mod validation;
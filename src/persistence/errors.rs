@@ -2,6 +2,8 @@
 
 use std::{fmt, path::PathBuf};
 
+use crate::helpers::closest_matches;
+
 #[derive(Debug)]
 
 /// All the possible persistence-related errors.
@@ -18,15 +20,25 @@ pub enum PersistenceError {
     NotDirectoryError(Option<PathBuf>),
     /// Sent when an unknown option is given.
     UnknownOptionError(String),
+    /// Sent when a given value matches none of the valid choices, including the valid values it could have been.
+    UnknownChoiceError(String, Vec<String>),
     /// Sent when discouraged settings are chosen without the "force" flag enabled.
     DiscouragedSettingsError(String),
+    /// Sent when the ensemble described in config.toml cannot be used.
+    InvalidEnsembleError(String),
+    /// Sent when an include or exclude glob pattern is malformed, including the offending pattern.
+    InvalidGlobError(String),
+    /// Sent when options have unknown keys or out-of-range values, including a description of every such problem.
+    InvalidConfigError(Vec<String>),
+    /// Sent when a config would be written over an existing one without the "force" flag enabled, including its path.
+    ConfigExistsError(PathBuf),
 }
 
 fn path_buf_as_str(path_buf: &Option<PathBuf>) -> &str {
-    return match path_buf {
+    match path_buf {
         Some(buf) => buf.to_str().unwrap_or(""),
         _ => "",
-    };
+    }
 }
 
 impl fmt::Display for PersistenceError {
@@ -49,13 +61,16 @@ impl fmt::Display for PersistenceError {
             Self::DeserializeError(string) => {
                 write!(f, "DeserializeError: {}", string)
             }
-            Self::EmptyInputDirError(path_buf) => {
+            Self::EmptyInputDirError(Some(path_buf)) => {
                 write!(
                     f,
                     "EmptyInputDirError: No images in {} to compare.",
-                    path_buf_as_str(path_buf)
+                    path_buf.to_str().unwrap_or("")
                 )
             }
+            Self::EmptyInputDirError(None) => {
+                write!(f, "EmptyInputDirError: No images to compare.")
+            }
             Self::NotDirectoryError(path_buf) => {
                 write!(
                     f,
@@ -70,9 +85,50 @@ impl fmt::Display for PersistenceError {
                     string
                 )
             }
+            Self::UnknownChoiceError(string, choices) => {
+                write!(
+                    f,
+                    "UnknownChoiceError: Given option \"{}\" does not match any known values.",
+                    string
+                )?;
+                if let Some(closest) = closest_matches(string, choices).first() {
+                    write!(f, " Did you mean \"{}\"?", closest)?;
+                }
+                if !choices.is_empty() {
+                    write!(f, "\nValid values: {}", choices.join(", "))?;
+                }
+                Ok(())
+            }
             Self::DiscouragedSettingsError(string) => {
                 write!(f, "DiscouragedSettingsError: {}\nUse the '--force' option to run using these settings anyways.", string)
             }
+            Self::InvalidEnsembleError(string) => {
+                write!(f, "InvalidEnsembleError: {}", string)
+            }
+            Self::InvalidGlobError(string) => {
+                write!(
+                    f,
+                    "InvalidGlobError: Glob pattern \"{}\" is malformed.",
+                    string
+                )
+            }
+            Self::InvalidConfigError(problems) => {
+                write!(
+                    f,
+                    "InvalidConfigError: {} problem(s) with the options:",
+                    problems.len()
+                )?;
+                problems
+                    .iter()
+                    .try_for_each(|problem| write!(f, "\n\t{}", problem))
+            }
+            Self::ConfigExistsError(path_buf) => {
+                write!(
+                    f,
+                    "ConfigExistsError: A config already exists at {}. Use '--force' to overwrite it.",
+                    path_buf.to_str().unwrap_or("config.toml")
+                )
+            }
         }
     }
 }
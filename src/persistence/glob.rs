@@ -0,0 +1,130 @@
+#![warn(missing_docs)]
+//! Glob patterns used to include or exclude files when scanning for images.
+
+use regex::Regex;
+
+use crate::PersistenceError;
+
+/// A compiled glob pattern, matched against paths relative to the scanned directory with `/` separators.
+///
+/// * `*` matches anything except `/`
+/// * `**` matches any number of directories
+/// * `?` matches any single character except `/`
+/// * `[abc]`, `[a-z]` and `[!abc]` match one character from (or not from) a set
+///
+/// A pattern without a `/` is matched against the file name alone, so `*.png` matches PNG files in every directory.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+impl Glob {
+    /// Compiles the given glob pattern. Returns [PersistenceError::InvalidGlobError] if the pattern is malformed.
+    pub fn new(pattern: &str) -> Result<Glob, PersistenceError> {
+        let invalid = || PersistenceError::InvalidGlobError(String::from(pattern));
+        let mut body = String::new();
+        let mut chars = pattern.trim_start_matches('/').chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        body.push_str("(?:.*/)?");
+                    } else {
+                        body.push_str(".*");
+                    }
+                }
+                '*' => body.push_str("[^/]*"),
+                '?' => body.push_str("[^/]"),
+                '[' => {
+                    body.push('[');
+                    if chars.peek() == Some(&'!') {
+                        chars.next();
+                        body.push('^');
+                    }
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some('\\') => body.push_str("\\\\"),
+                            Some(c) => body.push(c),
+                            None => return Err(invalid()),
+                        }
+                    }
+                    body.push(']');
+                }
+                '\\' => match chars.next() {
+                    Some(c) => body.push_str(&regex::escape(&c.to_string())),
+                    None => return Err(invalid()),
+                },
+                c => body.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        let regex_str = if pattern.contains('/') {
+            format!("^{}$", body)
+        } else {
+            format!("(?:^|/){}$", body)
+        };
+        match Regex::new(&regex_str) {
+            Ok(regex) => Ok(Glob {
+                pattern: String::from(pattern),
+                regex,
+            }),
+            Err(_) => Err(invalid()),
+        }
+    }
+
+    /// Returns the original glob pattern.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Return true if the given relative path, with `/` separators, matches the pattern.
+    pub fn is_match(&self, relative_path: &str) -> bool {
+        self.regex.is_match(relative_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_only_pattern() {
+        let glob = Glob::new("*.png").unwrap();
+        assert!(glob.is_match("a.png"));
+        assert!(glob.is_match("sub/dir/a.png"));
+        assert!(!glob.is_match("a.png.bak"));
+        assert!(!glob.is_match("a.jpg"));
+    }
+
+    #[test]
+    fn path_pattern() {
+        let glob = Glob::new("raw/*.jpg").unwrap();
+        assert!(glob.is_match("raw/a.jpg"));
+        assert!(!glob.is_match("raw/sub/a.jpg"));
+        assert!(!glob.is_match("other/raw/a.jpg"));
+    }
+
+    #[test]
+    fn double_star() {
+        let glob = Glob::new("**/thumbs/**").unwrap();
+        assert!(glob.is_match("thumbs/a.png"));
+        assert!(glob.is_match("x/y/thumbs/z/a.png"));
+        assert!(!glob.is_match("thumbsup/a.png"));
+    }
+
+    #[test]
+    fn classes_and_question_mark() {
+        let glob = Glob::new("img?[0-9][!a].PNG").unwrap();
+        assert!(glob.is_match("img_12.PNG"));
+        assert!(!glob.is_match("img_1a.PNG"));
+        assert!(!glob.is_match("img/12.PNG"));
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(Glob::new("[abc").is_err());
+        assert!(Glob::new("abc\\").is_err());
+    }
+}
@@ -1,29 +1,262 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 
 use crate::PersistenceError;
-use crate::{ImgsimImage, ImgsimOptions};
+use crate::{display_names, Glob, ImageId, ImgsimImage, ImgsimOptions};
 
-/// Loads vector of images from given directory
+/// Loads vector of images from given directory, or from the given list of image files if there is one.
+///
+/// If scanning recursively, images are named by their path relative to the directory, so that images with the same
+/// file name in different subdirectories can be told apart. If a query image is given, it is left out of the
+/// directory's images so that it is not compared to itself.
 pub fn load_images(imgsim_options: &ImgsimOptions) -> Result<Vec<ImgsimImage>, PersistenceError> {
-    let images = fs::read_dir(imgsim_options.input_dir())
-        .unwrap()
-        .filter_map(|entry| {
-            entry.ok().and_then(|ok_entry| {
-                let path = ok_entry.path();
-                if path.is_file() {
-                    ImgsimImage::new(path, imgsim_options)
-                } else {
-                    None
-                }
-            })
+    if imgsim_options.uses_file_list() {
+        load_files(imgsim_options)
+    } else {
+        load_dir(imgsim_options.input_dir(), imgsim_options)
+    }
+}
+
+/// Loads the images of the directory the input directory is compared against, if one was given.
+pub fn load_against(
+    imgsim_options: &ImgsimOptions,
+) -> Result<Option<Vec<ImgsimImage>>, PersistenceError> {
+    match imgsim_options.against() {
+        Some(against) => load_dir(against, imgsim_options).map(Some),
+        None => Ok(None),
+    }
+}
+
+// Load every image file found within the given directory, leaving out the query image.
+fn load_dir(
+    dir: &Path,
+    imgsim_options: &ImgsimOptions,
+) -> Result<Vec<ImgsimImage>, PersistenceError> {
+    let mut scanner = Scanner {
+        include: compile_globs(imgsim_options.include_globs())?,
+        exclude: compile_globs(imgsim_options.exclude_globs())?,
+        imgsim_options,
+        visited: HashSet::new(),
+        files: Vec::new(),
+    };
+    if let Ok(canonical_dir) = dir.canonicalize() {
+        scanner.visited.insert(canonical_dir);
+    }
+    if scanner.scan(dir, "", 0).is_err() {
+        return Err(PersistenceError::ReadFileError(Some(PathBuf::from(dir))));
+    }
+
+    let query = imgsim_options
+        .query()
+        .as_ref()
+        .and_then(|query| query.canonicalize().ok());
+    let images = scanner
+        .files
+        .into_iter()
+        .filter(|(path, _)| query.is_none() || path.canonicalize().ok() != query)
+        .filter_map(|(path, relative_path)| {
+            ImgsimImage::new(path, imgsim_options).map(|image| image.with_name(relative_path))
         })
         .collect::<Vec<ImgsimImage>>();
+    let images = identify_images(images);
     if images.is_empty() {
         Err(PersistenceError::EmptyInputDirError(Some(PathBuf::from(
-            imgsim_options.input_dir(),
+            dir,
         ))))
     } else {
         Ok(images)
     }
 }
+
+// Load each image file given in the cli or listed in the files_from file, leaving out the query image.
+fn load_files(imgsim_options: &ImgsimOptions) -> Result<Vec<ImgsimImage>, PersistenceError> {
+    let mut paths: Vec<PathBuf> = imgsim_options.files().to_vec();
+    if let Some(files_from) = imgsim_options.files_from() {
+        let read_error = || PersistenceError::ReadFileError(Some(PathBuf::from(files_from)));
+        let mut bytes = Vec::new();
+        if files_from.as_os_str() == "-" {
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|_| read_error())?;
+        } else {
+            bytes = fs::read(files_from).map_err(|_| read_error())?;
+        }
+        paths.extend(parse_file_list(&bytes));
+    }
+
+    let query = imgsim_options
+        .query()
+        .as_ref()
+        .and_then(|query| query.canonicalize().ok());
+    let images = paths
+        .into_iter()
+        .filter(|path| {
+            if path.is_file() {
+                true
+            } else {
+                eprintln!(
+                    "Warning: Could not find image file {}",
+                    path.to_str().unwrap_or("unknown path")
+                );
+                false
+            }
+        })
+        .filter(|path| query.is_none() || path.canonicalize().ok() != query)
+        .filter_map(|path| ImgsimImage::new(path, imgsim_options))
+        .collect::<Vec<ImgsimImage>>();
+    let images = identify_images(images);
+    if images.is_empty() {
+        Err(PersistenceError::EmptyInputDirError(
+            imgsim_options.files_from().clone(),
+        ))
+    } else {
+        Ok(images)
+    }
+}
+
+// Split a list of paths separated by NUL characters, as printed by `find -print0`, or else by newlines. Empty entries are skipped.
+fn parse_file_list(bytes: &[u8]) -> Vec<PathBuf> {
+    let separator = if bytes.contains(&b'\0') { b'\0' } else { b'\n' };
+    bytes
+        .split(|byte| *byte == separator)
+        .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(OsStr::from_bytes(entry)))
+        .collect()
+}
+
+/// Give each image an [ImageId] indexed by its place in the list, and a display name which no other image shares.
+///
+/// See [display_names].
+pub fn identify_images(images: Vec<ImgsimImage>) -> Vec<ImgsimImage> {
+    let images: Vec<ImgsimImage> = images
+        .into_iter()
+        .enumerate()
+        .map(|(index, image)| image.with_index(index))
+        .collect();
+    let ids: Vec<ImageId> = images.iter().map(|image| image.id().clone()).collect();
+    let names: Vec<String> = images
+        .iter()
+        .map(|image| String::from(image.name()))
+        .collect();
+    images
+        .into_iter()
+        .zip(display_names(&ids, &names))
+        .map(|(image, name)| image.with_name(name))
+        .collect()
+}
+
+fn compile_globs(patterns: &[String]) -> Result<Vec<Glob>, PersistenceError> {
+    patterns.iter().map(|pattern| Glob::new(pattern)).collect()
+}
+
+// Walks a directory tree, collecting the files to load along with their paths relative to the top directory.
+struct Scanner<'a> {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    imgsim_options: &'a ImgsimOptions,
+    // Canonical paths of the directories already scanned, so that symbolic links cannot cause a loop
+    visited: HashSet<PathBuf>,
+    files: Vec<(PathBuf, String)>,
+}
+impl Scanner<'_> {
+    fn scan(&mut self, dir: &Path, relative_dir: &str, depth: usize) -> std::io::Result<()> {
+        let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)?.filter_map(Result::ok).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let Some(file_name) = entry.file_name().to_str().map(String::from) else {
+                eprintln!(
+                    "Warning: Could not parse file name at {}",
+                    entry.path().to_str().unwrap_or("unknown directory")
+                );
+                continue;
+            };
+            if !self.imgsim_options.hidden() && file_name.starts_with('.') {
+                continue;
+            }
+            let relative_path = if relative_dir.is_empty() {
+                file_name
+            } else {
+                format!("{}/{}", relative_dir, file_name)
+            };
+            if self
+                .exclude
+                .iter()
+                .any(|glob| glob.is_match(&relative_path))
+            {
+                continue;
+            }
+
+            let path = entry.path();
+            if path.is_dir() {
+                let is_symlink = entry
+                    .file_type()
+                    .is_ok_and(|file_type| file_type.is_symlink());
+                if self
+                    .imgsim_options
+                    .scan_depth()
+                    .is_some_and(|max_depth| depth >= max_depth)
+                    || (is_symlink && !self.imgsim_options.follow_symlinks())
+                {
+                    continue;
+                }
+                if let Ok(canonical_path) = path.canonicalize() {
+                    if !self.visited.insert(canonical_path) {
+                        continue;
+                    }
+                }
+                if self.scan(&path, &relative_path, depth + 1).is_err() {
+                    eprintln!(
+                        "Warning: Could not read directory {}",
+                        path.to_str().unwrap_or("unknown directory")
+                    );
+                }
+            } else if path.is_file()
+                && (self.include.is_empty()
+                    || self
+                        .include
+                        .iter()
+                        .any(|glob| glob.is_match(&relative_path)))
+            {
+                self.files.push((path, relative_path));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Loads the query image, if one was given. Returns [PersistenceError::ReadFileError] if it cannot be read as an image.
+pub fn load_query(imgsim_options: &ImgsimOptions) -> Result<Option<ImgsimImage>, PersistenceError> {
+    match imgsim_options.query() {
+        Some(query) => ImgsimImage::new(PathBuf::from(query), imgsim_options)
+            .map(Some)
+            .ok_or(PersistenceError::ReadFileError(Some(PathBuf::from(query)))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn newline_file_list() {
+        assert_eq!(
+            parse_file_list(b"a.png\r\n\nsub dir/b.jpg\n"),
+            vec![PathBuf::from("a.png"), PathBuf::from("sub dir/b.jpg")]
+        );
+    }
+
+    #[test]
+    fn nul_file_list() {
+        assert_eq!(
+            parse_file_list(b"a.png\0new\nline.jpg\0\0"),
+            vec![PathBuf::from("a.png"), PathBuf::from("new\nline.jpg")]
+        );
+    }
+}
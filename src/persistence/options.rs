@@ -1,36 +1,212 @@
 #![warn(missing_docs)]
 
-use clap::ArgMatches;
-use serde::Deserialize;
-use std::{env, fs, path::Path, path::PathBuf, result::Result};
-use strum::IntoEnumIterator;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fmt, fs,
+    path::Path,
+    path::PathBuf,
+    result::Result,
+};
 
-use crate::{ClusteringAlg, MatchEnumAsStr, PersistenceError, PixeldistAlg, SimilarityAlg};
+use crate::{
+    persistence::validation, ClusteringAlg, EnsembleCombine, EnsembleMember, Glob, GroupingMethod,
+    Linkage, LogisticWeights, PersistenceError, PixeldistAlg, SimilarityAlg,
+};
 
-const CONFIG_PATH_STR: &str = ".config/imgsim/config.toml";
+const CONFIG_PATH_STR: &str = "imgsim/config.toml";
+const CONFIG_ENV_VAR: &str = "IMGSIM_CONFIG";
+// Environment variables named IMGSIM_<SECTION>__<KEY> override the config value `key` in `[section]`
+const ENV_VAR_PREFIX: &str = "IMGSIM_";
+const ENV_VAR_SEPARATOR: &str = "__";
+// The shipped config, used for any values missing from the user's config, or in place of it if there is none.
+const DEFAULT_CONFIG_TOML_STR: &str = include_str!("../../config/config.toml");
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct ClustersizeOptions {
     clustersize_cluster_cutoff: f32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct ColoursimOptions {
     coloursim_cluster_cutoff: f32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct ClustershapeOptions {
+    clustershape_cluster_cutoff: f32,
+}
+impl Default for ClustershapeOptions {
+    fn default() -> ClustershapeOptions {
+        ClustershapeOptions {
+            clustershape_cluster_cutoff: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct RegiongraphOptions {
+    regiongraph_cluster_cutoff: f32,
+    iterations: usize,
+}
+impl Default for RegiongraphOptions {
+    fn default() -> RegiongraphOptions {
+        RegiongraphOptions {
+            regiongraph_cluster_cutoff: 0.02,
+            iterations: 3,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 struct KmeansOptions {
     max_k: usize,
     silhouette_threshold: f32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct AgglomerativeOptions {
     tolerance: f32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct TextureOptions {
+    grid_size: u32,
+}
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions { grid_size: 4 }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct EdgeOrientationOptions {
+    grid_size: u32,
+    orientation_bins: usize,
+}
+impl Default for EdgeOrientationOptions {
+    fn default() -> EdgeOrientationOptions {
+        EdgeOrientationOptions {
+            grid_size: 4,
+            orientation_bins: 9,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct KeypointOptions {
+    fast_threshold: u8,
+    max_keypoints: usize,
+    pyramid_levels: usize,
+    match_ratio: f32,
+    ransac: bool,
+    ransac_iterations: usize,
+    ransac_threshold: f32,
+}
+impl Default for KeypointOptions {
+    fn default() -> KeypointOptions {
+        KeypointOptions {
+            fast_threshold: 20,
+            max_keypoints: 500,
+            pyramid_levels: 3,
+            match_ratio: 0.8,
+            ransac: true,
+            ransac_iterations: 500,
+            ransac_threshold: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct ContainmentOptions {
+    min_score: f32,
+}
+impl Default for ContainmentOptions {
+    fn default() -> ContainmentOptions {
+        ContainmentOptions { min_score: 0.5 }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct GroupingOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    threshold: Option<f32>,
+    method: GroupingMethod,
+}
+impl Default for GroupingOptions {
+    fn default() -> GroupingOptions {
+        GroupingOptions {
+            threshold: None,
+            method: GroupingMethod::ConnectedComponents,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct DendrogramOptions {
+    linkage: Linkage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cut_height: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cut_count: Option<usize>,
+    newick: bool,
+}
+impl Default for DendrogramOptions {
+    fn default() -> DendrogramOptions {
+        DendrogramOptions {
+            linkage: Linkage::Average,
+            cut_height: None,
+            cut_count: None,
+            newick: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct ScanOptions {
+    recursive: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    hidden: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct EnsembleOptions {
+    combine: EnsembleCombine,
+    members: Vec<EnsembleMember>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logistic_weights_path: Option<PathBuf>,
+    #[serde(skip)]
+    logistic_weights: Option<LogisticWeights>,
+}
+impl Default for EnsembleOptions {
+    fn default() -> EnsembleOptions {
+        EnsembleOptions {
+            combine: EnsembleCombine::WeightedMean,
+            members: vec![
+                EnsembleMember::new(SimilarityAlg::ColourSim, 1.0),
+                EnsembleMember::new(SimilarityAlg::ClusterSize, 1.0),
+            ],
+            logistic_weights_path: None,
+            logistic_weights: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 struct Settings {
     debug: bool,
     #[serde(default)]
@@ -41,9 +217,17 @@ struct Settings {
     skip_pixelsim: bool,
     #[serde(default)]
     force: bool,
+    #[serde(default)]
+    transform_invariant: bool,
+    #[serde(default)]
+    containment: bool,
+    #[serde(default)]
+    top: Option<usize>,
+    #[serde(default)]
+    dendrogram: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct Args {
     input_dir: PathBuf,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -51,10 +235,18 @@ struct Args {
     pixeldist_alg: PixeldistAlg,
     clustering_alg: ClusteringAlg,
     similarity_alg: SimilarityAlg,
+    #[serde(skip)]
+    files: Vec<PathBuf>,
+    #[serde(skip)]
+    files_from: Option<PathBuf>,
+    #[serde(skip)]
+    query: Option<PathBuf>,
+    #[serde(skip)]
+    against: Option<PathBuf>,
 }
 
 /// A variety of options and parameters that determine how imgsim acts. Values are accessed through the methods.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct ImgsimOptions {
     args: Args,
     settings: Settings,
@@ -62,171 +254,640 @@ pub struct ImgsimOptions {
     kmeans_options: KmeansOptions,
     coloursim_options: ColoursimOptions,
     clustersize_options: ClustersizeOptions,
+    #[serde(default)]
+    clustershape_options: ClustershapeOptions,
+    #[serde(default)]
+    regiongraph_options: RegiongraphOptions,
+    #[serde(default)]
+    texture_options: TextureOptions,
+    #[serde(default)]
+    edge_orientation_options: EdgeOrientationOptions,
+    #[serde(default)]
+    keypoint_options: KeypointOptions,
+    #[serde(default)]
+    containment_options: ContainmentOptions,
+    #[serde(default)]
+    grouping: GroupingOptions,
+    #[serde(default)]
+    dendrogram: DendrogramOptions,
+    #[serde(default)]
+    scan: ScanOptions,
+    #[serde(default)]
+    ensemble: EnsembleOptions,
+    // Named sets of config values, any one of which can be merged over the rest of the config
+    #[serde(default, skip_serializing)]
+    profiles: BTreeMap<String, toml::Table>,
+    #[serde(skip)]
+    profile: Option<String>,
+    // The config file the options were loaded from, and the line each of its keys is set on
+    #[serde(skip)]
+    config_file: Option<PathBuf>,
+    #[serde(skip)]
+    key_lines: BTreeMap<String, usize>,
+    // Keys given which are not options, by their dotted key path
+    #[serde(skip)]
+    unknown_keys: Vec<String>,
+    // Where each config value came from, by its dotted key path
+    #[serde(skip)]
+    value_sources: BTreeMap<String, ValueSource>,
+    // Each config value as loaded, before any were set through the builder
+    #[serde(skip)]
+    loaded_values: BTreeMap<String, toml::Value>,
 }
-impl ImgsimOptions {
-    /// Create a new ImgsimOptions. Return [PersistenceError] on failure to read file or deserialise.
-    ///
-    /// Argument `config_path_str` must point to a valid `.toml` file.
-    pub fn build(arg_matches: ArgMatches) -> Result<ImgsimOptions, PersistenceError> {
-        // Load config
-        let mut config_path = home::home_dir().unwrap();
-        config_path.push(CONFIG_PATH_STR);
-        let config_toml_str = if let Ok(string) = fs::read_to_string(&config_path) {
-            string
+
+/// Where the value of an option came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueSource {
+    /// The default config.
+    Default,
+    /// The config file at the given path.
+    ConfigFile(PathBuf),
+    /// The given environment variable.
+    Env(String),
+    /// The profile with the given name, from the `[profiles]` section of the config.
+    Profile(String),
+    /// Set through [ImgsimOptionsBuilder], such as by cli args.
+    Set,
+}
+impl fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::ConfigFile(path) => write!(f, "{}", path.to_str().unwrap_or("config file")),
+            Self::Env(var) => write!(f, "env {}", var),
+            Self::Profile(profile) => write!(f, "profile {}", profile),
+            Self::Set => write!(f, "set"),
+        }
+    }
+}
+// Config values, along with where they came from.
+type ConfigLayer = (ValueSource, toml::Table);
+
+// The config file given, or else the one named by IMGSIM_CONFIG.
+fn given_config(config_path: Option<&Path>) -> Option<PathBuf> {
+    config_path.map(PathBuf::from).or_else(|| {
+        env::var_os(CONFIG_ENV_VAR)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+// imgsim/config.toml in $XDG_CONFIG_HOME or ~/.config.
+fn config_dir_config() -> Option<PathBuf> {
+    // Relative XDG_CONFIG_HOME paths are invalid, and are ignored
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| home::home_dir().map(|home_dir| home_dir.join(".config")));
+    config_dir.map(|config_dir| config_dir.join(CONFIG_PATH_STR))
+}
+
+// Find the config file to load: the given one, then the one named by IMGSIM_CONFIG, then imgsim/config.toml in
+// $XDG_CONFIG_HOME or ~/.config.
+fn find_config(config_path: Option<&Path>) -> Result<Option<PathBuf>, PersistenceError> {
+    if let Some(config_path) = given_config(config_path) {
+        return if config_path.is_file() {
+            Ok(Some(config_path))
         } else {
-            return Err(PersistenceError::ReadFileError(Some(config_path)));
+            Err(PersistenceError::ReadFileError(Some(config_path)))
         };
+    }
+    Ok(config_dir_config().filter(|config_path| config_path.is_file()))
+}
 
-        let mut imgsim_options: ImgsimOptions = match toml::from_str(&config_toml_str) {
-            Ok(toml) => toml,
-            Err(toml_error) => {
-                return Err(PersistenceError::DeserializeError(String::from(
-                    toml_error.message(),
-                )));
+// Read the given config file into a layer, along with the line each of its keys is set on.
+fn read_config(
+    config_path: &Path,
+) -> Result<(ConfigLayer, BTreeMap<String, usize>), PersistenceError> {
+    match fs::read_to_string(config_path) {
+        Ok(config_toml_str) => Ok((
+            (
+                ValueSource::ConfigFile(PathBuf::from(config_path)),
+                parse_toml(&config_toml_str)?,
+            ),
+            validation::key_lines(&config_toml_str),
+        )),
+        Err(_) => Err(PersistenceError::ReadFileError(Some(PathBuf::from(
+            config_path,
+        )))),
+    }
+}
+
+fn parse_toml(config_toml_str: &str) -> Result<toml::Table, PersistenceError> {
+    toml::from_str(config_toml_str).map_err(|toml_error| {
+        PersistenceError::DeserializeError(String::from(toml_error.message()))
+    })
+}
+
+// Turn each IMGSIM_<SECTION>__<KEY> environment variable into a config layer, sorted by name. A value which doesn't
+// parse as a TOML value, such as an algorithm name without quotes, is taken as a string.
+fn env_layers(vars: impl Iterator<Item = (String, String)>) -> Vec<ConfigLayer> {
+    let mut layers: Vec<ConfigLayer> = vars
+        .filter_map(|(var, raw_value)| {
+            let keys: Vec<String> = var
+                .strip_prefix(ENV_VAR_PREFIX)?
+                .split(ENV_VAR_SEPARATOR)
+                .map(str::to_lowercase)
+                .collect();
+            if keys.len() < 2 || keys.iter().any(String::is_empty) {
+                return None;
+            }
+            let value = toml::from_str::<toml::Table>(&format!("value = {}", raw_value))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or(toml::Value::String(raw_value));
+            let table = keys.iter().rev().fold(value, |value, key| {
+                toml::Value::Table(toml::Table::from_iter([(key.clone(), value)]))
+            });
+            match table {
+                toml::Value::Table(table) => Some((ValueSource::Env(var), table)),
+                _ => None,
+            }
+        })
+        .collect();
+    layers.sort_by_key(|(source, _)| source.to_string());
+    layers
+}
+
+// Parse the default config with each of the given layers merged over it in turn, noting where each value came from.
+//
+// The chosen profile is merged over the config files, but under the environment variables, so that they can still
+// override its values.
+//
+// Values which cannot be deserialised are reported along with their key path and where they came from, using the line
+// each key of the config file is set on.
+fn parse_config(
+    layers: Vec<ConfigLayer>,
+    key_lines: BTreeMap<String, usize>,
+    profile: Option<&str>,
+) -> Result<ImgsimOptions, PersistenceError> {
+    let mut config = parse_toml(DEFAULT_CONFIG_TOML_STR)?;
+    let mut value_sources = BTreeMap::new();
+    let config_file = layers.iter().find_map(|(source, _)| match source {
+        ValueSource::ConfigFile(config_path) => Some(config_path.clone()),
+        _ => None,
+    });
+    for (source, table) in &layers {
+        merge_tables(&mut config, table.clone(), source, "", &mut value_sources);
+    }
+    if let Some(profile) = profile {
+        let profile_table = match config
+            .get("profiles")
+            .and_then(|profiles| profiles.get(profile))
+        {
+            Some(toml::Value::Table(profile_table)) => profile_table.clone(),
+            _ => {
+                return Err(PersistenceError::UnknownChoiceError(
+                    String::from(profile),
+                    match config.get("profiles") {
+                        Some(toml::Value::Table(profiles)) => profiles.keys().cloned().collect(),
+                        _ => Vec::new(),
+                    },
+                ))
             }
         };
+        merge_tables(
+            &mut config,
+            profile_table,
+            &ValueSource::Profile(String::from(profile)),
+            "",
+            &mut value_sources,
+        );
+        for (source, table) in layers {
+            if let ValueSource::Env(_) = source {
+                merge_tables(&mut config, table, &source, "", &mut value_sources);
+            }
+        }
+    }
+    let mut key_paths: Vec<String> = value_sources
+        .iter()
+        .filter(|(_, source)| !matches!(source, ValueSource::Profile(_)))
+        .map(|(key_path, _)| key_path.clone())
+        .collect();
+    // Keys within inline tables, such as the ensemble's members, aren't flattened, so list them separately
+    let array_table_keys: Vec<String> = validation::array_table_keys(&config, "")
+        .into_iter()
+        .filter(|key_path| {
+            value_sources
+                .get(validation::base_key_path(key_path))
+                .is_some_and(|source| !matches!(source, ValueSource::Profile(_)))
+        })
+        .collect();
+    key_paths.extend(array_table_keys);
 
-        if imgsim_options.debug() {
-            println!("imgsim_options parsed from config.toml:");
-            dbg!(&imgsim_options);
+    let mut imgsim_options: ImgsimOptions = match toml::Value::Table(config.clone()).try_into() {
+        Ok(imgsim_options) => imgsim_options,
+        Err(toml_error) => {
+            // Values carry no position, so deserialise the config's text instead to find which key is invalid
+            let invalid_key = toml::to_string(&config).ok().and_then(|config_toml_str| {
+                let span = toml::from_str::<ImgsimOptions>(&config_toml_str)
+                    .err()?
+                    .span()?;
+                let line = config_toml_str[..span.start].lines().count().max(1);
+                validation::key_at_line(&validation::key_lines(&config_toml_str), line)
+                    .map(String::from)
+            });
+            let mut imgsim_options = ImgsimOptions {
+                key_lines,
+                config_file,
+                value_sources,
+                ..ImgsimOptions::default()
+            };
+            return Err(match invalid_key {
+                Some(key_path) => PersistenceError::InvalidConfigError(vec![
+                    imgsim_options.problem(&key_path, toml_error.message())
+                ]),
+                None => {
+                    imgsim_options.value_sources.clear();
+                    PersistenceError::DeserializeError(String::from(toml_error.message()))
+                }
+            });
         }
+    };
+    imgsim_options.loaded_values = imgsim_options.values();
+    imgsim_options.unknown_keys =
+        validation::unknown_keys(key_paths.iter(), &ImgsimOptions::known_key_paths());
+    imgsim_options.value_sources = value_sources;
+    imgsim_options.key_lines = key_lines;
+    imgsim_options.config_file = config_file;
+    imgsim_options.profile = profile.map(String::from);
+    Ok(imgsim_options)
+}
 
-        // Get working directory to use if no input_dir arg
-        let working_directory: PathBuf = match env::current_dir() {
-            Ok(dir) => dir,
-            Err(_) => {
-                return Err(PersistenceError::ReadFileError(None));
-            }
+// Merge the overriding table into the base table. Tables are merged key by key, and any other value is replaced whole.
+fn merge_tables(
+    base: &mut toml::Table,
+    overrides: toml::Table,
+    source: &ValueSource,
+    key_path: &str,
+    value_sources: &mut BTreeMap<String, ValueSource>,
+) {
+    for (key, value) in overrides {
+        let value_path = if key_path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", key_path, key)
         };
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_tables(base_table, table, source, &value_path, value_sources)
+            }
+            (_, value) => {
+                flatten_value(&value, &value_path, &mut |path, _| {
+                    value_sources.insert(path, source.clone());
+                });
+                base.insert(key, value);
+            }
+        }
+    }
+}
 
-        // Default to working dir
-        if imgsim_options.args.input_dir.to_str().unwrap().is_empty() {
-            imgsim_options.args.input_dir = working_directory
-        }
-
-        // If cli arg given and exists, replace input_dir from config.toml
-        // Return ReadFileError if input directory doesn't exist or isn't a directory
-        fn verify_cli_arg_path(
-            cli_arg: Option<&PathBuf>,
-        ) -> Result<Option<PathBuf>, PersistenceError> {
-            if let Some(cli_arg_unwrapped) = cli_arg {
-                if !cli_arg_unwrapped.exists() {
-                    return Err(PersistenceError::ReadFileError(Some(PathBuf::from(
-                        cli_arg_unwrapped,
-                    ))));
-                }
+// Options are stored as f32, so print floats to f32 precision, e.g. 0.4 rather than 0.4000000059604645.
+fn as_f32_precision(value: &toml::Value) -> toml::Value {
+    match value {
+        toml::Value::Float(float) => {
+            toml::Value::Float((*float as f32).to_string().parse().unwrap_or(*float))
+        }
+        toml::Value::Array(array) => {
+            toml::Value::Array(array.iter().map(as_f32_precision).collect())
+        }
+        toml::Value::Table(table) => toml::Value::Table(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), as_f32_precision(value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
 
-                if !cli_arg_unwrapped.is_dir() {
-                    return Err(PersistenceError::NotDirectoryError(Some(PathBuf::from(
-                        cli_arg_unwrapped,
-                    ))));
-                }
-                return Ok(Some(PathBuf::from(cli_arg_unwrapped)));
+// Call `visit` with the dotted key path of each value within the given value which isn't a table.
+fn flatten_value(
+    value: &toml::Value,
+    key_path: &str,
+    visit: &mut impl FnMut(String, &toml::Value),
+) {
+    match value {
+        toml::Value::Table(table) => table
+            .iter()
+            .for_each(|(key, value)| flatten_value(value, &format!("{}.{}", key_path, key), visit)),
+        value => visit(String::from(key_path), value),
+    }
+}
+
+impl Default for ImgsimOptions {
+    /// Returns the options in the default config.
+    fn default() -> ImgsimOptions {
+        parse_config(Vec::new(), BTreeMap::new(), None).expect("The default config is valid")
+    }
+}
+
+impl ImgsimOptions {
+    /// Return the path of the config file to load. Return [PersistenceError::ReadFileError] if the config given, or named
+    /// by the `IMGSIM_CONFIG` environment variable, does not exist.
+    ///
+    /// If neither is given, `imgsim/config.toml` within `$XDG_CONFIG_HOME` or `~/.config` is used. Returns
+    /// [Option::None] if there is no config file there either.
+    pub fn find_config(config_path: Option<&Path>) -> Result<Option<PathBuf>, PersistenceError> {
+        find_config(config_path)
+    }
+
+    /// Create a new ImgsimOptions out of the given config file. Return [PersistenceError] on failure to read file or deserialise.
+    ///
+    /// Any values the config leaves out are taken from the default config, which is used alone if no config is given.
+    /// If a profile is given, the values in its `[profiles.<name>]` section are used over the rest of the config.
+    /// Return [PersistenceError::UnknownChoiceError] if there is no such profile. Environment variables named
+    /// `IMGSIM_<SECTION>__<KEY>` override the value of `key` in `[section]`, e.g.
+    /// `IMGSIM_AGGLOMERATIVE_OPTIONS__TOLERANCE=0.4`. The options still need to be checked with
+    /// [ImgsimOptionsBuilder::build] before use.
+    pub fn from_config_file(
+        config_path: Option<&Path>,
+        profile: Option<&str>,
+    ) -> Result<ImgsimOptions, PersistenceError> {
+        let mut layers = Vec::new();
+        let mut key_lines = BTreeMap::new();
+        if let Some(config_path) = config_path {
+            let (layer, file_key_lines) = read_config(config_path)?;
+            layers.push(layer);
+            key_lines = file_key_lines;
+        }
+        layers.extend(env_layers(env::vars()));
+        parse_config(layers, key_lines, profile)
+    }
+
+    /// Return the path `imgsim config init` writes the default config to: the config given, or named by the
+    /// `IMGSIM_CONFIG` environment variable, else `imgsim/config.toml` within `$XDG_CONFIG_HOME` or `~/.config`.
+    ///
+    /// Unlike [ImgsimOptions::find_config], the file does not need to exist. Returns [Option::None] if there is no
+    /// home directory to put it in.
+    pub fn init_config_path(config_path: Option<&Path>) -> Option<PathBuf> {
+        given_config(config_path).or_else(config_dir_config)
+    }
+
+    /// Write the default config to the given path, creating any missing parent directories. Return
+    /// [PersistenceError::WriteFileError] on failure.
+    pub fn write_default_config(config_path: &Path) -> Result<(), PersistenceError> {
+        let write_error = || PersistenceError::WriteFileError(Some(PathBuf::from(config_path)));
+        if let Some(parent) = config_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).map_err(|_| write_error())?;
+        }
+        fs::write(config_path, DEFAULT_CONFIG_TOML_STR).map_err(|_| write_error())
+    }
+
+    /// Check the given config file alone, without any environment variables, along with each of its profiles.
+    ///
+    /// Return [PersistenceError::InvalidConfigError], listing every problem, if it has any unknown keys or out-of-range
+    /// values.
+    pub fn validate_config_file(config_path: &Path) -> Result<(), PersistenceError> {
+        let (layer, key_lines) = read_config(config_path)?;
+        // Listed from the file itself, so that the profiles are still checked if the rest of the config is invalid
+        let mut profiles: BTreeSet<String> = ImgsimOptions::default().profiles().cloned().collect();
+        if let Some(toml::Value::Table(file_profiles)) = layer.1.get("profiles") {
+            profiles.extend(file_profiles.keys().cloned());
+        }
+        let mut problems: Vec<String> = Vec::new();
+        for profile in [None].into_iter().chain(profiles.iter().map(Some)) {
+            // Values which cannot be deserialised are problems too, so keep going to list the rest
+            let profile_problems = match parse_config(
+                vec![layer.clone()],
+                key_lines.clone(),
+                profile.map(String::as_str),
+            ) {
+                Ok(imgsim_options) => imgsim_options.problems(),
+                Err(PersistenceError::InvalidConfigError(profile_problems)) => profile_problems,
+                Err(persistence_error) => return Err(persistence_error),
             };
-            Ok(None)
-        }
-
-        // Get input_dir arg from cli and update imgsim_options if provided
-        match verify_cli_arg_path(arg_matches.get_one::<PathBuf>("input_dir")) {
-            Ok(Some(path)) => imgsim_options.args.input_dir = path,
-            Err(error) => return Err(error),
-            _ => (),
-        }
-
-        // Get output_dir arg from cli and update imgsim_options if provided
-        match verify_cli_arg_path(arg_matches.get_one::<PathBuf>("output_dir")) {
-            Ok(Some(path)) => imgsim_options.args.output_dir = Some(path),
-            Err(error) => return Err(error),
-            _ => (),
-        }
-
-        // Update any config values with corresponding cli args
-        fn get_cli_arg<T: IntoEnumIterator + MatchEnumAsStr>(
-            arg_matches: &ArgMatches,
-            id: &str,
-            default: &T,
-        ) -> Option<T> {
-            let cli_arg_val = arg_matches.get_one::<String>(id);
-            if let Some(val) = cli_arg_val {
-                // match value to enum
-                for option in T::iter() {
-                    if option.match_enum_as_str(val) {
-                        return Some(option);
-                    }
+            profile_problems.into_iter().for_each(|problem| {
+                if !problems.contains(&problem) {
+                    problems.push(problem);
                 }
-                eprintln!("Warning: Input \"{}\" does not match a known algorithm. Utilising default ({:?})", val, default);
-                None
-            } else {
-                None
-            }
+            });
         }
-
-        // update pixeldist_alg if given in cli
-        if let Some(arg) = get_cli_arg::<PixeldistAlg>(
-            &arg_matches,
-            "pixeldist_alg",
-            &imgsim_options.args.pixeldist_alg,
-        ) {
-            imgsim_options.args.pixeldist_alg = arg;
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(PersistenceError::InvalidConfigError(problems))
         }
+    }
 
-        // update clustering_alg if given in cli
-        if let Some(arg) = get_cli_arg::<ClusteringAlg>(
-            &arg_matches,
-            "clustering_alg",
-            &imgsim_options.args.clustering_alg,
-        ) {
-            imgsim_options.args.clustering_alg = arg;
+    /// Return the options as a TOML config, in the same format as `config.toml`.
+    pub fn to_toml(&self) -> String {
+        match toml::Value::try_from(self) {
+            Ok(value) => toml::to_string(&as_f32_precision(&value)).unwrap_or_default(),
+            Err(_) => String::new(),
         }
+    }
 
-        // update similarity_alg if given in cli
-        if let Some(arg) = get_cli_arg::<SimilarityAlg>(
-            &arg_matches,
-            "similarity_alg",
-            &imgsim_options.args.similarity_alg,
-        ) {
-            imgsim_options.args.similarity_alg = arg;
+    // Each config value by its dotted key path.
+    fn values(&self) -> BTreeMap<String, toml::Value> {
+        let mut values = BTreeMap::new();
+        if let Ok(toml::Value::Table(table)) = toml::Value::try_from(self) {
+            table.iter().for_each(|(key, value)| {
+                flatten_value(value, key, &mut |path, value| {
+                    values.insert(path, as_f32_precision(value));
+                })
+            });
         }
+        values
+    }
 
-        // update verbose
-        imgsim_options.settings.verbose = arg_matches.get_flag("verbose");
+    /// Return each config value by its dotted key path, such as `settings.max_width`, along with where it came from.
+    pub fn value_sources(&self) -> Vec<(String, String, ValueSource)> {
+        self.values()
+            .into_iter()
+            .map(|(key_path, value)| {
+                let source = if self.loaded_values.get(&key_path) != Some(&value) {
+                    ValueSource::Set
+                } else {
+                    self.value_sources
+                        .get(&key_path)
+                        .cloned()
+                        .unwrap_or(ValueSource::Default)
+                };
+                (key_path, value.to_string(), source)
+            })
+            .collect()
+    }
 
-        // update force
-        imgsim_options.settings.force = arg_matches.get_flag("force");
+    /// Start building a new ImgsimOptions out of the default options.
+    pub fn builder() -> ImgsimOptionsBuilder {
+        ImgsimOptionsBuilder::default()
+    }
 
-        // Debug imgsim_options
-        if imgsim_options.debug() {
-            println!("imgsim_options updated by cli args:");
-            dbg!(&imgsim_options);
+    // The dotted key path of every option which can be set in the config.
+    fn known_key_paths() -> BTreeSet<String> {
+        // Unset optional values are left out of the serialised options, so set each of them first
+        let mut imgsim_options: ImgsimOptions =
+            toml::from_str(DEFAULT_CONFIG_TOML_STR).expect("The default config is valid");
+        imgsim_options.args.output_dir = Some(PathBuf::new());
+        imgsim_options.settings.top = Some(1);
+        imgsim_options.scan.max_depth = Some(1);
+        imgsim_options.grouping.threshold = Some(0.0);
+        imgsim_options.dendrogram.cut_height = Some(0.0);
+        imgsim_options.dendrogram.cut_count = Some(1);
+        imgsim_options.ensemble.logistic_weights_path = Some(PathBuf::new());
+        let mut known_key_paths: BTreeSet<String> = imgsim_options.values().into_keys().collect();
+        if let Ok(toml::Value::Table(table)) = toml::Value::try_from(&imgsim_options) {
+            known_key_paths.extend(
+                validation::array_table_keys(&table, "")
+                    .iter()
+                    .map(|key_path| validation::without_indices(key_path)),
+            );
         }
+        known_key_paths
+    }
 
-        // If any chosen algorithms need to change any other settings, change them
-        if let ClusteringAlg::KMeans = imgsim_options.args.clustering_alg {
-            imgsim_options.settings.skip_pixelsim = true
+    // Describe a problem with the value of the given key, along with where the value came from.
+    fn problem(&self, key_path: &str, message: &str) -> String {
+        // Keys within an array's tables are looked up by the array holding them, as they have no line of their own
+        let value_path = validation::base_key_path(key_path);
+        let source = self
+            .value_sources
+            .get(value_path)
+            .cloned()
+            .unwrap_or_else(|| match self.loaded_values.get(value_path) {
+                Some(_) => ValueSource::Default,
+                None => ValueSource::Set,
+            });
+        let line = match &source {
+            ValueSource::ConfigFile(_) => validation::find_line(&self.key_lines, value_path),
+            ValueSource::Profile(profile) => validation::find_line(
+                &self.key_lines,
+                &format!("profiles.{}.{}", profile, value_path),
+            ),
+            _ => None,
+        };
+        match (&self.config_file, line) {
+            (Some(config_file), Some(line)) => format!(
+                "{} ({}, line {}): {}",
+                key_path,
+                config_file.to_str().unwrap_or("config file"),
+                line,
+                message
+            ),
+            _ => format!("{} ({}): {}", key_path, source, message),
         }
+    }
 
-        // If any discouraged settings combinations are chosen, stop and warn the user if the --force flag is not activated
-        if let Some(messages) = imgsim_options.discouraged_options() {
-            if imgsim_options.settings.force {
-                messages.iter().for_each(|message| {
-                    eprintln!("Discouraged Settings Warning: {}", message);
-                });
+    /// Return a description of every unknown key and out-of-range value in the options, along with its dotted key path
+    /// and where it came from.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .unknown_keys
+            .iter()
+            .map(|key_path| self.problem(key_path, "is not a known option"))
+            .collect();
+        let sources: BTreeMap<String, ValueSource> = self
+            .value_sources()
+            .into_iter()
+            .map(|(key_path, _, source)| (key_path, source))
+            .collect();
+        problems.extend(
+            validation::range_problems(self)
+                .into_iter()
+                .map(|(key_path, message)| {
+                    match sources.get(validation::base_key_path(&key_path)) {
+                        Some(ValueSource::Set) => format!("{} (set): {}", key_path, message),
+                        _ => self.problem(&key_path, &message),
+                    }
+                }),
+        );
+        problems
+    }
+
+    // Check the ensemble's members and load its logistic weights file if needed, unless the weights were given directly.
+    fn load_ensemble(&mut self) -> Result<(), PersistenceError> {
+        if self.ensemble.members.is_empty() {
+            return Err(PersistenceError::InvalidEnsembleError(String::from(
+                "The ensemble has no members.",
+            )));
+        }
+        if let Some(member) = self
+            .ensemble
+            .members
+            .iter()
+            .find(|member| matches!(member.alg(), SimilarityAlg::Ensemble))
+        {
+            return Err(PersistenceError::InvalidEnsembleError(format!(
+                "{:?} cannot be a member of the ensemble.",
+                member.alg()
+            )));
+        }
+        // Each member's score is combined once, so an algorithm listed twice would be counted twice
+        if let Some((index, member)) =
+            self.ensemble
+                .members
+                .iter()
+                .enumerate()
+                .find(|(index, member)| {
+                    self.ensemble.members[..*index]
+                        .iter()
+                        .any(|other| other.alg() == member.alg())
+                })
+        {
+            return Err(PersistenceError::InvalidEnsembleError(format!(
+                "{:?} is listed in the ensemble more than once, as member {}. Give it a single member with the combined weight instead.",
+                member.alg(),
+                index + 1
+            )));
+        }
+
+        if let (EnsembleCombine::Logistic, Some(logistic_weights)) =
+            (&self.ensemble.combine, &self.ensemble.logistic_weights)
+        {
+            if let Some(member) = self
+                .ensemble
+                .members
+                .iter()
+                .find(|member| logistic_weights.coefficient(member.alg()).is_none())
+            {
+                return Err(PersistenceError::InvalidEnsembleError(format!(
+                    "The logistic weights have no coefficient for {:?}.",
+                    member.alg()
+                )));
+            }
+        } else if let EnsembleCombine::Logistic = self.ensemble.combine {
+            let weights_path = match &self.ensemble.logistic_weights_path {
+                Some(path) => path,
+                None => {
+                    return Err(PersistenceError::InvalidEnsembleError(String::from(
+                        "The Logistic combine requires a logistic_weights_path.",
+                    )));
+                }
+            };
+            let weights_toml_str = if let Ok(string) = fs::read_to_string(weights_path) {
+                string
             } else {
-                return Err(PersistenceError::DiscouragedSettingsError(String::from(
-                    &messages[0],
+                return Err(PersistenceError::ReadFileError(Some(PathBuf::from(
+                    weights_path,
+                ))));
+            };
+            let logistic_weights: LogisticWeights = match toml::from_str(&weights_toml_str) {
+                Ok(toml) => toml,
+                Err(toml_error) => {
+                    return Err(PersistenceError::DeserializeError(String::from(
+                        toml_error.message(),
+                    )));
+                }
+            };
+            if let Some(member) = self
+                .ensemble
+                .members
+                .iter()
+                .find(|member| logistic_weights.coefficient(member.alg()).is_none())
+            {
+                return Err(PersistenceError::InvalidEnsembleError(format!(
+                    "{} has no coefficient for {:?}.",
+                    weights_path.to_str().unwrap_or("The logistic weights file"),
+                    member.alg()
                 )));
             }
+            self.ensemble.logistic_weights = Some(logistic_weights);
         }
-
-        println!("=======Selected Algorithms=======");
-        println!(
-            "Pixel Distance:   {:?}\nPixel Clustering: {:?}\nImage Similarity: {:?}",
-            imgsim_options.args.pixeldist_alg,
-            imgsim_options.args.clustering_alg,
-            imgsim_options.args.similarity_alg
-        );
-        println!("=================================");
-        Ok(imgsim_options)
+        Ok(())
     }
 
     /// Return whether or not any discouraged options combinations have been selected.
@@ -244,11 +905,75 @@ impl ImgsimOptions {
         }
     }
 
+    /// Return the names of the profiles defined in the config, including the built-in ones.
+    pub fn profiles(&self) -> impl Iterator<Item = &String> {
+        self.profiles.keys()
+    }
+
+    /// Return the name of the profile the options were loaded with, if any.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
     /// Return the directory of images imgsim compares.
     pub fn input_dir(&self) -> &Path {
         &self.args.input_dir
     }
 
+    /// Return the image files given instead of the input directory.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.args.files
+    }
+
+    /// Return the file listing further image files to compare instead of the input directory, if given. `-` means stdin.
+    pub fn files_from(&self) -> &Option<PathBuf> {
+        &self.args.files_from
+    }
+
+    /// Return true if a list of image files is compared instead of the input directory.
+    pub fn uses_file_list(&self) -> bool {
+        !self.args.files.is_empty() || self.args.files_from.is_some()
+    }
+
+    /// Return the image compared against the input directory, if the similarities of every pairing are not wanted.
+    pub fn query(&self) -> &Option<PathBuf> {
+        &self.args.query
+    }
+
+    /// Return the directory of images the input directory is compared against, if only pairings between the two directories are wanted.
+    pub fn against(&self) -> &Option<PathBuf> {
+        &self.args.against
+    }
+
+    /// Return how many levels of subdirectories below the input directory are scanned for images. [Option::None] means there is no limit.
+    pub fn scan_depth(&self) -> Option<usize> {
+        if self.scan.recursive {
+            self.scan.max_depth
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Return whether symbolic links to directories are followed when scanning recursively.
+    pub fn follow_symlinks(&self) -> bool {
+        self.scan.follow_symlinks
+    }
+
+    /// Return whether hidden files and directories, whose names start with '.', are scanned.
+    pub fn hidden(&self) -> bool {
+        self.scan.hidden
+    }
+
+    /// Return the glob patterns an image's relative path must match one of to be loaded. Every image is loaded if there are none.
+    pub fn include_globs(&self) -> &[String] {
+        &self.scan.include
+    }
+
+    /// Return the glob patterns which leave out any matching images and directories.
+    pub fn exclude_globs(&self) -> &[String] {
+        &self.scan.exclude
+    }
+
     /// Return the algorithm used to determine image pixel similarity.
     pub fn pixeldist_alg(&self) -> &PixeldistAlg {
         &self.args.pixeldist_alg
@@ -314,6 +1039,86 @@ impl ImgsimOptions {
         self.clustersize_options.clustersize_cluster_cutoff
     }
 
+    /// Return the cluster cutoff point for the clustershape similarity algorithm.
+    pub fn clustershape_cluster_cutoff(&self) -> f32 {
+        self.clustershape_options.clustershape_cluster_cutoff
+    }
+
+    /// Return the cluster cutoff point for the regiongraph similarity algorithm.
+    pub fn regiongraph_cluster_cutoff(&self) -> f32 {
+        self.regiongraph_options.regiongraph_cluster_cutoff
+    }
+
+    /// Return the number of rounds the regiongraph similarity algorithm spends refining region matches by their neighbours.
+    pub fn regiongraph_iterations(&self) -> usize {
+        self.regiongraph_options.iterations
+    }
+
+    /// Return the number of rows and columns in the grid of local binary pattern histograms used by the texture similarity algorithm.
+    pub fn texture_grid_size(&self) -> u32 {
+        self.texture_options.grid_size
+    }
+
+    /// Return the number of rows and columns in the grid of orientation histograms used by the edge orientation similarity algorithm.
+    pub fn edge_grid_size(&self) -> u32 {
+        self.edge_orientation_options.grid_size
+    }
+
+    /// Return the number of orientation bins from 0° to 180° used by the edge orientation similarity algorithm.
+    pub fn orientation_bins(&self) -> usize {
+        self.edge_orientation_options.orientation_bins
+    }
+
+    /// Return the brightness difference past which a pixel on the FAST circle counts as brighter or darker than its centre.
+    pub fn fast_threshold(&self) -> u8 {
+        self.keypoint_options.fast_threshold
+    }
+
+    /// Return the max number of keypoints detected in each image.
+    pub fn max_keypoints(&self) -> usize {
+        self.keypoint_options.max_keypoints
+    }
+
+    /// Return the number of image pyramid levels searched for keypoints.
+    pub fn pyramid_levels(&self) -> usize {
+        self.keypoint_options.pyramid_levels
+    }
+
+    /// Return the max ratio between the distances of a keypoint's nearest and second-nearest matches for the match to be kept.
+    pub fn match_ratio(&self) -> f32 {
+        self.keypoint_options.match_ratio
+    }
+
+    /// Return whether or not keypoint matches are checked for geometric consistency with RANSAC.
+    pub fn ransac(&self) -> bool {
+        self.keypoint_options.ransac
+    }
+
+    /// Return the number of homographies RANSAC tries.
+    pub fn ransac_iterations(&self) -> usize {
+        self.keypoint_options.ransac_iterations
+    }
+
+    /// Return the max distance in pixels between a projected keypoint and its match for the match to count as an inlier.
+    pub fn ransac_threshold(&self) -> f32 {
+        self.keypoint_options.ransac_threshold
+    }
+
+    /// Return the way the scores of the ensemble's members are combined.
+    pub fn ensemble_combine(&self) -> &EnsembleCombine {
+        &self.ensemble.combine
+    }
+
+    /// Return the similarity algorithms that make up the ensemble, along with their weights.
+    pub fn ensemble_members(&self) -> &Vec<EnsembleMember> {
+        &self.ensemble.members
+    }
+
+    /// Return the learned weights used by the logistic ensemble combine, if loaded.
+    pub fn logistic_weights(&self) -> &Option<LogisticWeights> {
+        &self.ensemble.logistic_weights
+    }
+
     /// Return whether or not the pixelsim algorithm should be skipped.
     pub fn skip_pixelsim(&self) -> bool {
         self.settings.skip_pixelsim
@@ -323,4 +1128,890 @@ impl ImgsimOptions {
     pub fn force(&self) -> bool {
         self.settings.force
     }
+
+    /// Return true if images should also be compared against flipped and rotated copies of each other.
+    pub fn transform_invariant(&self) -> bool {
+        self.settings.transform_invariant
+    }
+
+    /// Return the number of most-similar images to print for each image, or [Option::None] to print every pairing.
+    pub fn top(&self) -> Option<usize> {
+        self.settings.top
+    }
+
+    /// Return the similarity above which images are grouped together as duplicates, or [Option::None] to not group images.
+    pub fn grouping_threshold(&self) -> Option<f32> {
+        self.grouping.threshold
+    }
+
+    /// Return the way images are grouped together.
+    pub fn grouping_method(&self) -> &GroupingMethod {
+        &self.grouping.method
+    }
+
+    /// Return true if the images should be arranged into a [Dendrogram](crate::Dendrogram) instead of printing every pairing.
+    pub fn dendrogram(&self) -> bool {
+        self.settings.dendrogram
+    }
+
+    /// Return the linkage used to build the dendrogram.
+    pub fn linkage(&self) -> &Linkage {
+        &self.dendrogram.linkage
+    }
+
+    /// Return the height at which the dendrogram is cut into groups, if any.
+    pub fn cut_height(&self) -> Option<f32> {
+        self.dendrogram.cut_height
+    }
+
+    /// Return the number of groups the dendrogram is cut into, if any.
+    pub fn cut_count(&self) -> Option<usize> {
+        self.dendrogram.cut_count
+    }
+
+    /// Return true if the dendrogram should be printed in Newick format instead of as a text tree.
+    pub fn newick(&self) -> bool {
+        self.dendrogram.newick
+    }
+
+    /// Return true if images which are crops or scaled sub-regions of other images should be detected and reported.
+    pub fn containment(&self) -> bool {
+        self.settings.containment
+    }
+
+    /// Return the minimum proportion of a smaller image's keypoints which must be found within a larger image for it to be reported as contained.
+    pub fn containment_min_score(&self) -> f32 {
+        self.containment_options.min_score
+    }
+}
+
+/// Builds an [ImgsimOptions] programmatically, without a config file or cli args.
+///
+/// Starts from the default options, or from options loaded with [ImgsimOptions::from_config_file]. Each setter
+/// replaces one value, and [ImgsimOptionsBuilder::build] checks the options and loads anything they depend on.
+///
+/// # Examples
+///
+/// ```
+/// use imgsim::{ImgsimOptions, SimilarityAlg};
+/// let imgsim_options = ImgsimOptions::builder()
+///     .similarity_alg(SimilarityAlg::Texture)
+///     .texture_grid_size(2)
+///     .build()
+///     .unwrap();
+/// assert_eq![imgsim_options.texture_grid_size(), 2];
+/// ```
+#[derive(Debug, Default)]
+pub struct ImgsimOptionsBuilder {
+    imgsim_options: ImgsimOptions,
+}
+impl From<ImgsimOptions> for ImgsimOptionsBuilder {
+    fn from(imgsim_options: ImgsimOptions) -> ImgsimOptionsBuilder {
+        ImgsimOptionsBuilder { imgsim_options }
+    }
+}
+impl ImgsimOptionsBuilder {
+    /// Start building a new ImgsimOptions out of the default options.
+    pub fn new() -> ImgsimOptionsBuilder {
+        ImgsimOptionsBuilder::default()
+    }
+
+    /// Check the options and return them. Return [PersistenceError] if any path does not exist, a glob pattern is
+    /// malformed, the ensemble cannot be used, or discouraged settings are chosen without `force`.
+    ///
+    /// An empty input directory defaults to the working directory.
+    pub fn build(self) -> Result<ImgsimOptions, PersistenceError> {
+        let mut imgsim_options = self.imgsim_options;
+
+        let problems = imgsim_options.problems();
+        if !problems.is_empty() {
+            return Err(PersistenceError::InvalidConfigError(problems));
+        }
+
+        // Default to working dir
+        if imgsim_options.args.input_dir.as_os_str().is_empty() {
+            imgsim_options.args.input_dir = match env::current_dir() {
+                Ok(dir) => dir,
+                Err(_) => {
+                    return Err(PersistenceError::ReadFileError(None));
+                }
+            };
+        }
+
+        // Return ReadFileError if a directory doesn't exist, or NotDirectoryError if it isn't a directory
+        fn verify_dir(dir: &Path) -> Result<(), PersistenceError> {
+            if !dir.exists() {
+                return Err(PersistenceError::ReadFileError(Some(PathBuf::from(dir))));
+            }
+            if !dir.is_dir() {
+                return Err(PersistenceError::NotDirectoryError(Some(PathBuf::from(
+                    dir,
+                ))));
+            }
+            Ok(())
+        }
+        if !imgsim_options.uses_file_list() {
+            verify_dir(&imgsim_options.args.input_dir)?;
+        }
+        if let Some(output_dir) = &imgsim_options.args.output_dir {
+            verify_dir(output_dir)?;
+        }
+        if let Some(against) = &imgsim_options.args.against {
+            verify_dir(against)?;
+        }
+
+        // Return ReadFileError if an image file doesn't exist or isn't a file. "-" reads the files_from list from stdin.
+        if let Some(file) = imgsim_options
+            .args
+            .files
+            .iter()
+            .chain(imgsim_options.args.query.iter())
+            .chain(
+                imgsim_options
+                    .args
+                    .files_from
+                    .iter()
+                    .filter(|files_from| files_from.as_os_str() != "-"),
+            )
+            .find(|file| !file.is_file())
+        {
+            return Err(PersistenceError::ReadFileError(Some(PathBuf::from(file))));
+        }
+
+        for pattern in imgsim_options
+            .scan
+            .include
+            .iter()
+            .chain(imgsim_options.scan.exclude.iter())
+        {
+            Glob::new(pattern)?;
+        }
+
+        // If any chosen algorithms need to change any other settings, change them
+        if let ClusteringAlg::KMeans = imgsim_options.args.clustering_alg {
+            imgsim_options.settings.skip_pixelsim = true
+        }
+
+        // Load the learned weights if the ensemble combines its members through logistic regression
+        if let SimilarityAlg::Ensemble = imgsim_options.args.similarity_alg {
+            imgsim_options.load_ensemble()?;
+        }
+
+        // If any discouraged settings combinations are chosen, stop unless force is enabled
+        if let Some(messages) = imgsim_options.discouraged_options() {
+            if !imgsim_options.settings.force {
+                return Err(PersistenceError::DiscouragedSettingsError(String::from(
+                    &messages[0],
+                )));
+            }
+        }
+        Ok(imgsim_options)
+    }
+
+    /// Set the directory of images imgsim compares. Leave this empty to use the working directory.
+    pub fn input_dir(mut self, input_dir: PathBuf) -> Self {
+        self.imgsim_options.args.input_dir = input_dir;
+        self
+    }
+
+    /// Set the image files compared instead of the input directory.
+    pub fn files(mut self, files: Vec<PathBuf>) -> Self {
+        self.imgsim_options.args.files = files;
+        self
+    }
+
+    /// Set the file listing image files compared instead of the input directory. `-` reads the list from stdin.
+    pub fn files_from(mut self, files_from: Option<PathBuf>) -> Self {
+        self.imgsim_options.args.files_from = files_from;
+        self
+    }
+
+    /// Set the image compared against the input directory instead of comparing every pairing.
+    pub fn query(mut self, query: Option<PathBuf>) -> Self {
+        self.imgsim_options.args.query = query;
+        self
+    }
+
+    /// Set the directory of images the input directory is compared against instead of comparing every pairing.
+    pub fn against(mut self, against: Option<PathBuf>) -> Self {
+        self.imgsim_options.args.against = against;
+        self
+    }
+
+    /// Set the directory to which debug images are saved. [Option::None] saves no debug images.
+    pub fn output_dir(mut self, output_dir: Option<PathBuf>) -> Self {
+        self.imgsim_options.args.output_dir = output_dir;
+        self
+    }
+
+    /// Set the algorithm used to determine image pixel similarity.
+    pub fn pixeldist_alg(mut self, pixeldist_alg: PixeldistAlg) -> Self {
+        self.imgsim_options.args.pixeldist_alg = pixeldist_alg;
+        self
+    }
+
+    /// Set the algorithm used to determine pixel clustering.
+    pub fn clustering_alg(mut self, clustering_alg: ClusteringAlg) -> Self {
+        self.imgsim_options.args.clustering_alg = clustering_alg;
+        self
+    }
+
+    /// Set the algorithm used to determine image similarity.
+    pub fn similarity_alg(mut self, similarity_alg: SimilarityAlg) -> Self {
+        self.imgsim_options.args.similarity_alg = similarity_alg;
+        self
+    }
+
+    /// Set whether or not debug messages are printed.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.imgsim_options.settings.debug = debug;
+        self
+    }
+
+    /// Set whether or not more messages are printed to the terminal.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.imgsim_options.settings.verbose = verbose;
+        self
+    }
+
+    /// Set the width past which input images are shrunk for calculations.
+    pub fn max_width(mut self, max_width: u32) -> Self {
+        self.imgsim_options.settings.max_width = max_width;
+        self
+    }
+
+    /// Set the height past which input images are shrunk for calculations.
+    pub fn max_height(mut self, max_height: u32) -> Self {
+        self.imgsim_options.settings.max_height = max_height;
+        self
+    }
+
+    /// Set whether or not the pixelsim algorithm is skipped. Always skipped for k-means clustering.
+    pub fn skip_pixelsim(mut self, skip_pixelsim: bool) -> Self {
+        self.imgsim_options.settings.skip_pixelsim = skip_pixelsim;
+        self
+    }
+
+    /// Set whether or not discouraged settings are allowed.
+    pub fn force(mut self, force: bool) -> Self {
+        self.imgsim_options.settings.force = force;
+        self
+    }
+
+    /// Set whether or not images are also compared against flipped and rotated copies of each other.
+    pub fn transform_invariant(mut self, transform_invariant: bool) -> Self {
+        self.imgsim_options.settings.transform_invariant = transform_invariant;
+        self
+    }
+
+    /// Set whether or not images which are crops or scaled sub-regions of other images are detected.
+    pub fn containment(mut self, containment: bool) -> Self {
+        self.imgsim_options.settings.containment = containment;
+        self
+    }
+
+    /// Set the number of most-similar images to print for each image. [Option::None] prints every pairing.
+    pub fn top(mut self, top: Option<usize>) -> Self {
+        self.imgsim_options.settings.top = top;
+        self
+    }
+
+    /// Set whether or not the images are arranged into a [Dendrogram](crate::Dendrogram).
+    pub fn dendrogram(mut self, dendrogram: bool) -> Self {
+        self.imgsim_options.settings.dendrogram = dendrogram;
+        self
+    }
+
+    /// Set whether or not the subdirectories of the input directory are also scanned for images.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.imgsim_options.scan.recursive = recursive;
+        self
+    }
+
+    /// Set how many levels of subdirectories are scanned when scanning recursively. [Option::None] means no limit.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.imgsim_options.scan.max_depth = max_depth;
+        self
+    }
+
+    /// Set whether or not symbolic links to directories are followed when scanning recursively.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.imgsim_options.scan.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Set whether or not hidden files and directories are scanned.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.imgsim_options.scan.hidden = hidden;
+        self
+    }
+
+    /// Set the glob patterns an image's relative path must match one of to be loaded.
+    pub fn include_globs(mut self, include_globs: Vec<String>) -> Self {
+        self.imgsim_options.scan.include = include_globs;
+        self
+    }
+
+    /// Set the glob patterns which leave out any matching images and directories.
+    pub fn exclude_globs(mut self, exclude_globs: Vec<String>) -> Self {
+        self.imgsim_options.scan.exclude = exclude_globs;
+        self
+    }
+
+    /// Set the tolerance of the agglomerative clustering algorithm.
+    pub fn agglo_tolerance(mut self, agglo_tolerance: f32) -> Self {
+        self.imgsim_options.agglomerative_options.tolerance = agglo_tolerance;
+        self
+    }
+
+    /// Set the max number of clusters to attempt for k-means clustering.
+    pub fn max_k(mut self, max_k: usize) -> Self {
+        self.imgsim_options.kmeans_options.max_k = max_k;
+        self
+    }
+
+    /// Set the average silhouette value past which k-means clustering returns early.
+    pub fn silhouette_threshold(mut self, silhouette_threshold: f32) -> Self {
+        self.imgsim_options.kmeans_options.silhouette_threshold = silhouette_threshold;
+        self
+    }
+
+    /// Set the cluster cutoff point for the coloursim similarity algorithm.
+    pub fn coloursim_cluster_cutoff(mut self, cutoff: f32) -> Self {
+        self.imgsim_options
+            .coloursim_options
+            .coloursim_cluster_cutoff = cutoff;
+        self
+    }
+
+    /// Set the cluster cutoff point for the clustersize similarity algorithm.
+    pub fn clustersize_cluster_cutoff(mut self, cutoff: f32) -> Self {
+        self.imgsim_options
+            .clustersize_options
+            .clustersize_cluster_cutoff = cutoff;
+        self
+    }
+
+    /// Set the cluster cutoff point for the clustershape similarity algorithm.
+    pub fn clustershape_cluster_cutoff(mut self, cutoff: f32) -> Self {
+        self.imgsim_options
+            .clustershape_options
+            .clustershape_cluster_cutoff = cutoff;
+        self
+    }
+
+    /// Set the cluster cutoff point for the regiongraph similarity algorithm.
+    pub fn regiongraph_cluster_cutoff(mut self, cutoff: f32) -> Self {
+        self.imgsim_options
+            .regiongraph_options
+            .regiongraph_cluster_cutoff = cutoff;
+        self
+    }
+
+    /// Set the number of rounds the regiongraph similarity algorithm spends refining region matches.
+    pub fn regiongraph_iterations(mut self, iterations: usize) -> Self {
+        self.imgsim_options.regiongraph_options.iterations = iterations;
+        self
+    }
+
+    /// Set the number of rows and columns in the grid used by the texture similarity algorithm.
+    pub fn texture_grid_size(mut self, grid_size: u32) -> Self {
+        self.imgsim_options.texture_options.grid_size = grid_size;
+        self
+    }
+
+    /// Set the number of rows and columns in the grid used by the edge orientation similarity algorithm.
+    pub fn edge_grid_size(mut self, grid_size: u32) -> Self {
+        self.imgsim_options.edge_orientation_options.grid_size = grid_size;
+        self
+    }
+
+    /// Set the number of orientation bins used by the edge orientation similarity algorithm.
+    pub fn orientation_bins(mut self, orientation_bins: usize) -> Self {
+        self.imgsim_options
+            .edge_orientation_options
+            .orientation_bins = orientation_bins;
+        self
+    }
+
+    /// Set the brightness difference past which a pixel on the FAST circle counts as brighter or darker than its centre.
+    pub fn fast_threshold(mut self, fast_threshold: u8) -> Self {
+        self.imgsim_options.keypoint_options.fast_threshold = fast_threshold;
+        self
+    }
+
+    /// Set the max number of keypoints detected in each image.
+    pub fn max_keypoints(mut self, max_keypoints: usize) -> Self {
+        self.imgsim_options.keypoint_options.max_keypoints = max_keypoints;
+        self
+    }
+
+    /// Set the number of image pyramid levels searched for keypoints.
+    pub fn pyramid_levels(mut self, pyramid_levels: usize) -> Self {
+        self.imgsim_options.keypoint_options.pyramid_levels = pyramid_levels;
+        self
+    }
+
+    /// Set the max ratio between the distances of a keypoint's nearest and second-nearest matches.
+    pub fn match_ratio(mut self, match_ratio: f32) -> Self {
+        self.imgsim_options.keypoint_options.match_ratio = match_ratio;
+        self
+    }
+
+    /// Set whether or not keypoint matches are checked for geometric consistency with RANSAC.
+    pub fn ransac(mut self, ransac: bool) -> Self {
+        self.imgsim_options.keypoint_options.ransac = ransac;
+        self
+    }
+
+    /// Set the number of homographies RANSAC tries.
+    pub fn ransac_iterations(mut self, ransac_iterations: usize) -> Self {
+        self.imgsim_options.keypoint_options.ransac_iterations = ransac_iterations;
+        self
+    }
+
+    /// Set the max distance in pixels between a projected keypoint and its match for the match to count as an inlier.
+    pub fn ransac_threshold(mut self, ransac_threshold: f32) -> Self {
+        self.imgsim_options.keypoint_options.ransac_threshold = ransac_threshold;
+        self
+    }
+
+    /// Set the minimum proportion of a smaller image's keypoints which must be found within a larger image for it to be reported as contained.
+    pub fn containment_min_score(mut self, min_score: f32) -> Self {
+        self.imgsim_options.containment_options.min_score = min_score;
+        self
+    }
+
+    /// Set the similarity above which images are grouped together. [Option::None] does not group images.
+    pub fn grouping_threshold(mut self, threshold: Option<f32>) -> Self {
+        self.imgsim_options.grouping.threshold = threshold;
+        self
+    }
+
+    /// Set the way images are grouped together.
+    pub fn grouping_method(mut self, method: GroupingMethod) -> Self {
+        self.imgsim_options.grouping.method = method;
+        self
+    }
+
+    /// Set the linkage used to build the dendrogram.
+    pub fn linkage(mut self, linkage: Linkage) -> Self {
+        self.imgsim_options.dendrogram.linkage = linkage;
+        self
+    }
+
+    /// Set the height at which the dendrogram is cut into groups, if any.
+    pub fn cut_height(mut self, cut_height: Option<f32>) -> Self {
+        self.imgsim_options.dendrogram.cut_height = cut_height;
+        self
+    }
+
+    /// Set the number of groups the dendrogram is cut into, if any.
+    pub fn cut_count(mut self, cut_count: Option<usize>) -> Self {
+        self.imgsim_options.dendrogram.cut_count = cut_count;
+        self
+    }
+
+    /// Set whether or not the dendrogram is printed in Newick format.
+    pub fn newick(mut self, newick: bool) -> Self {
+        self.imgsim_options.dendrogram.newick = newick;
+        self
+    }
+
+    /// Set the way the scores of the ensemble's members are combined.
+    pub fn ensemble_combine(mut self, combine: EnsembleCombine) -> Self {
+        self.imgsim_options.ensemble.combine = combine;
+        self
+    }
+
+    /// Set the similarity algorithms that make up the ensemble, along with their weights.
+    pub fn ensemble_members(mut self, members: Vec<EnsembleMember>) -> Self {
+        self.imgsim_options.ensemble.members = members;
+        self
+    }
+
+    /// Set the file from which the logistic ensemble combine's weights are loaded.
+    pub fn logistic_weights_path(mut self, logistic_weights_path: Option<PathBuf>) -> Self {
+        self.imgsim_options.ensemble.logistic_weights_path = logistic_weights_path;
+        self.imgsim_options.ensemble.logistic_weights = None;
+        self
+    }
+
+    /// Set the weights used by the logistic ensemble combine, instead of loading them from a file.
+    pub fn logistic_weights(mut self, logistic_weights: LogisticWeights) -> Self {
+        self.imgsim_options.ensemble.logistic_weights = Some(logistic_weights);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MatchEnumAsStr;
+    use pretty_assertions::assert_eq;
+    use strum::IntoEnumIterator;
+
+    fn file_layer(config_toml_str: &str) -> ConfigLayer {
+        (
+            ValueSource::ConfigFile(PathBuf::from("config.toml")),
+            parse_toml(config_toml_str).unwrap(),
+        )
+    }
+
+    fn env_vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(var, value)| (String::from(*var), String::from(*value)))
+            .collect::<Vec<(String, String)>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn default_config_parses() {
+        let imgsim_options = parse_config(Vec::new(), BTreeMap::new(), None).unwrap();
+        assert_eq!(imgsim_options.max_width(), 1000);
+        assert_eq!(imgsim_options.texture_grid_size(), 4);
+    }
+
+    #[test]
+    fn partial_config_merged() {
+        let imgsim_options = parse_config(
+            vec![file_layer(
+                "[settings]\nmax_width = 200\n[edge_orientation_options]\norientation_bins = 12\n[ensemble]\nmembers = [{ alg = \"Texture\", weight = 2.0 }]",
+            )],
+            BTreeMap::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(imgsim_options.max_width(), 200);
+        assert_eq!(imgsim_options.max_height(), 1000);
+        assert_eq!(imgsim_options.orientation_bins(), 12);
+        assert_eq!(imgsim_options.edge_grid_size(), 4);
+        assert_eq!(imgsim_options.ensemble_members().len(), 1);
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert!(parse_config(
+            vec![file_layer("[settings]\nmax_width = \"wide\"")],
+            BTreeMap::new(),
+            None
+        )
+        .is_err());
+        assert!(parse_toml("[settings").is_err());
+    }
+
+    #[test]
+    fn config_problems_listed() {
+        let config_toml_str = "[settings]\nmax_widht = 500\n\n[kmeans_options]\nmax_k = 1";
+        let imgsim_options = parse_config(
+            vec![file_layer(config_toml_str)],
+            validation::key_lines(config_toml_str),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            imgsim_options.problems(),
+            vec![
+                "settings.max_widht (config.toml, line 2): is not a known option",
+                "kmeans_options.max_k (config.toml, line 5): must be at least 2, but is 1",
+            ]
+        );
+        assert!(matches!(
+            ImgsimOptions::builder()
+                .input_dir(env::temp_dir())
+                .agglo_tolerance(1.5)
+                .build(),
+            Err(PersistenceError::InvalidConfigError(_))
+        ));
+        assert!(matches!(
+            parse_config(
+                vec![file_layer("[settings]\nmax_width = \"wide\"")],
+                validation::key_lines("[settings]\nmax_width = \"wide\""),
+                None
+            ),
+            Err(PersistenceError::InvalidConfigError(problems)) if problems[0].starts_with("settings.max_width (config.toml, line 2)")
+        ));
+    }
+
+    #[test]
+    fn ensemble_member_problems_listed() {
+        let config_toml_str = "[ensemble]\nmembers = [\n    { alg = \"Texture\", weight = -1.0 },\n    { alg = \"hog\", wieght = 2.0 },\n    { alg = \"ColourSim\", weight = -0.5 },\n]";
+        let imgsim_options = parse_config(
+            vec![file_layer(config_toml_str)],
+            validation::key_lines(config_toml_str),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            imgsim_options.problems(),
+            vec![
+                "ensemble.members[1].wieght (config.toml, line 2): is not a known option",
+                "ensemble.members[0].weight (config.toml, line 2): must be at least 0, but is -1 for Texture",
+                "ensemble.members[2].weight (config.toml, line 2): must be at least 0, but is -0.5 for ColourSim",
+            ]
+        );
+    }
+
+    // Every alias matched on the cli must also be accepted in config.toml, as the same value, and the other way around.
+    fn check_aliases<T: IntoEnumIterator + MatchEnumAsStr + serde::de::DeserializeOwned>() {
+        // serde lists every name and alias it accepts between backticks, after the unknown one
+        let unknown_variant = toml::Value::String(String::from("?"))
+            .try_into::<T>()
+            .unwrap_err()
+            .to_string();
+        let accepted: Vec<&str> = unknown_variant.split('`').skip(3).step_by(2).collect();
+        assert!(!accepted.is_empty());
+        accepted.iter().for_each(|name| {
+            let from_config: T = toml::Value::String(String::from(*name)).try_into().unwrap();
+            assert_eq!(
+                format!("{:?}", T::from_enum_str(name).unwrap()),
+                format!("{:?}", from_config)
+            );
+        });
+        T::iter().for_each(|value| {
+            value.aliases().iter().for_each(|alias| {
+                let from_config: T = toml::Value::String(String::from(*alias))
+                    .try_into()
+                    .unwrap();
+                assert_eq!(format!("{:?}", from_config), format!("{:?}", value));
+                assert_eq!(
+                    format!("{:?}", T::from_enum_str(alias).unwrap()),
+                    format!("{:?}", value)
+                );
+            })
+        });
+    }
+
+    #[test]
+    fn aliases_match_config() {
+        check_aliases::<PixeldistAlg>();
+        check_aliases::<ClusteringAlg>();
+        check_aliases::<SimilarityAlg>();
+        check_aliases::<Linkage>();
+        check_aliases::<EnsembleCombine>();
+        assert!(matches!(
+            SimilarityAlg::from_enum_str("HOG"),
+            Ok(SimilarityAlg::EdgeOrientation)
+        ));
+        assert!(matches!(
+            SimilarityAlg::from_enum_str("hogg"),
+            Err(PersistenceError::UnknownChoiceError(_, _))
+        ));
+    }
+
+    #[test]
+    fn builder_sets_values() {
+        let imgsim_options = ImgsimOptions::builder()
+            .input_dir(env::temp_dir())
+            .similarity_alg(SimilarityAlg::EdgeOrientation)
+            .orientation_bins(12)
+            .grouping_threshold(Some(0.8))
+            .max_depth(Some(2))
+            .recursive(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            imgsim_options.similarity_alg(),
+            &SimilarityAlg::EdgeOrientation
+        );
+        assert_eq!(imgsim_options.orientation_bins(), 12);
+        assert_eq!(imgsim_options.grouping_threshold(), Some(0.8));
+        assert_eq!(imgsim_options.scan_depth(), Some(2));
+        assert_eq!(imgsim_options.max_width(), 1000);
+    }
+
+    #[test]
+    fn builder_checks_values() {
+        let builder = || ImgsimOptions::builder().input_dir(env::temp_dir());
+        assert!(builder()
+            .include_globs(vec![String::from("[abc")])
+            .build()
+            .is_err());
+        assert!(builder()
+            .query(Some(PathBuf::from("/no/such/image.png")))
+            .build()
+            .is_err());
+        assert!(builder()
+            .similarity_alg(SimilarityAlg::Ensemble)
+            .ensemble_members(Vec::new())
+            .build()
+            .is_err());
+        assert!(matches!(
+            builder()
+                .similarity_alg(SimilarityAlg::Ensemble)
+                .ensemble_members(vec![
+                    EnsembleMember::new(SimilarityAlg::Texture, 1.0),
+                    EnsembleMember::new(SimilarityAlg::Texture, 2.0),
+                ])
+                .build(),
+            Err(PersistenceError::InvalidEnsembleError(_))
+        ));
+        assert!(builder()
+            .clustering_alg(ClusteringAlg::KMeans)
+            .build()
+            .is_err());
+        assert!(builder()
+            .clustering_alg(ClusteringAlg::KMeans)
+            .force(true)
+            .build()
+            .unwrap()
+            .skip_pixelsim());
+    }
+
+    #[test]
+    fn env_overrides_config() {
+        let mut layers = vec![file_layer(
+            "[agglomerative_options]\ntolerance = 0.5\n[settings]\nmax_width = 200",
+        )];
+        layers.extend(env_layers(env_vars(&[
+            ("IMGSIM_AGGLOMERATIVE_OPTIONS__TOLERANCE", "0.4"),
+            ("IMGSIM_ARGS__SIMILARITY_ALG", "Texture"),
+            ("IMGSIM_SCAN__EXCLUDE", "[\"thumbs\"]"),
+            ("IMGSIM_CONFIG", "ignored.toml"),
+            ("HOME", "/root"),
+        ])));
+        let imgsim_options = parse_config(layers, BTreeMap::new(), None).unwrap();
+        assert_eq!(imgsim_options.agglo_tolerance(), 0.4);
+        assert_eq!(imgsim_options.similarity_alg(), &SimilarityAlg::Texture);
+        assert_eq!(imgsim_options.exclude_globs(), &[String::from("thumbs")]);
+        assert_eq!(imgsim_options.max_width(), 200);
+    }
+
+    #[test]
+    fn value_sources_tracked() {
+        let mut layers = vec![file_layer("[settings]\nmax_width = 200")];
+        layers.extend(env_layers(env_vars(&[(
+            "IMGSIM_SETTINGS__MAX_HEIGHT",
+            "300",
+        )])));
+        let imgsim_options =
+            ImgsimOptionsBuilder::from(parse_config(layers, BTreeMap::new(), None).unwrap())
+                .input_dir(env::temp_dir())
+                .texture_grid_size(2)
+                .build()
+                .unwrap();
+        let source = |key_path: &str| {
+            imgsim_options
+                .value_sources()
+                .into_iter()
+                .find(|(path, _, _)| path == key_path)
+                .map(|(_, value, source)| (value, source))
+                .unwrap()
+        };
+        assert_eq!(
+            source("settings.max_width"),
+            (
+                String::from("200"),
+                ValueSource::ConfigFile(PathBuf::from("config.toml"))
+            )
+        );
+        assert_eq!(
+            source("settings.max_height"),
+            (
+                String::from("300"),
+                ValueSource::Env(String::from("IMGSIM_SETTINGS__MAX_HEIGHT"))
+            )
+        );
+        assert_eq!(
+            source("texture_options.grid_size"),
+            (String::from("2"), ValueSource::Set)
+        );
+        assert_eq!(
+            source("args.similarity_alg"),
+            (String::from("\"ClusterSize\""), ValueSource::Default)
+        );
+    }
+
+    #[test]
+    fn config_toml_round_trip() {
+        let imgsim_options = parse_config(
+            vec![file_layer(
+                "[kmeans_options]\nmax_k = 4\n[agglomerative_options]\ntolerance = 0.3\n[ensemble]\nlogistic_weights_path = \"weights.toml\"",
+            )],
+            BTreeMap::new(),
+            None,
+        )
+        .unwrap();
+        // An unset weights path is left out rather than written as an empty value
+        assert!(!ImgsimOptions::default()
+            .to_toml()
+            .contains("logistic_weights_path"));
+        assert!(imgsim_options.to_toml().contains(
+            "[ensemble]\ncombine = \"WeightedMean\"\nlogistic_weights_path = \"weights.toml\"\n"
+        ));
+        let reloaded = parse_config(
+            vec![file_layer(&imgsim_options.to_toml())],
+            BTreeMap::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(reloaded.values(), imgsim_options.values());
+        assert!(reloaded.problems().is_empty());
+    }
+
+    #[test]
+    fn config_file_validated() {
+        let config_dir = env::temp_dir().join("imgsim_config_file_validated");
+        let config_path = config_dir.join("imgsim").join("config.toml");
+        ImgsimOptions::write_default_config(&config_path).unwrap();
+        assert!(ImgsimOptions::validate_config_file(&config_path).is_ok());
+
+        fs::write(
+            &config_path,
+            "[profiles.wide.settings]\nmax_width = 0\nmax_hieght = 5",
+        )
+        .unwrap();
+        let problems = match ImgsimOptions::validate_config_file(&config_path) {
+            Err(PersistenceError::InvalidConfigError(problems)) => problems,
+            _ => panic!("The config should be invalid"),
+        };
+        fs::remove_dir_all(&config_dir).unwrap();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("profiles.wide.settings.max_hieght"));
+        assert!(problems[1].ends_with("line 2): must be above 0, but is 0"));
+
+        // A value of the wrong type in one profile doesn't hide the problems elsewhere
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(
+            &config_path,
+            "[settings]\nmax_widht = 5\n[profiles.a.settings]\nmax_width = \"wide\"\n[profiles.b.settings]\nmax_height = 0",
+        )
+        .unwrap();
+        let problems = match ImgsimOptions::validate_config_file(&config_path) {
+            Err(PersistenceError::InvalidConfigError(problems)) => problems,
+            _ => panic!("The config should be invalid"),
+        };
+        fs::remove_dir_all(&config_dir).unwrap();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].ends_with("line 2): is not a known option"));
+        assert!(problems[1].contains("line 4): invalid type"));
+        assert!(problems[2].ends_with("line 6): must be above 0, but is 0"));
+    }
+
+    #[test]
+    fn builtin_profiles() {
+        let imgsim_options = parse_config(Vec::new(), BTreeMap::new(), None).unwrap();
+        let profiles: Vec<&String> = imgsim_options.profiles().collect();
+        assert_eq!(profiles, vec!["duplicates", "layout", "palette"]);
+        for profile in profiles {
+            assert!(parse_config(Vec::new(), BTreeMap::new(), Some(profile)).is_ok());
+        }
+        assert!(parse_config(Vec::new(), BTreeMap::new(), Some("nope")).is_err());
+    }
+
+    #[test]
+    fn profile_precedence() {
+        let mut layers = vec![file_layer(
+            "[settings]\nmax_width = 200\n[profiles.small.settings]\nmax_width = 100\nmax_height = 100\n[profiles.small.args]\nsimilarity_alg = \"Texture\"",
+        )];
+        layers.extend(env_layers(env_vars(&[(
+            "IMGSIM_SETTINGS__MAX_HEIGHT",
+            "300",
+        )])));
+        let imgsim_options = parse_config(layers, BTreeMap::new(), Some("small")).unwrap();
+        assert_eq!(imgsim_options.profile(), Some("small"));
+        assert_eq!(imgsim_options.max_width(), 100);
+        assert_eq!(imgsim_options.max_height(), 300);
+        assert_eq!(imgsim_options.similarity_alg(), &SimilarityAlg::Texture);
+    }
 }
@@ -0,0 +1,371 @@
+#![warn(missing_docs)]
+//! Checks of config values which deserialising alone cannot catch, such as out-of-range numbers and unknown keys.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ImgsimOptions;
+
+/// Return the line each key is set on in the given TOML, by dotted key path. Table headers are listed too.
+///
+/// Keys within inline tables and arrays are not listed, so look them up with [find_line].
+pub fn key_lines(config_toml_str: &str) -> BTreeMap<String, usize> {
+    let mut key_lines = BTreeMap::new();
+    let mut table_path = String::new();
+    for (index, line) in config_toml_str.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            table_path =
+                normalise_key(line.trim_start_matches('[').split(']').next().unwrap_or(""));
+            key_lines.entry(table_path.clone()).or_insert(index + 1);
+        } else if let Some((key, _)) = line.split_once('=') {
+            // Skip comments and the lines of multi-line arrays
+            if !key.chars().all(|c| {
+                c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '"' | '\'' | ' ')
+            }) || key.trim().is_empty()
+            {
+                continue;
+            }
+            let key = normalise_key(key);
+            let key_path = if table_path.is_empty() {
+                key
+            } else {
+                format!("{}.{}", table_path, key)
+            };
+            key_lines.entry(key_path).or_insert(index + 1);
+        }
+    }
+    key_lines
+}
+
+fn normalise_key(key: &str) -> String {
+    key.split('.')
+        .map(|part| part.trim().trim_matches('"').trim_matches('\''))
+        .collect::<Vec<&str>>()
+        .join(".")
+}
+
+/// Return the line the given key, or the nearest table or key containing it, is set on.
+pub fn find_line(key_lines: &BTreeMap<String, usize>, key_path: &str) -> Option<usize> {
+    let mut key_path = key_path;
+    loop {
+        if let Some(line) = key_lines.get(key_path) {
+            return Some(*line);
+        }
+        key_path = &key_path[..key_path.rfind('.')?];
+    }
+}
+
+/// Return the dotted key path set on the given line, if any.
+pub fn key_at_line(key_lines: &BTreeMap<String, usize>, line: usize) -> Option<&str> {
+    key_lines
+        .iter()
+        .filter(|(_, key_line)| **key_line == line)
+        .map(|(key_path, _)| key_path.as_str())
+        .max_by_key(|key_path| key_path.len())
+}
+
+/// Return the dotted key path of each key within the tables of an array, such as `ensemble.members[0].alg`, for every
+/// array of tables in the given table.
+pub fn array_table_keys(table: &toml::Table, key_path: &str) -> Vec<String> {
+    table
+        .iter()
+        .flat_map(|(key, value)| {
+            let value_path = if key_path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", key_path, key)
+            };
+            match value {
+                toml::Value::Table(table) => array_table_keys(table, &value_path),
+                toml::Value::Array(array) => array
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, value)| value.as_table().map(|table| (index, table)))
+                    .flat_map(|(index, table)| {
+                        let element_path = format!("{}[{}]", value_path, index);
+                        table
+                            .iter()
+                            .map(|(key, _)| format!("{}.{}", element_path, key))
+                            .chain(array_table_keys(table, &element_path))
+                            .collect::<Vec<String>>()
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// Return the key path of the value holding the given key, e.g. `ensemble.members` for `ensemble.members[0].alg`.
+pub fn base_key_path(key_path: &str) -> &str {
+    key_path.split('[').next().unwrap_or(key_path)
+}
+
+/// Return the key path with each array index left out, e.g. `ensemble.members[].alg` for `ensemble.members[2].alg`, so
+/// that keys within any element of an array are checked alike.
+pub fn without_indices(key_path: &str) -> String {
+    let mut in_index = false;
+    key_path
+        .chars()
+        .filter(|c| {
+            match c {
+                '[' => in_index = true,
+                ']' => in_index = false,
+                _ => return !in_index,
+            }
+            true
+        })
+        .collect()
+}
+
+/// Return the key paths of the given values which are not known options. Values within a `[profiles.<name>]` table
+/// are checked as if they were outside it, and keys within the tables of an array are checked against the known keys
+/// of any of its elements, which are given without their index, e.g. `ensemble.members[].alg`.
+pub fn unknown_keys<'a>(
+    key_paths: impl Iterator<Item = &'a String>,
+    known_key_paths: &BTreeSet<String>,
+) -> Vec<String> {
+    key_paths
+        .filter(|key_path| {
+            let option_path = match key_path.strip_prefix("profiles.") {
+                Some(profile_path) => match profile_path.split_once('.') {
+                    Some((_, option_path)) => option_path,
+                    None => profile_path,
+                },
+                None => key_path.as_str(),
+            };
+            !known_key_paths.contains(&without_indices(option_path))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Return the key path of each option whose value is out of range, along with the reason.
+pub fn range_problems(imgsim_options: &ImgsimOptions) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    let mut check = |key_path: &str, valid: bool, requirement: &str, value: String| {
+        if !valid {
+            problems.push((
+                String::from(key_path),
+                format!("must be {}, but is {}", requirement, value),
+            ));
+        }
+    };
+    let between = |value: f32, min: f32, max: f32| (min..=max).contains(&value);
+
+    check(
+        "settings.max_width",
+        imgsim_options.max_width() > 0,
+        "above 0",
+        imgsim_options.max_width().to_string(),
+    );
+    check(
+        "settings.max_height",
+        imgsim_options.max_height() > 0,
+        "above 0",
+        imgsim_options.max_height().to_string(),
+    );
+    if let Some(top) = imgsim_options.top() {
+        check("settings.top", top > 0, "above 0", top.to_string());
+    }
+    // The tolerance is used to index into the sorted pixel distances, so 1 would be past the end
+    let tolerance = imgsim_options.agglo_tolerance();
+    check(
+        "agglomerative_options.tolerance",
+        (0.0..1.0).contains(&tolerance),
+        "at least 0 and less than 1",
+        tolerance.to_string(),
+    );
+    check(
+        "kmeans_options.max_k",
+        imgsim_options.max_k() >= 2,
+        "at least 2",
+        imgsim_options.max_k().to_string(),
+    );
+    let silhouette_threshold = imgsim_options.silhouette_threshold();
+    check(
+        "kmeans_options.silhouette_threshold",
+        between(silhouette_threshold, -1.0, 1.0),
+        "from -1 to 1",
+        silhouette_threshold.to_string(),
+    );
+    for (key_path, cutoff) in [
+        (
+            "coloursim_options.coloursim_cluster_cutoff",
+            imgsim_options.coloursim_cluster_cutoff(),
+        ),
+        (
+            "clustersize_options.clustersize_cluster_cutoff",
+            imgsim_options.clustersize_cluster_cutoff(),
+        ),
+        (
+            "clustershape_options.clustershape_cluster_cutoff",
+            imgsim_options.clustershape_cluster_cutoff(),
+        ),
+        (
+            "regiongraph_options.regiongraph_cluster_cutoff",
+            imgsim_options.regiongraph_cluster_cutoff(),
+        ),
+        (
+            "containment_options.min_score",
+            imgsim_options.containment_min_score(),
+        ),
+    ] {
+        check(
+            key_path,
+            between(cutoff, 0.0, 1.0),
+            "from 0 to 1",
+            cutoff.to_string(),
+        );
+    }
+    for (key_path, count) in [
+        (
+            "texture_options.grid_size",
+            imgsim_options.texture_grid_size() as usize,
+        ),
+        (
+            "edge_orientation_options.grid_size",
+            imgsim_options.edge_grid_size() as usize,
+        ),
+        (
+            "edge_orientation_options.orientation_bins",
+            imgsim_options.orientation_bins(),
+        ),
+        (
+            "keypoint_options.max_keypoints",
+            imgsim_options.max_keypoints(),
+        ),
+        (
+            "keypoint_options.pyramid_levels",
+            imgsim_options.pyramid_levels(),
+        ),
+        (
+            "keypoint_options.ransac_iterations",
+            imgsim_options.ransac_iterations(),
+        ),
+    ] {
+        check(key_path, count > 0, "above 0", count.to_string());
+    }
+    let match_ratio = imgsim_options.match_ratio();
+    check(
+        "keypoint_options.match_ratio",
+        match_ratio > 0.0 && match_ratio <= 1.0,
+        "above 0 and at most 1",
+        match_ratio.to_string(),
+    );
+    let ransac_threshold = imgsim_options.ransac_threshold();
+    check(
+        "keypoint_options.ransac_threshold",
+        ransac_threshold > 0.0,
+        "above 0",
+        ransac_threshold.to_string(),
+    );
+    if let Some(threshold) = imgsim_options.grouping_threshold() {
+        check(
+            "grouping.threshold",
+            between(threshold, -1.0, 1.0),
+            "from -1 to 1",
+            threshold.to_string(),
+        );
+    }
+    if let Some(cut_height) = imgsim_options.cut_height() {
+        check(
+            "dendrogram.cut_height",
+            between(cut_height, 0.0, 1.0),
+            "from 0 to 1",
+            cut_height.to_string(),
+        );
+    }
+    if let Some(cut_count) = imgsim_options.cut_count() {
+        check(
+            "dendrogram.cut_count",
+            cut_count > 0,
+            "above 0",
+            cut_count.to_string(),
+        );
+    }
+    for (index, member) in imgsim_options.ensemble_members().iter().enumerate() {
+        check(
+            &format!("ensemble.members[{}].weight", index),
+            member.weight() >= 0.0 && member.weight().is_finite(),
+            "at least 0",
+            format!("{} for {:?}", member.weight(), member.alg()),
+        );
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn lines_of_keys() {
+        let key_lines = key_lines(
+            "top = 1\n[settings]\n# max_width = 5\nmax_width = 10\n\"max_height\" = 5\n[ensemble]\nmembers = [\n    { alg = \"Texture\", weight = 1.0 },\n]\n[profiles.a.args]\nsimilarity_alg = \"Texture\"",
+        );
+        assert_eq!(key_lines.get("top"), Some(&1));
+        assert_eq!(key_lines.get("settings.max_width"), Some(&4));
+        assert_eq!(key_lines.get("settings.max_height"), Some(&5));
+        assert_eq!(find_line(&key_lines, "ensemble.members.alg"), Some(7));
+        assert_eq!(key_lines.get("profiles.a.args.similarity_alg"), Some(&11));
+        assert_eq!(key_at_line(&key_lines, 4), Some("settings.max_width"));
+        assert_eq!(key_lines.len(), 8);
+    }
+
+    #[test]
+    fn unknown_keys_found() {
+        let known: BTreeSet<String> = [
+            "settings.max_width",
+            "args.similarity_alg",
+            "ensemble.members[].alg",
+        ]
+        .iter()
+        .map(|key_path| String::from(*key_path))
+        .collect();
+        let key_paths: Vec<String> = [
+            "settings.max_width",
+            "settings.max_widht",
+            "profiles.a.args.similarity_alg",
+            "profiles.a.args.simlarity_alg",
+            "setings.debug",
+            "ensemble.members[0].alg",
+            "ensemble.members[1].wieght",
+        ]
+        .iter()
+        .map(|key_path| String::from(*key_path))
+        .collect();
+        assert_eq!(
+            unknown_keys(key_paths.iter(), &known),
+            vec![
+                "settings.max_widht",
+                "profiles.a.args.simlarity_alg",
+                "setings.debug",
+                "ensemble.members[1].wieght"
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_of_array_tables() {
+        let table: toml::Table = toml::from_str(
+            "[ensemble]\nmembers = [{ alg = \"Texture\" }, { alg = \"Hog\", weight = 2.0 }]\nscores = [1, 2]",
+        )
+        .unwrap();
+        assert_eq!(
+            array_table_keys(&table, ""),
+            vec![
+                "ensemble.members[0].alg",
+                "ensemble.members[1].alg",
+                "ensemble.members[1].weight"
+            ]
+        );
+        assert_eq!(base_key_path("ensemble.members[1].alg"), "ensemble.members");
+    }
+
+    #[test]
+    fn default_options_in_range() {
+        assert_eq!(range_problems(&ImgsimOptions::default()), vec![]);
+    }
+}
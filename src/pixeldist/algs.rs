@@ -1,12 +1,12 @@
 #![warn(missing_docs)]
 
 use image::Rgba;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
-use crate::{ImgsimOptions, MatchEnumAsStr};
+use crate::{DescribeAlg, ImgsimOptions, MatchEnumAsStr};
 
-#[derive(Debug, Deserialize, EnumIter)]
+#[derive(Debug, Deserialize, Serialize, EnumIter)]
 /// Denotes the type of pixel distance algorithm being utilised.
 pub enum PixeldistAlg {
     // IDEA distance more heavily weighted on hue distance and less on brightness distance?
@@ -17,7 +17,26 @@ pub enum PixeldistAlg {
     /// Euclidean distance scaled to better approximate human colour perception.
     Redmean,
 }
-impl MatchEnumAsStr for PixeldistAlg {}
+impl MatchEnumAsStr for PixeldistAlg {
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            PixeldistAlg::Euclidean => &["euclidean"],
+            PixeldistAlg::Redmean => &["redmean"],
+        }
+    }
+}
+impl DescribeAlg for PixeldistAlg {
+    fn description(&self) -> &'static str {
+        match self {
+            PixeldistAlg::Euclidean => {
+                "Standard Euclidean distance between two pixels' sRGB values."
+            }
+            PixeldistAlg::Redmean => {
+                "Euclidean distance scaled to better approximate human colour perception."
+            }
+        }
+    }
+}
 
 /// Get the colour distance between two pixels. The method by which this distance is calculated is determined by [ImgsimOptions].
 pub fn get_pixeldist(
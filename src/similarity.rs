@@ -1,4 +1,15 @@
 #![warn(missing_docs)]
 
 pub mod algs;
+pub mod containment;
+pub mod cross;
+pub mod dendrogram;
+pub mod dhash;
+pub mod edge_orientation;
+pub mod ensemble;
 pub mod errors;
+pub mod grouping;
+pub mod indexed;
+pub mod keypoints;
+pub mod region_graph;
+pub mod texture;
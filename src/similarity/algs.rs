@@ -1,13 +1,28 @@
 #![warn(missing_docs)]
 
 use rayon::prelude::*;
-use serde::Deserialize;
-use std::{cmp, collections::HashMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp,
+    collections::HashMap,
+    f32::consts::{FRAC_PI_2, PI},
+    ptr,
+};
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
-use crate::{ImageSimilarityMatrixNoMatchError, ImgsimImage, ImgsimOptions, MatchEnumAsStr};
+use crate::{
+    dhash::{dhash, hash_similarity},
+    display_names,
+    edge_orientation::{cell_similarity, orientation_histograms},
+    keypoints::{detect_keypoints, match_keypoints, ransac_inliers},
+    similarity::ensemble::ensemble_similarities,
+    texture::{chi_square, lbp_histograms},
+    ClusterShape, DescribeAlg, ImageId, ImageSimilarityMatrixNoMatchError, ImgsimImage,
+    ImgsimOptions, MatchEnumAsStr, RegionGraph, Transform,
+};
 
-#[derive(Debug, Deserialize, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, EnumIter)]
 /// Denotes the type of image similarity algorithm being utilised.
 pub enum SimilarityAlg {
     #[serde(alias = "coloursim", alias = "colorsim")]
@@ -16,340 +31,1044 @@ pub enum SimilarityAlg {
     #[serde(alias = "clustersize", alias = "Clustersize")]
     /// Matches similar images based on the relative shape and size of their most distinct clusters.
     ClusterSize,
+    #[serde(alias = "clustershape", alias = "Clustershape")]
+    /// Matches similar images based on the shape descriptors (extent, centroid, Hu moments, eccentricity, orientation) of their most distinct clusters.
+    ClusterShape,
+    #[serde(alias = "regiongraph", alias = "Regiongraph")]
+    /// Matches similar images based on the colour, size and spatial arrangement of their most distinct clusters and which clusters border each other.
+    RegionGraph,
+    #[serde(alias = "texture", alias = "lbp")]
+    /// Matches similar images based on histograms of their grayscale local binary patterns.
+    Texture,
+    #[serde(alias = "edgeorientation", alias = "edges", alias = "hog")]
+    /// Matches similar images based on histograms of their edges' orientations, regardless of colour.
+    EdgeOrientation,
+    #[serde(alias = "keypoints", alias = "orb")]
+    /// Matches similar images based on the proportion of their corners that can be matched to each other. Detects cropped, rotated and partially overlapping copies.
+    Keypoints,
+    #[serde(alias = "dhash", alias = "hash")]
+    /// Matches near-identical copies based on the Hamming distance between their 64-bit difference hashes.
+    DHash,
+    #[serde(alias = "ensemble")]
+    /// Combines the scores of several other similarity algorithms, as listed in the `[ensemble]` section of config.toml.
+    Ensemble,
+}
+impl MatchEnumAsStr for SimilarityAlg {
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            SimilarityAlg::ColourSim => &["coloursim", "colorsim"],
+            SimilarityAlg::ClusterSize => &["clustersize", "Clustersize"],
+            SimilarityAlg::ClusterShape => &["clustershape", "Clustershape"],
+            SimilarityAlg::RegionGraph => &["regiongraph", "Regiongraph"],
+            SimilarityAlg::Texture => &["texture", "lbp"],
+            SimilarityAlg::EdgeOrientation => &["edgeorientation", "edges", "hog"],
+            SimilarityAlg::Keypoints => &["keypoints", "orb"],
+            SimilarityAlg::DHash => &["dhash", "hash"],
+            SimilarityAlg::Ensemble => &["ensemble"],
+        }
+    }
+}
+impl DescribeAlg for SimilarityAlg {
+    fn description(&self) -> &'static str {
+        match self {
+            SimilarityAlg::ColourSim => "Matches similar images based on the average colour of their most distinct clusters.",
+            SimilarityAlg::ClusterSize => "Matches similar images based on the relative shape and size of their most distinct clusters.",
+            SimilarityAlg::ClusterShape => "Matches similar images based on the shape descriptors of their most distinct clusters.",
+            SimilarityAlg::RegionGraph => "Matches similar images based on the colour, size and arrangement of their most distinct clusters.",
+            SimilarityAlg::Texture => "Matches similar images based on histograms of their grayscale local binary patterns.",
+            SimilarityAlg::EdgeOrientation => "Matches similar images based on histograms of their edges' orientations, regardless of colour.",
+            SimilarityAlg::Keypoints => "Matches similar images based on how many of their corners match. Detects cropped, rotated and overlapping copies.",
+            SimilarityAlg::DHash => "Matches near-identical copies based on how many bits of their difference hashes differ. Very fast.",
+            SimilarityAlg::Ensemble => "Combines the scores of several other similarity algorithms.",
+        }
+    }
+
+    fn tunable_options(&self) -> &'static [&'static str] {
+        match self {
+            SimilarityAlg::ColourSim => &["coloursim_options.coloursim_cluster_cutoff"],
+            SimilarityAlg::ClusterSize => &["clustersize_options.clustersize_cluster_cutoff"],
+            SimilarityAlg::ClusterShape => &["clustershape_options.clustershape_cluster_cutoff"],
+            SimilarityAlg::RegionGraph => &[
+                "regiongraph_options.regiongraph_cluster_cutoff",
+                "regiongraph_options.iterations",
+            ],
+            SimilarityAlg::Texture => &["texture_options.grid_size"],
+            SimilarityAlg::EdgeOrientation => &[
+                "edge_orientation_options.grid_size",
+                "edge_orientation_options.orientation_bins",
+            ],
+            SimilarityAlg::Keypoints => &[
+                "keypoint_options.fast_threshold",
+                "keypoint_options.max_keypoints",
+                "keypoint_options.pyramid_levels",
+                "keypoint_options.match_ratio",
+                "keypoint_options.ransac",
+                "keypoint_options.ransac_iterations",
+                "keypoint_options.ransac_threshold",
+            ],
+            SimilarityAlg::DHash => &[],
+            SimilarityAlg::Ensemble => &[
+                "ensemble.combine",
+                "ensemble.members",
+                "ensemble.logistic_weights_path",
+            ],
+        }
+    }
 }
-impl MatchEnumAsStr for SimilarityAlg {}
 
 /// Get each image's similarity to every other image
+///
+/// If `transform_invariant` is enabled, each pairing is also compared with the second image flipped and rotated, and
+/// the best score is kept along with the [Transform] which produced it.
 pub fn get_similarities(
-    images: &Vec<ImgsimImage>,
+    images: &[ImgsimImage],
     imgsim_options: &ImgsimOptions,
 ) -> ImageSimilarityMatrix {
-    let mut output_matrix = ImageSimilarityMatrix::from(images);
-    match imgsim_options.similarity_alg() {
-        SimilarityAlg::ColourSim => output_matrix.colour_sim(images, imgsim_options),
-        SimilarityAlg::ClusterSize => output_matrix.cluster_size(images, imgsim_options),
+    let (similarities, transforms) = best_similarities(
+        images,
+        images,
+        &Pairings::Within(images.len()),
+        imgsim_options,
+    );
+    ImageSimilarityMatrix::from(images).with_similarities(similarities, transforms)
+}
+
+/// The image pairings to compare, given as indices into two lists of images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pairings {
+    /// Every pairing `(i, j)` with `i < j` within a single list of this many images, row by row.
+    Within(usize),
+    /// Every image of the first list against every image of the second list, row by row.
+    Between(usize, usize),
+}
+impl Pairings {
+    /// Compute the given function for every pairing, in order. [Option::None] is stored as NaN.
+    pub(crate) fn fill(&self, similarity: impl Fn(usize, usize) -> Option<f32> + Sync) -> Vec<f32> {
+        let pairing = |(i, j): (usize, usize)| similarity(i, j).unwrap_or(f32::NAN);
+        match *self {
+            Pairings::Within(len) => (0..len)
+                .into_par_iter()
+                .flat_map(|i| (i + 1..len).into_par_iter().map(move |j| (i, j)))
+                .map(pairing)
+                .collect(),
+            Pairings::Between(rows, columns) => (0..rows)
+                .into_par_iter()
+                .flat_map(|i| (0..columns).into_par_iter().map(move |j| (i, j)))
+                .map(pairing)
+                .collect(),
+        }
+    }
+}
+
+/// Compute every pairing's similarity, keeping the best of every [Transform] of the second image if `transform_invariant` is enabled.
+///
+/// Returns the similarities in the order of the pairings, along with the non-identity transforms which produced them.
+pub(crate) fn best_similarities(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> (Vec<f32>, HashMap<usize, Transform>) {
+    let mut similarities = compute(
+        imgsim_options.similarity_alg(),
+        images,
+        others,
+        pairings,
+        imgsim_options,
+    );
+    let mut transforms: HashMap<usize, Transform> = HashMap::new();
+    if imgsim_options.transform_invariant() {
+        for transform in Transform::iter().filter(|t| *t != Transform::Identity) {
+            if imgsim_options.debug() || imgsim_options.verbose() {
+                println!("Computing {:?} similarities.", transform);
+            }
+            let transformed_others: Vec<ImgsimImage> = others
+                .par_iter()
+                .map(|image| image.transformed(&transform))
+                .collect();
+            keep_best(
+                &mut similarities,
+                &mut transforms,
+                compute(
+                    imgsim_options.similarity_alg(),
+                    images,
+                    &transformed_others,
+                    pairings,
+                    imgsim_options,
+                ),
+                transform,
+            );
+        }
+    }
+    (similarities, transforms)
+}
+
+// Replace each similarity with the one from `other` if it is higher, recording the transform that produced it.
+fn keep_best(
+    similarities: &mut [f32],
+    transforms: &mut HashMap<usize, Transform>,
+    other: Vec<f32>,
+    transform: Transform,
+) {
+    for (index, (similarity, other_similarity)) in similarities.iter_mut().zip(other).enumerate() {
+        if !other_similarity.is_nan() && (similarity.is_nan() || other_similarity > *similarity) {
+            *similarity = other_similarity;
+            transforms.insert(index, transform);
+        }
+    }
+}
+
+/// Compute every pairing's similarity using a single similarity algorithm, in the order of the pairings.
+///
+/// The second image of each pairing is taken from `others`, which may hold transformed copies of `images`.
+pub(crate) fn compute(
+    similarity_alg: &SimilarityAlg,
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    match similarity_alg {
+        SimilarityAlg::ColourSim => colour_sim(images, others, pairings, imgsim_options),
+        SimilarityAlg::ClusterSize => cluster_size(images, others, pairings, imgsim_options),
+        SimilarityAlg::ClusterShape => cluster_shape(images, others, pairings, imgsim_options),
+        SimilarityAlg::RegionGraph => region_graph(images, others, pairings, imgsim_options),
+        SimilarityAlg::Texture => texture(images, others, pairings, imgsim_options),
+        SimilarityAlg::EdgeOrientation => {
+            edge_orientation(images, others, pairings, imgsim_options)
+        }
+        SimilarityAlg::Keypoints => keypoints(images, others, pairings, imgsim_options),
+        SimilarityAlg::DHash => difference_hash(images, others, pairings),
+        SimilarityAlg::Ensemble => ensemble_similarities(images, others, pairings, imgsim_options),
+    }
+}
+
+// Compute every pairing's similarity by comparing the first image's features to the second image's features.
+//
+// The second image of each pairing is taken from `others`. This is usually `images` itself, in which case each
+// image's features are only extracted once.
+fn compare_all<'a, T: Send + Sync>(
+    images: &'a [ImgsimImage],
+    others: &'a [ImgsimImage],
+    pairings: &Pairings,
+    extract: impl Fn(&'a ImgsimImage) -> T + Sync,
+    compare: impl Fn(&T, &T) -> Option<f32> + Sync,
+) -> Vec<f32> {
+    let features_a: Vec<T> = images.par_iter().map(&extract).collect();
+    let features_b: Option<Vec<T>> = if ptr::eq(images, others) {
+        None
+    } else {
+        Some(others.par_iter().map(&extract).collect())
     };
-    output_matrix
+    let features_b = features_b.as_ref().unwrap_or(&features_a);
+    pairings.fill(|i, j| compare(&features_a[i], &features_b[j]))
 }
 
-/// A matrix of [ImgsimImage] pairings and their similarities.
+/// A symmetric matrix of [ImgsimImage] pairings and their similarities.
+///
+/// Images are referred to by their [ImageId], by their display name, or by their index in the list of images the
+/// matrix was built from. Only the upper triangle of the matrix is stored, packed row by row, so each pairing is
+/// stored once.
 #[derive(Debug)]
 pub struct ImageSimilarityMatrix {
-    matrix: HashMap<(String, String), Option<f32>>,
+    ids: Vec<ImageId>,
+    names: Vec<String>,
+    indices: HashMap<String, usize>,
+    // NaN marks a pairing which could not be compared
+    similarities: Vec<f32>,
+    transforms: HashMap<usize, Transform>,
 }
 impl ImageSimilarityMatrix {
     /// Builds an empty [ImageSimilarityMatrix] out of a provided list of images.
-    fn from(images: &[ImgsimImage]) -> ImageSimilarityMatrix {
-        let combinations = (images.len().pow(2) - images.len()) / 2;
-        let mut matrix: HashMap<(String, String), Option<f32>> =
-            HashMap::with_capacity(combinations);
-
-        for i in 0..images.len() {
-            for j in i + 1..images.len() {
-                matrix.insert(
-                    (
-                        String::from(images[i].name()),
-                        String::from(images[j].name()),
-                    ),
-                    None,
-                );
+    ///
+    /// Each image's [ImageId] is indexed by its place in the list, and images which share a name are given unique
+    /// display names.
+    pub(crate) fn from(images: &[ImgsimImage]) -> ImageSimilarityMatrix {
+        let ids: Vec<ImageId> = images
+            .iter()
+            .enumerate()
+            .map(|(index, image)| image.id().with_index(index))
+            .collect();
+        let names: Vec<String> = images
+            .iter()
+            .map(|image| String::from(image.name()))
+            .collect();
+        let names = display_names(&ids, &names);
+        ImageSimilarityMatrix::from_ids(ids, names)
+    }
+
+    /// Builds an empty [ImageSimilarityMatrix] out of a list of image names, which are also used as their paths.
+    #[cfg(test)]
+    pub(crate) fn from_names(names: Vec<String>) -> ImageSimilarityMatrix {
+        let ids: Vec<ImageId> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| ImageId::new(index, std::path::PathBuf::from(name)))
+            .collect();
+        ImageSimilarityMatrix::from_ids(ids, names)
+    }
+
+    // Builds an empty matrix out of each image's identity and unique display name.
+    fn from_ids(ids: Vec<ImageId>, names: Vec<String>) -> ImageSimilarityMatrix {
+        let indices: HashMap<String, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.clone(), index))
+            .collect();
+        let combinations = names.len() * names.len().saturating_sub(1) / 2;
+        ImageSimilarityMatrix {
+            ids,
+            names,
+            indices,
+            similarities: vec![f32::NAN; combinations],
+            transforms: HashMap::new(),
+        }
+    }
+
+    /// Replaces the matrix's similarities, given in packed order, along with the transforms which produced them.
+    pub(crate) fn with_similarities(
+        mut self,
+        similarities: Vec<f32>,
+        transforms: HashMap<usize, Transform>,
+    ) -> ImageSimilarityMatrix {
+        self.similarities = similarities;
+        self.transforms = transforms;
+        self
+    }
+
+    /// Returns the number of images in the matrix.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns true if the matrix has no images.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns the identities of the images, in index order.
+    pub fn ids(&self) -> &[ImageId] {
+        &self.ids
+    }
+
+    /// Returns the index of the image with the given identity.
+    pub fn index_of(&self, id: &ImageId) -> Option<usize> {
+        if self.ids.get(id.index()) == Some(id) {
+            Some(id.index())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the display names of the images, in index order. No two images share a display name.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the index of the image with the given name.
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    /// Returns the name of the image at the given index.
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(index).map(String::as_str)
+    }
+
+    // Position of the pairing (i, j) within the packed upper triangle. Requires i < j < len.
+    fn packed_index(&self, i: usize, j: usize) -> usize {
+        i * (2 * self.names.len() - i - 1) / 2 + (j - i - 1)
+    }
+
+    // The pairing (i, j) at the given position within the packed upper triangle. Requires the position to be in range.
+    fn unpacked_index(&self, packed_index: usize) -> (usize, usize) {
+        // Row i starts at packed_index(i, i + 1), so search for the last row starting at or before the position
+        let (mut low, mut high) = (0, self.names.len() - 1);
+        while high - low > 1 {
+            let mid = (low + high) / 2;
+            if self.packed_index(mid, mid + 1) <= packed_index {
+                low = mid;
+            } else {
+                high = mid;
             }
         }
-        ImageSimilarityMatrix { matrix }
+        (
+            low,
+            packed_index - self.packed_index(low, low + 1) + low + 1,
+        )
     }
 
-    /// Returns the matrix.
-    pub fn matrix(&self) -> &HashMap<(String, String), Option<f32>> {
-        &self.matrix
+    // Position of the pairing of two indices in either order, or None if they are equal or out of range.
+    fn checked_packed_index(&self, (i, j): (usize, usize)) -> Option<usize> {
+        let (i, j) = (i.min(j), i.max(j));
+        if i == j || j >= self.names.len() {
+            None
+        } else {
+            Some(self.packed_index(i, j))
+        }
+    }
+
+    // Position of the pairing of two names in either order.
+    fn named_packed_index(
+        &self,
+        (name1, name2): (&str, &str),
+    ) -> Result<usize, ImageSimilarityMatrixNoMatchError> {
+        match (self.index(name1), self.index(name2)) {
+            (Some(i), Some(j)) => {
+                self.checked_packed_index((i, j))
+                    .ok_or(ImageSimilarityMatrixNoMatchError(
+                        "Cannot compare an image to itself",
+                    ))
+            }
+            _ => Err(ImageSimilarityMatrixNoMatchError("Cannot find entry")),
+        }
+    }
+
+    /// Return the similarity of the images at the two given indices, in either order.
+    ///
+    /// Returns [Option::None] if the images could not be compared, or if the indices are equal or out of range.
+    pub fn get_by_index(&self, pairing: (usize, usize)) -> Option<f32> {
+        self.checked_packed_index(pairing)
+            .and_then(|packed_index| as_option(self.similarities[packed_index]))
+    }
+
+    /// Return the similarity of the two images with the given identities.
+    pub fn get_by_id(
+        &self,
+        (id1, id2): (&ImageId, &ImageId),
+    ) -> Result<Option<f32>, ImageSimilarityMatrixNoMatchError> {
+        match (self.index_of(id1), self.index_of(id2)) {
+            (Some(i), Some(j)) => self
+                .checked_packed_index((i, j))
+                .map(|packed_index| as_option(self.similarities[packed_index]))
+                .ok_or(ImageSimilarityMatrixNoMatchError(
+                    "Cannot compare an image to itself",
+                )),
+            _ => Err(ImageSimilarityMatrixNoMatchError("Cannot find entry")),
+        }
     }
 
     /// Return the similarity of the two given [ImgsimImage] names.
     pub fn get(
+        &self,
+        pairing: (&str, &str),
+    ) -> Result<Option<f32>, ImageSimilarityMatrixNoMatchError> {
+        self.named_packed_index(pairing)
+            .map(|packed_index| as_option(self.similarities[packed_index]))
+    }
+
+    /// Return the [Transform] which, applied to the second given [ImgsimImage], gave the best similarity to the first.
+    ///
+    /// This is always [Transform::Identity] unless the similarities were computed with `transform_invariant` enabled.
+    pub fn transform(
         &self,
         (name1, name2): (&str, &str),
-    ) -> Result<&Option<f32>, ImageSimilarityMatrixNoMatchError> {
-        if let Some(option_similarity) =
-            self.matrix.get(&(String::from(name1), String::from(name2)))
-        {
-            Ok(option_similarity)
-        } else if let Some(option_similarity) =
-            self.matrix.get(&(String::from(name2), String::from(name1)))
-        {
-            Ok(option_similarity)
+    ) -> Result<Transform, ImageSimilarityMatrixNoMatchError> {
+        let packed_index = self.named_packed_index((name1, name2))?;
+        let transform = *self
+            .transforms
+            .get(&packed_index)
+            .unwrap_or(&Transform::Identity);
+        // Transforms are stored as applied to the image with the higher index
+        if self.index(name1) < self.index(name2) {
+            Ok(transform)
         } else {
-            Err(ImageSimilarityMatrixNoMatchError("Cannot find entry"))
+            Ok(transform.inverse())
         }
     }
 
-    /// Print the image similarities to the terminal in a sorted, readable manner.
-    pub fn print(&self) {
-        let mut list: Vec<(&(String, String), &Option<f32>)> = self.matrix.iter().collect();
-        list.sort_by(|((_, _), sim_a), ((_, _), sim_b)| match (sim_a, sim_b) {
-            (Some(a), Some(b)) => b.partial_cmp(a).unwrap(),
-            (Some(_), None) => cmp::Ordering::Less,
-            (None, Some(_)) => cmp::Ordering::Greater,
-            _ => cmp::Ordering::Equal,
+    /// Returns an iterator over every pairing as `(i, j, similarity)` with `i < j`, in index order.
+    pub fn pairs(&self) -> impl Iterator<Item = (usize, usize, Option<f32>)> + '_ {
+        let len = self.names.len();
+        (0..len)
+            .flat_map(move |i| (i + 1..len).map(move |j| (i, j)))
+            .zip(self.similarities.iter())
+            .map(|((i, j), similarity)| (i, j, as_option(*similarity)))
+    }
+
+    /// Returns an iterator over every pairing as `(i, j, similarity)`, sorted from most to least similar. Pairings which
+    /// could not be compared come last.
+    ///
+    /// Only the packed index of each pairing is copied out and sorted, in parallel, rather than the pairings themselves.
+    pub fn sorted_pairs(&self) -> impl Iterator<Item = (usize, usize, Option<f32>)> + '_ {
+        let mut packed_indices: Vec<usize> = (0..self.similarities.len()).collect();
+        // Ties are broken by packed index, so the order is the same as that of a stable sort
+        packed_indices.par_sort_unstable_by_key(|packed_index| {
+            (
+                sort_key(as_option(self.similarities[*packed_index])),
+                *packed_index,
+            )
         });
+        packed_indices.into_iter().map(|packed_index| {
+            let (i, j) = self.unpacked_index(packed_index);
+            (i, j, as_option(self.similarities[packed_index]))
+        })
+    }
+
+    /// Returns the similarity of the image at the given index to each image, in index order. Its similarity to itself is [Option::None].
+    pub fn row(&self, index: usize) -> impl Iterator<Item = Option<f32>> + '_ {
+        (0..self.names.len()).map(move |other| self.get_by_index((index, other)))
+    }
 
+    /// Return the `k` images most similar to the image with the given name, from most to least similar, along with their similarities.
+    ///
+    /// Images which could not be compared to the given image are left out.
+    pub fn nearest(
+        &self,
+        name: &str,
+        k: usize,
+    ) -> Result<Vec<(&str, f32)>, ImageSimilarityMatrixNoMatchError> {
+        let index = self
+            .index(name)
+            .ok_or(ImageSimilarityMatrixNoMatchError("Cannot find entry"))?;
+        let mut neighbours: Vec<(&str, f32)> = self
+            .row(index)
+            .enumerate()
+            .filter_map(|(other, similarity)| similarity.map(|s| (self.names[other].as_str(), s)))
+            .collect();
+        neighbours.sort_by(|(_, sim_a), (_, sim_b)| sim_b.total_cmp(sim_a));
+        neighbours.truncate(k);
+        Ok(neighbours)
+    }
+
+    /// Print each image's `k` most similar images to the terminal.
+    pub fn print_top(&self, k: usize) {
         println!("\n======Most-Similar Images======");
-        list.iter().for_each(|((image_a, image_b), similarity)| {
+        self.names.iter().for_each(|name| {
+            println!("\"{}\":", name);
+            let neighbours = self.nearest(name, k).unwrap_or_default();
+            if neighbours.is_empty() {
+                println!("\tN/A");
+            }
+            neighbours.iter().for_each(|(other, similarity)| {
+                println!(
+                    "\t\"{}\": {}{}",
+                    other,
+                    similarity,
+                    match self.transform((name, other)) {
+                        Ok(Transform::Identity) | Err(_) => String::new(),
+                        Ok(transform) => format!(" ({:?})", transform),
+                    }
+                )
+            });
+        });
+        println!("===============================");
+    }
+
+    /// Print the image similarities to the terminal in a sorted, readable manner.
+    pub fn print(&self) {
+        println!("\n======Most-Similar Images======");
+        self.sorted_pairs().for_each(|(i, j, similarity)| {
             println!(
-                "[\"{}\" & \"{}\"]: {}",
-                image_a,
-                image_b,
+                "[\"{}\" & \"{}\"]: {}{}",
+                self.names[i],
+                self.names[j],
                 if let Some(s) = similarity {
                     s.to_string()
                 } else {
                     String::from("N/A")
+                },
+                if let Some(transform) = self.transforms.get(&self.packed_index(i, j)) {
+                    format!(" ({:?})", transform)
+                } else {
+                    String::new()
                 }
             )
         });
         println!("=====Least-Similar Images======");
     }
 
-    fn colour_sim(&mut self, images: &Vec<ImgsimImage>, imgsim_options: &ImgsimOptions) {
-        #[derive(Debug)]
-        struct ClusterInfo {
-            _cluster_id: usize,
-            size: usize,
-            average_rgba: (u8, u8, u8, u8),
+    /// Fill in every pairing `(i, j)` with `i < j` using the given function of the two images' indices.
+    #[cfg(test)]
+    pub(crate) fn fill(&mut self, similarity: impl Fn(usize, usize) -> Option<f32> + Sync) {
+        self.similarities = Pairings::Within(self.names.len()).fill(similarity);
+    }
+}
+
+// Orders similarities from highest to lowest, with pairings which could not be compared last.
+fn sort_key(similarity: Option<f32>) -> u32 {
+    match similarity {
+        Some(similarity) => {
+            // Flipping the sign bit of positive floats, and every bit of negative floats, keeps their order as integers
+            let bits = similarity.to_bits();
+            let ascending = if bits >> 31 == 1 {
+                !bits
+            } else {
+                bits | 1 << 31
+            };
+            !ascending
         }
-        // I. SETUP
-        // For each image, get a list of its clusters and their average RGBA values, sorted by size.
-        let clusters_info: Vec<(&str, Vec<ClusterInfo>)> = images
-            .par_iter()
-            .map(|image| {
-                let img_size =
-                    image.rgba_image().width() as usize * image.rgba_image().height() as usize;
-                let mut clusters_info: Vec<ClusterInfo> = Vec::with_capacity(img_size);
-                for cluster in image.pixel_clusters() {
-                    let size = cluster.1.len();
-                    if size
-                        > (img_size as f32 * imgsim_options.coloursim_cluster_cutoff()).round()
-                            as usize
-                    {
-                        let colour_sum =
-                            cluster.1.iter().fold((0, 0, 0, 0), |accumulator, coords| {
-                                let image::Rgba(data) =
-                                    *image.rgba_image().get_pixel(coords.0, coords.1);
-                                (
-                                    accumulator.0 + data[0] as u128,
-                                    accumulator.1 + data[1] as u128,
-                                    accumulator.2 + data[2] as u128,
-                                    accumulator.3 + data[3] as u128,
-                                )
-                            });
-                        clusters_info.push(ClusterInfo {
-                            _cluster_id: *cluster.0,
-                            size: cluster.1.len(),
-                            average_rgba: (
-                                (colour_sum.0 / size as u128) as u8,
-                                (colour_sum.1 / size as u128) as u8,
-                                (colour_sum.2 / size as u128) as u8,
-                                (colour_sum.3 / size as u128) as u8,
-                            ),
-                        });
-                    }
-                }
-                if clusters_info.is_empty() {
-                    eprintln!(
-                        "Warning: \"{}\" has no clusters above {}% of the image. Cannot compare.",
-                        image.name(),
-                        imgsim_options.coloursim_cluster_cutoff() * 100.0
-                    );
-                }
-                clusters_info.sort_unstable_by(|a, b| b.size.cmp(&a.size));
-                (image.name(), clusters_info)
-            })
-            .collect();
+        None => u32::MAX,
+    }
+}
 
-        // II. COMPARE
-        // Generate the similarity of each image pairing based on the average colours of their most dominant clusters
-        let mut new_matrix: HashMap<(String, String), Option<f32>> =
-            HashMap::with_capacity(self.matrix.len());
-        self.matrix
-            .iter_mut()
-            .for_each(|((image_a_name, image_b_name), _)| {
-                // Get refs to image_a's and image_b's clusters' info
-                let clusters_info_a = &clusters_info
+fn colour_sim(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    #[derive(Debug)]
+    struct ClusterInfo {
+        _cluster_id: usize,
+        size: usize,
+        average_rgba: (u8, u8, u8, u8),
+    }
+    // I. SETUP
+    // For each image, get a list of its clusters and their average RGBA values, sorted by size.
+    let extract = |image: &ImgsimImage| {
+        let img_size = image.rgba_image().width() as usize * image.rgba_image().height() as usize;
+        let mut clusters_info: Vec<ClusterInfo> = Vec::with_capacity(img_size);
+        for cluster in image.pixel_clusters() {
+            let size = cluster.1.len();
+            if size > (img_size as f32 * imgsim_options.coloursim_cluster_cutoff()).round() as usize
+            {
+                let colour_sum = cluster.1.iter().fold((0, 0, 0, 0), |accumulator, coords| {
+                    let image::Rgba(data) = *image.rgba_image().get_pixel(coords.0, coords.1);
+                    (
+                        accumulator.0 + data[0] as u128,
+                        accumulator.1 + data[1] as u128,
+                        accumulator.2 + data[2] as u128,
+                        accumulator.3 + data[3] as u128,
+                    )
+                });
+                clusters_info.push(ClusterInfo {
+                    _cluster_id: *cluster.0,
+                    size: cluster.1.len(),
+                    average_rgba: (
+                        (colour_sum.0 / size as u128) as u8,
+                        (colour_sum.1 / size as u128) as u8,
+                        (colour_sum.2 / size as u128) as u8,
+                        (colour_sum.3 / size as u128) as u8,
+                    ),
+                });
+            }
+        }
+        if clusters_info.is_empty() {
+            warn_cannot_compare(
+                image,
+                &format!(
+                    "has no clusters above {}% of the image",
+                    imgsim_options.coloursim_cluster_cutoff() * 100.0
+                ),
+            );
+        }
+        clusters_info.sort_unstable_by_key(|info| cmp::Reverse(info.size));
+        clusters_info
+    };
+
+    // II. COMPARE
+    // Generate the similarity of each image pairing based on the average colours of their most dominant clusters
+    compare_all(
+        images,
+        others,
+        pairings,
+        extract,
+        |clusters_info_a, clusters_info_b| {
+            if clusters_info_a.is_empty() || clusters_info_b.is_empty() {
+                return None;
+            }
+            // Calculate Similarity
+            let mut new_similarity = 0.0;
+            let mut i = 0;
+            while i < clusters_info_a.len() && i < clusters_info_b.len() {
+                new_similarity += avg_colour_sim(
+                    clusters_info_a[i].average_rgba.0,
+                    clusters_info_a[i].average_rgba.1,
+                    clusters_info_a[i].average_rgba.2,
+                    clusters_info_a[i].average_rgba.3,
+                    clusters_info_b[i].average_rgba.0,
+                    clusters_info_b[i].average_rgba.1,
+                    clusters_info_b[i].average_rgba.2,
+                    clusters_info_b[i].average_rgba.3,
+                );
+                i += 1;
+            }
+            Some(new_similarity / i as f32)
+        },
+    )
+}
+
+fn cluster_size(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    #[derive(Debug)]
+    struct ClusterInfo {
+        _cluster_id: usize,
+        size: usize,
+        proportional_start: (f32, f32),
+        proportional_width: f32,
+        proportional_height: f32,
+    }
+    // I. SETUP
+    // Build a lookup table of each image's cluster's proportional dimensions and locations.
+    let extract = |image: &ImgsimImage| {
+        let img_size = image.rgba_image().width() as usize * image.rgba_image().height() as usize;
+        let mut clusters_info: Vec<ClusterInfo> = Vec::with_capacity(img_size);
+        for cluster in image.pixel_clusters() {
+            let size = cluster.1.len();
+            if size
+                > (img_size as f32 * imgsim_options.clustersize_cluster_cutoff()).round() as usize
+            {
+                // Plot out a quadrilateral that contains the entire cluster
+                let left_x = cluster
+                    .1
                     .iter()
-                    .find(|(name, _)| *name == image_a_name)
+                    .min_by(|(a, _), (b, _)| a.cmp(b))
                     .unwrap()
-                    .1;
-                let clusters_info_b = &clusters_info
+                    .0;
+                let top_y = cluster
+                    .1
                     .iter()
-                    .find(|(name, _)| *name == image_b_name)
+                    .min_by(|(_, a), (_, b)| a.cmp(b))
                     .unwrap()
                     .1;
-                if !clusters_info_a.is_empty() && clusters_info_b.is_empty() {
-                    // Calculate Similarity
-                    let mut new_similarity = 0.0;
-                    let mut i = 0;
-                    while i < clusters_info_a.len() && i < clusters_info_b.len() {
-                        new_similarity += avg_colour_sim(
-                            clusters_info_a[i].average_rgba.0,
-                            clusters_info_a[i].average_rgba.1,
-                            clusters_info_a[i].average_rgba.2,
-                            clusters_info_a[i].average_rgba.3,
-                            clusters_info_b[i].average_rgba.0,
-                            clusters_info_b[i].average_rgba.1,
-                            clusters_info_b[i].average_rgba.2,
-                            clusters_info_b[i].average_rgba.3,
-                        );
-                        i += 1;
-                    }
-                    new_matrix.insert(
-                        (String::from(image_a_name), String::from(image_b_name)),
-                        Some(new_similarity / i as f32),
-                    );
-                } else {
-                    new_matrix.insert(
-                        (String::from(image_a_name), String::from(image_b_name)),
-                        None,
-                    );
-                }
-            });
-        self.matrix = new_matrix;
-    }
-
-    fn cluster_size(&mut self, images: &Vec<ImgsimImage>, imgsim_options: &ImgsimOptions) {
-        #[derive(Debug)]
-        struct ClusterInfo {
-            _cluster_id: usize,
-            size: usize,
-            proportional_start: (f32, f32),
-            proportional_width: f32,
-            proportional_height: f32,
-        }
-        // I. SETUP
-        // Build a lookup table of each image's cluster's proportional dimensions and locations.
-        let clusters_info: Vec<(&str, Vec<ClusterInfo>)> = images
-            .par_iter()
-            .map(|image| {
-                let img_size =
-                    image.rgba_image().width() as usize * image.rgba_image().height() as usize;
-                let mut clusters_info: Vec<ClusterInfo> = Vec::with_capacity(img_size);
-                for cluster in image.pixel_clusters() {
-                    let size = cluster.1.len();
-                    if size
-                        > (img_size as f32 * imgsim_options.clustersize_cluster_cutoff()).round()
-                            as usize
-                    {
-                        // Plot out a quadrilateral that contains the entire cluster
-                        let left_x = cluster
-                            .1
-                            .iter()
-                            .min_by(|(a, _), (b, _)| a.cmp(b))
-                            .unwrap()
-                            .0;
-                        let top_y = cluster
-                            .1
-                            .iter()
-                            .min_by(|(_, a), (_, b)| a.cmp(b))
-                            .unwrap()
-                            .1;
-                        let right_x = cluster
-                            .1
-                            .iter()
-                            .max_by(|(a, _), (b, _)| a.cmp(b))
-                            .unwrap()
-                            .0;
-                        let bottom_y = cluster
-                            .1
-                            .iter()
-                            .max_by(|(_, a), (_, b)| a.cmp(b))
-                            .unwrap()
-                            .1;
-
-                        let proportional_start = (
-                            left_x as f32 / image.rgba_image().width() as f32,
-                            top_y as f32 / image.rgba_image().height() as f32,
-                        );
-
-                        let proportional_width =
-                            (right_x - left_x) as f32 / image.rgba_image().width() as f32;
-
-                        let proportional_height =
-                            (bottom_y - top_y) as f32 / image.rgba_image().height() as f32;
-
-                        clusters_info.push(ClusterInfo {
-                            _cluster_id: *cluster.0,
-                            size: cluster.1.len(),
-                            proportional_start,
-                            proportional_width,
-                            proportional_height,
-                        });
-                    }
-                }
-                if clusters_info.is_empty() {
-                    eprintln!(
-                        "Warning: \"{}\" has no clusters above {}% of the image. Cannot compare.",
-                        image.name(),
-                        imgsim_options.clustersize_cluster_cutoff() * 100.0
-                    );
-                }
-                clusters_info.sort_unstable_by(|a, b| b.size.cmp(&a.size));
-                (image.name(), clusters_info)
-            })
-            .collect();
-
-        // II. COMPARE
-        // Generate the similarity of each image pairing based on the size and location of their most dominant clusters
-        let mut new_matrix: HashMap<(String, String), Option<f32>> =
-            HashMap::with_capacity(self.matrix.len());
-        self.matrix
-            .iter_mut()
-            .for_each(|((image_a_name, image_b_name), _)| {
-                // Get refs to image_a's and image_b's clusters' info
-                let clusters_info_a = &clusters_info
+                let right_x = cluster
+                    .1
                     .iter()
-                    .find(|(name, _)| *name == image_a_name)
+                    .max_by(|(a, _), (b, _)| a.cmp(b))
                     .unwrap()
-                    .1;
-                let clusters_info_b = &clusters_info
+                    .0;
+                let bottom_y = cluster
+                    .1
                     .iter()
-                    .find(|(name, _)| *name == image_b_name)
+                    .max_by(|(_, a), (_, b)| a.cmp(b))
                     .unwrap()
                     .1;
-                if !clusters_info_a.is_empty() && !clusters_info_b.is_empty() {
-                    let mut new_similarity = 0.0;
-                    let mut count = 0;
-                    let mut i = 0;
-
-                    while i < clusters_info_a.len() && i < clusters_info_b.len() {
-                        new_similarity += proportional_similarity_coords(
-                            &clusters_info_a[i].proportional_start,
-                            &clusters_info_b[i].proportional_start,
-                        );
-                        new_similarity += proportional_similarity(
-                            clusters_info_a[i].proportional_width,
-                            clusters_info_b[i].proportional_width,
-                        );
-                        new_similarity += proportional_similarity(
-                            clusters_info_a[i].proportional_height,
-                            clusters_info_b[i].proportional_height,
-                        );
-                        i += 1;
-                        count += 3;
-                    }
 
-                    new_matrix.insert(
-                        (String::from(image_a_name), String::from(image_b_name)),
-                        Some(new_similarity / count as f32),
-                    );
-                } else {
-                    new_matrix.insert(
-                        (String::from(image_a_name), String::from(image_b_name)),
-                        None,
-                    );
-                }
+                let proportional_start = (
+                    left_x as f32 / image.rgba_image().width() as f32,
+                    top_y as f32 / image.rgba_image().height() as f32,
+                );
+
+                let proportional_width =
+                    (right_x - left_x) as f32 / image.rgba_image().width() as f32;
+
+                let proportional_height =
+                    (bottom_y - top_y) as f32 / image.rgba_image().height() as f32;
+
+                clusters_info.push(ClusterInfo {
+                    _cluster_id: *cluster.0,
+                    size: cluster.1.len(),
+                    proportional_start,
+                    proportional_width,
+                    proportional_height,
+                });
+            }
+        }
+        if clusters_info.is_empty() {
+            warn_cannot_compare(
+                image,
+                &format!(
+                    "has no clusters above {}% of the image",
+                    imgsim_options.clustersize_cluster_cutoff() * 100.0
+                ),
+            );
+        }
+        clusters_info.sort_unstable_by_key(|info| cmp::Reverse(info.size));
+        clusters_info
+    };
+
+    // II. COMPARE
+    // Generate the similarity of each image pairing based on the size and location of their most dominant clusters
+    compare_all(
+        images,
+        others,
+        pairings,
+        extract,
+        |clusters_info_a, clusters_info_b| {
+            if clusters_info_a.is_empty() || clusters_info_b.is_empty() {
+                return None;
+            }
+            let mut new_similarity = 0.0;
+            let mut count = 0;
+            let mut i = 0;
+
+            while i < clusters_info_a.len() && i < clusters_info_b.len() {
+                new_similarity += proportional_similarity_coords(
+                    &clusters_info_a[i].proportional_start,
+                    &clusters_info_b[i].proportional_start,
+                );
+                new_similarity += proportional_similarity(
+                    clusters_info_a[i].proportional_width,
+                    clusters_info_b[i].proportional_width,
+                );
+                new_similarity += proportional_similarity(
+                    clusters_info_a[i].proportional_height,
+                    clusters_info_b[i].proportional_height,
+                );
+                i += 1;
+                count += 3;
+            }
+
+            Some(new_similarity / count as f32)
+        },
+    )
+}
+
+fn cluster_shape(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    // I. SETUP
+    // For each image, get the shape descriptors of its clusters above the cutoff, sorted by size.
+    let extract = |image: &ImgsimImage| {
+        let img_size = image.rgba_image().width() as usize * image.rgba_image().height() as usize;
+        let mut shapes: Vec<ClusterShape> = image
+            .cluster_shapes()
+            .values()
+            .filter(|shape| {
+                shape.size()
+                    > (img_size as f32 * imgsim_options.clustershape_cluster_cutoff()).round()
+                        as usize
+            })
+            .cloned()
+            .collect();
+        if shapes.is_empty() {
+            warn_cannot_compare(
+                image,
+                &format!(
+                    "has no clusters above {}% of the image",
+                    imgsim_options.clustershape_cluster_cutoff() * 100.0
+                ),
+            );
+        }
+        shapes.sort_unstable_by_key(|shape| cmp::Reverse(shape.size()));
+        shapes
+    };
+
+    // II. COMPARE
+    // Generate the similarity of each image pairing based on the shapes of their most dominant clusters
+    compare_all(images, others, pairings, extract, |shapes_a, shapes_b| {
+        if shapes_a.is_empty() || shapes_b.is_empty() {
+            return None;
+        }
+        let mut new_similarity = 0.0;
+        let mut count = 0;
+        shapes_a
+            .iter()
+            .zip(shapes_b.iter())
+            .for_each(|(shape_a, shape_b)| {
+                new_similarity += proportional_similarity(shape_a.extent(), shape_b.extent());
+                new_similarity +=
+                    proportional_similarity_coords(&shape_a.centroid(), &shape_b.centroid());
+                new_similarity += hu_similarity(shape_a.hu_moments(), shape_b.hu_moments());
+                new_similarity +=
+                    proportional_similarity(shape_a.eccentricity(), shape_b.eccentricity());
+                new_similarity += orientation_similarity(shape_a, shape_b);
+                count += 5;
             });
-        self.matrix = new_matrix;
+        Some(new_similarity / count as f32)
+    })
+}
+
+fn region_graph(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    // I. SETUP
+    // Build each image's region adjacency graph out of its clusters above the cutoff.
+    let extract = |image: &ImgsimImage| {
+        let graph = RegionGraph::new(image, imgsim_options.regiongraph_cluster_cutoff());
+        if graph.nodes().is_empty() {
+            warn_cannot_compare(
+                image,
+                &format!(
+                    "has no clusters above {}% of the image",
+                    imgsim_options.regiongraph_cluster_cutoff() * 100.0
+                ),
+            );
+        }
+        graph
+    };
+
+    // II. COMPARE
+    // Generate the similarity of each image pairing by matching their graphs' regions and borders
+    compare_all(images, others, pairings, extract, |graph_a, graph_b| {
+        graph_a.similarity(graph_b, imgsim_options.regiongraph_iterations())
+    })
+}
+
+fn texture(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    // I. SETUP
+    // Build each image's grid of uniform LBP histograms.
+    let grid_size = imgsim_options.texture_grid_size();
+    let extract = |image: &ImgsimImage| lbp_histograms(image.rgba_image(), grid_size);
+
+    // II. COMPARE
+    // Generate the similarity of each image pairing based on the mean chi-square distance between their grid cells' histograms
+    compare_all(
+        images,
+        others,
+        pairings,
+        extract,
+        |histogram_a, histogram_b| Some(texture_similarity(histogram_a, histogram_b, grid_size)),
+    )
+}
+
+fn difference_hash(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+) -> Vec<f32> {
+    // I. SETUP
+    // Hash each image's grayscale thumbnail.
+    let extract = |image: &ImgsimImage| dhash(image.rgba_image());
+
+    // II. COMPARE
+    // Generate the similarity of each image pairing based on the number of bits their hashes share
+    compare_all(images, others, pairings, extract, |hash_a, hash_b| {
+        Some(hash_similarity(*hash_a, *hash_b))
+    })
+}
+
+/// The similarity of two grids of LBP histograms, based on the mean chi-square distance between their grid cells.
+pub(crate) fn texture_similarity(histogram_a: &[f32], histogram_b: &[f32], grid_size: u32) -> f32 {
+    let cells = (grid_size.max(1) * grid_size.max(1)) as f32;
+    1.0 - 2.0 * (chi_square(histogram_a, histogram_b) / cells)
+}
+
+fn edge_orientation(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    // I. SETUP
+    // Build each image's grid of gradient orientation histograms.
+    let grid_size = imgsim_options.edge_grid_size();
+    let orientation_bins = imgsim_options.orientation_bins().max(1);
+    let extract = |image: &ImgsimImage| {
+        orientation_histograms(image.rgba_image(), grid_size, orientation_bins)
+    };
+
+    // II. COMPARE
+    // Generate the similarity of each image pairing based on the mean similarity of their grid cells' histograms
+    compare_all(
+        images,
+        others,
+        pairings,
+        extract,
+        |histogram_a, histogram_b| {
+            Some(edge_orientation_similarity(
+                histogram_a,
+                histogram_b,
+                orientation_bins,
+            ))
+        },
+    )
+}
+
+/// The similarity of two grids of orientation histograms, based on the mean similarity of their grid cells.
+pub(crate) fn edge_orientation_similarity(
+    histogram_a: &[f32],
+    histogram_b: &[f32],
+    orientation_bins: usize,
+) -> f32 {
+    let cell_similarities: Vec<f32> = histogram_a
+        .chunks(orientation_bins)
+        .zip(histogram_b.chunks(orientation_bins))
+        .map(|(cell_a, cell_b)| cell_similarity(cell_a, cell_b))
+        .collect();
+    2.0 * (cell_similarities.iter().sum::<f32>() / cell_similarities.len() as f32) - 1.0
+}
+
+fn keypoints(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    // I. SETUP
+    // Detect each image's strongest corners and describe them.
+    let extract = |image: &ImgsimImage| {
+        let keypoints = detect_keypoints(
+            image.rgba_image(),
+            imgsim_options.fast_threshold(),
+            imgsim_options.max_keypoints(),
+            imgsim_options.pyramid_levels(),
+        );
+        if keypoints.is_empty() {
+            warn_cannot_compare(image, "has no keypoints");
+        } else if imgsim_options.debug() {
+            println!(
+                "\"{}\" ({:?}): Detected {} keypoints.",
+                image.name(),
+                image.transform(),
+                keypoints.len()
+            );
+        }
+        keypoints
+    };
+
+    // II. COMPARE
+    // Generate the similarity of each image pairing based on the proportion of keypoints that match
+    compare_all(
+        images,
+        others,
+        pairings,
+        extract,
+        |keypoints_a, keypoints_b| {
+            let fewest_keypoints = keypoints_a.len().min(keypoints_b.len());
+            if fewest_keypoints == 0 {
+                return None;
+            }
+            let matches = match_keypoints(keypoints_a, keypoints_b, imgsim_options.match_ratio());
+            let matched = if imgsim_options.ransac() {
+                ransac_inliers(
+                    keypoints_a,
+                    keypoints_b,
+                    &matches,
+                    imgsim_options.ransac_iterations(),
+                    imgsim_options.ransac_threshold(),
+                )
+            } else {
+                matches.len()
+            };
+            Some(2.0 * (matched as f32 / fewest_keypoints as f32).min(1.0) - 1.0)
+        },
+    )
+}
+
+// The packed matrix marks pairings which could not be compared with NaN.
+pub(crate) fn as_option(similarity: f32) -> Option<f32> {
+    if similarity.is_nan() {
+        None
+    } else {
+        Some(similarity)
+    }
+}
+
+// Warn that an image cannot be compared. Transformed copies are skipped, since the original image has already been warned about.
+fn warn_cannot_compare(image: &ImgsimImage, reason: &str) {
+    if let Transform::Identity = image.transform() {
+        eprintln!("Warning: \"{}\" {}. Cannot compare.", image.name(), reason);
     }
 }
 
 #[allow(clippy::too_many_arguments)]
-fn avg_colour_sim(r_a: u8, g_a: u8, b_a: u8, a_a: u8, r_b: u8, g_b: u8, b_b: u8, a_b: u8) -> f32 {
+pub(crate) fn avg_colour_sim(
+    r_a: u8,
+    g_a: u8,
+    b_a: u8,
+    a_a: u8,
+    r_b: u8,
+    g_b: u8,
+    b_b: u8,
+    a_b: u8,
+) -> f32 {
     let max_dist: f32 = 510.0;
     fn delta_sq(a: u8, b: u8) -> f32 {
         (a as f32 - b as f32).powf(2.0)
@@ -361,17 +1080,42 @@ fn avg_colour_sim(r_a: u8, g_a: u8, b_a: u8, a_a: u8, r_b: u8, g_b: u8, b_b: u8,
     (((delta_r_sq + delta_g_sq + delta_b_sq + delta_a_sq).sqrt() / max_dist) - 0.5) * -2.0
 }
 
-fn proportional_similarity_coords((x_a, y_a): &(f32, f32), (x_b, y_b): &(f32, f32)) -> f32 {
+pub(crate) fn proportional_similarity_coords(
+    (x_a, y_a): &(f32, f32),
+    (x_b, y_b): &(f32, f32),
+) -> f32 {
     let abs_delta_x = (x_a - x_b).abs();
     let abs_delta_y = (y_a - y_b).abs();
     -2.0 * (((abs_delta_x + abs_delta_y).sqrt() / (2.0_f32).sqrt()) - 0.5)
 }
 
-fn proportional_similarity(val_a: f32, val_b: f32) -> f32 {
+pub(crate) fn proportional_similarity(val_a: f32, val_b: f32) -> f32 {
     // val_a & val_b all already range from 0 to 1; no need to normalise.
     -2.0 * ((val_a - val_b).abs().sqrt() - 0.5)
 }
 
+// Hu moments span many orders of magnitude, so they are compared on a log scale.
+// The sign is dropped, so mirrored shapes compare equal.
+fn hu_similarity(hu_a: &[f32; 7], hu_b: &[f32; 7]) -> f32 {
+    fn log_hu(moment: f32) -> f32 {
+        moment.abs().max(1e-10).log10()
+    }
+    let mean_delta = hu_a
+        .iter()
+        .zip(hu_b.iter())
+        .map(|(a, b)| (log_hu(*a) - log_hu(*b)).abs())
+        .sum::<f32>()
+        / hu_a.len() as f32;
+    2.0 * (-mean_delta).exp() - 1.0
+}
+
+// Orientation matters less the rounder the clusters are, so the difference in angle is scaled down by eccentricity.
+fn orientation_similarity(shape_a: &ClusterShape, shape_b: &ClusterShape) -> f32 {
+    let delta = (shape_a.orientation() - shape_b.orientation()).rem_euclid(PI);
+    let delta = delta.min(PI - delta);
+    1.0 - 2.0 * (delta / FRAC_PI_2) * shape_a.eccentricity().min(shape_b.eccentricity())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -442,6 +1186,36 @@ mod tests {
         )
     }
 
+    #[test]
+    fn hu_sim_same() {
+        let hu = [0.2, 0.01, 0.003, 0.0001, 0.0, -0.00002, 0.0];
+        assert_eq!(hu_similarity(&hu, &hu), 1.0);
+    }
+
+    #[test]
+    fn hu_sim_mirrored() {
+        let hu = [0.2, 0.01, 0.003, 0.0001, 0.0, -0.00002, 0.00005];
+        let mirrored = [0.2, 0.01, 0.003, 0.0001, 0.0, -0.00002, -0.00005];
+        assert_eq!(hu_similarity(&hu, &mirrored), 1.0);
+    }
+
+    #[test]
+    fn hu_sim_far() {
+        let hu_a = [0.1; 7];
+        let hu_b = [0.001; 7];
+        assert_eq!(hu_similarity(&hu_a, &hu_b), 2.0 * (-2.0_f32).exp() - 1.0);
+    }
+
+    #[test]
+    fn orient_sim() {
+        let horizontal =
+            ClusterShape::new(0, &(0..20).map(|x| (x, 0)).collect::<Vec<_>>(), (20, 20)).unwrap();
+        let vertical =
+            ClusterShape::new(0, &(0..20).map(|y| (0, y)).collect::<Vec<_>>(), (20, 20)).unwrap();
+        assert_eq!(orientation_similarity(&horizontal, &horizontal), 1.0);
+        assert!(orientation_similarity(&horizontal, &vertical) < -0.99);
+    }
+
     #[test]
     fn acs_same() {
         assert_eq!(avg_colour_sim(123, 64, 42, 255, 123, 64, 42, 255), 1.0);
@@ -458,4 +1232,113 @@ mod tests {
         let ans: f32 = (((2826.0_f32).sqrt() / 510.0) - 0.5) * (-2.0);
         assert_eq!(avg_colour_sim(45, 129, 226, 255, 69, 174, 241, 255), ans);
     }
+
+    fn abc_matrix() -> ImageSimilarityMatrix {
+        let mut matrix = ImageSimilarityMatrix::from_names(
+            ["a", "b", "c", "d"]
+                .iter()
+                .map(|n| String::from(*n))
+                .collect(),
+        );
+        matrix.fill(|i, j| {
+            if j == 3 {
+                None
+            } else {
+                Some((i * 10 + j) as f32 / 100.0)
+            }
+        });
+        matrix
+    }
+
+    #[test]
+    fn packed_index_order() {
+        let matrix = abc_matrix();
+        let pairs: Vec<(usize, usize)> = matrix.pairs().map(|(i, j, _)| (i, j)).collect();
+        assert_eq!(pairs, vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+        pairs.iter().enumerate().for_each(|(packed_index, (i, j))| {
+            assert_eq!(matrix.packed_index(*i, *j), packed_index);
+        });
+    }
+
+    #[test]
+    fn matrix_get_symmetric() {
+        let matrix = abc_matrix();
+        assert_eq!(matrix.get(("a", "c")).unwrap(), Some(0.02));
+        assert_eq!(matrix.get(("c", "a")).unwrap(), Some(0.02));
+        assert_eq!(matrix.get_by_index((2, 1)), Some(0.12));
+        assert_eq!(matrix.get(("b", "d")).unwrap(), None);
+        assert!(matrix.get(("a", "a")).is_err());
+        assert!(matrix.get(("a", "z")).is_err());
+    }
+
+    #[test]
+    fn matrix_row() {
+        let matrix = abc_matrix();
+        let row: Vec<Option<f32>> = matrix.row(1).collect();
+        assert_eq!(row, vec![Some(0.01), None, Some(0.12), None]);
+    }
+
+    #[test]
+    fn unpacked_index_inverts_packed_index() {
+        let matrix = ImageSimilarityMatrix::from_names((0..7).map(|i| i.to_string()).collect());
+        (0..7)
+            .flat_map(|i| (i + 1..7).map(move |j| (i, j)))
+            .for_each(|(i, j)| {
+                assert_eq!(matrix.unpacked_index(matrix.packed_index(i, j)), (i, j))
+            });
+    }
+
+    #[test]
+    fn matrix_sorted_pairs() {
+        let matrix = abc_matrix();
+        let expected = vec![
+            (1, 2, Some(0.12)),
+            (0, 2, Some(0.02)),
+            (0, 1, Some(0.01)),
+            (0, 3, None),
+            (1, 3, None),
+            (2, 3, None),
+        ];
+        assert_eq!(matrix.sorted_pairs().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn matrix_keep_best() {
+        let mut matrix = abc_matrix();
+        let flipped = Pairings::Within(4).fill(|i, _| if i == 0 { Some(0.5) } else { None });
+        keep_best(
+            &mut matrix.similarities,
+            &mut matrix.transforms,
+            flipped,
+            Transform::Rotate90,
+        );
+        assert_eq!(matrix.get(("a", "d")).unwrap(), Some(0.5));
+        assert_eq!(matrix.get(("b", "c")).unwrap(), Some(0.12));
+        assert_eq!(matrix.transform(("a", "b")).unwrap(), Transform::Rotate90);
+        assert_eq!(matrix.transform(("b", "a")).unwrap(), Transform::Rotate270);
+        assert_eq!(matrix.transform(("b", "c")).unwrap(), Transform::Identity);
+    }
+
+    #[test]
+    fn matrix_nearest() {
+        let matrix = abc_matrix();
+        assert_eq!(
+            matrix.nearest("b", 5).unwrap(),
+            vec![("c", 0.12), ("a", 0.01)]
+        );
+        assert_eq!(matrix.nearest("b", 1).unwrap(), vec![("c", 0.12)]);
+        assert_eq!(matrix.nearest("d", 3).unwrap(), vec![]);
+        assert!(matrix.nearest("z", 3).is_err());
+    }
+
+    #[test]
+    fn matrix_get_by_id() {
+        let matrix = abc_matrix();
+        let (a, b) = (&matrix.ids()[0], &matrix.ids()[1]);
+        assert_eq!(matrix.get_by_id((a, b)).unwrap(), Some(0.01));
+        assert!(matrix.get_by_id((a, a)).is_err());
+        assert!(matrix
+            .get_by_id((a, &ImageId::new(1, std::path::PathBuf::from("z"))))
+            .is_err());
+    }
 }
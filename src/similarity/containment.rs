@@ -0,0 +1,355 @@
+#![warn(missing_docs)]
+//! Detection of images which are crops or scaled sub-regions of other images.
+
+use rayon::prelude::*;
+use std::cmp;
+
+use crate::{
+    display_names,
+    keypoints::{detect_keypoints, match_keypoints, project, ransac_homography, Keypoint},
+    ImageId, ImgsimImage, ImgsimOptions,
+};
+
+// A homography can always be fit to four matches, so fewer inliers than this are treated as chance.
+const MIN_INLIERS: usize = 8;
+
+/// One image found within another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Containment {
+    contained: ImageId,
+    container: ImageId,
+    contained_name: String,
+    container_name: String,
+    score: f32,
+    rect: (u32, u32, u32, u32),
+}
+impl Containment {
+    /// Returns the [ImageId] of the smaller image, which was found within the larger image.
+    pub fn contained(&self) -> &ImageId {
+        &self.contained
+    }
+
+    /// Returns the [ImageId] of the larger image.
+    pub fn container(&self) -> &ImageId {
+        &self.container
+    }
+
+    /// Returns the display name of the smaller image, unique among the compared images.
+    pub fn contained_name(&self) -> &str {
+        &self.contained_name
+    }
+
+    /// Returns the display name of the larger image, unique among the compared images.
+    pub fn container_name(&self) -> &str {
+        &self.container_name
+    }
+
+    /// Returns the proportion of the smaller image's keypoints which were found within the larger image, from 0 to 1.
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
+    /// Returns the region of the larger image matching the smaller image as `(left_x, top_y, width, height)`, in the larger image's original pixel coordinates.
+    pub fn rect(&self) -> (u32, u32, u32, u32) {
+        self.rect
+    }
+}
+
+// An image's keypoints, along with its working size and its original size.
+struct ContainmentFeatures {
+    keypoints: Vec<Keypoint>,
+    dimensions: (u32, u32),
+    original_dimensions: (u32, u32),
+}
+impl ContainmentFeatures {
+    fn new(
+        rgba_image: &image::RgbaImage,
+        original_dimensions: (u32, u32),
+        imgsim_options: &ImgsimOptions,
+    ) -> ContainmentFeatures {
+        ContainmentFeatures {
+            keypoints: detect_keypoints(
+                rgba_image,
+                imgsim_options.fast_threshold(),
+                imgsim_options.max_keypoints(),
+                imgsim_options.pyramid_levels(),
+            ),
+            dimensions: rgba_image.dimensions(),
+            original_dimensions,
+        }
+    }
+
+    fn original_area(&self) -> u64 {
+        self.original_dimensions.0 as u64 * self.original_dimensions.1 as u64
+    }
+}
+
+// A containment of the image at one index within the image at another, before the images are named.
+#[derive(Debug, Clone, PartialEq)]
+struct Found {
+    small: usize,
+    large: usize,
+    score: f32,
+    rect: (u32, u32, u32, u32),
+}
+
+/// Find every image which is (approximately) a crop or scaled sub-region of another image.
+///
+/// Containment is checked from each smaller image into each larger image by matching their keypoints and fitting a
+/// single homography between them. Only containments with a score of at least `min_score` are returned, sorted by score.
+pub fn get_containments(
+    images: &[ImgsimImage],
+    imgsim_options: &ImgsimOptions,
+) -> Vec<Containment> {
+    let features: Vec<ContainmentFeatures> = images
+        .par_iter()
+        .map(|image| {
+            ContainmentFeatures::new(
+                image.rgba_image(),
+                // Images may have been shrunk on load, so the rectangle is scaled back up to the original's size
+                image::image_dimensions(image.path()).unwrap_or(image.rgba_image().dimensions()),
+                imgsim_options,
+            )
+        })
+        .collect();
+
+    let found: Vec<Found> = pairings(&features)
+        .par_iter()
+        .filter_map(|(small, large)| {
+            let (score, rect) = containment(&features[*small], &features[*large], imgsim_options)?;
+            Some(Found {
+                small: *small,
+                large: *large,
+                score,
+                rect,
+            })
+        })
+        .filter(|found| found.score >= imgsim_options.containment_min_score())
+        .collect();
+
+    let ids: Vec<ImageId> = images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| image.id().with_index(index))
+        .collect();
+    let names: Vec<String> = images
+        .iter()
+        .map(|image| String::from(image.name()))
+        .collect();
+    let names = display_names(&ids, &names);
+    keep_best(found)
+        .into_iter()
+        .map(|found| Containment {
+            contained: ids[found.small].clone(),
+            container: ids[found.large].clone(),
+            contained_name: names[found.small].clone(),
+            container_name: names[found.large].clone(),
+            score: found.score,
+            rect: found.rect,
+        })
+        .collect()
+}
+
+// Each pairing of a smaller image with a larger image, by index. Images of equal size are paired both ways.
+fn pairings(features: &[ContainmentFeatures]) -> Vec<(usize, usize)> {
+    let mut pairings: Vec<(usize, usize)> = Vec::new();
+    for i in 0..features.len() {
+        for j in i + 1..features.len() {
+            match features[i]
+                .original_area()
+                .cmp(&features[j].original_area())
+            {
+                cmp::Ordering::Less => pairings.push((i, j)),
+                cmp::Ordering::Greater => pairings.push((j, i)),
+                cmp::Ordering::Equal => {
+                    pairings.push((i, j));
+                    pairings.push((j, i));
+                }
+            }
+        }
+    }
+    pairings
+}
+
+// Sort the containments by score. Images of equal size may have been found within each other; keep the better of the two.
+fn keep_best(mut found: Vec<Found>) -> Vec<Found> {
+    found.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<Found> = Vec::with_capacity(found.len());
+    found.into_iter().for_each(|found| {
+        if !kept
+            .iter()
+            .any(|other| other.small == found.large && other.large == found.small)
+        {
+            kept.push(found);
+        }
+    });
+    kept
+}
+
+// Check whether the small image is contained within the large image, returning the score and the matching rectangle.
+fn containment(
+    small: &ContainmentFeatures,
+    large: &ContainmentFeatures,
+    imgsim_options: &ImgsimOptions,
+) -> Option<(f32, (u32, u32, u32, u32))> {
+    if small.keypoints.is_empty() {
+        return None;
+    }
+    let matches = match_keypoints(
+        &small.keypoints,
+        &large.keypoints,
+        imgsim_options.match_ratio(),
+    );
+    let (homography, inliers) = ransac_homography(
+        &small.keypoints,
+        &large.keypoints,
+        &matches,
+        imgsim_options.ransac_iterations(),
+        imgsim_options.ransac_threshold(),
+    )?;
+    if inliers < MIN_INLIERS {
+        return None;
+    }
+
+    // Project the small image's corners into the large image
+    let (small_width, small_height) = small.dimensions;
+    let (large_width, large_height) = large.dimensions;
+    let mut corners = Vec::with_capacity(4);
+    for corner in [
+        (0.0, 0.0),
+        (small_width as f32, 0.0),
+        (small_width as f32, small_height as f32),
+        (0.0, small_height as f32),
+    ] {
+        corners.push(project(&homography, corner)?);
+    }
+    let left_x = corners.iter().map(|(x, _)| *x).fold(f32::MAX, f32::min);
+    let top_y = corners.iter().map(|(_, y)| *y).fold(f32::MAX, f32::min);
+    let right_x = corners.iter().map(|(x, _)| *x).fold(f32::MIN, f32::max);
+    let bottom_y = corners.iter().map(|(_, y)| *y).fold(f32::MIN, f32::max);
+    let projected_area = (right_x - left_x) * (bottom_y - top_y);
+
+    // Most of the projected region must land inside the large image
+    let clamped = (
+        left_x.clamp(0.0, large_width as f32),
+        top_y.clamp(0.0, large_height as f32),
+        right_x.clamp(0.0, large_width as f32),
+        bottom_y.clamp(0.0, large_height as f32),
+    );
+    let clamped_area = (clamped.2 - clamped.0) * (clamped.3 - clamped.1);
+    if projected_area <= 0.0 || clamped_area < 0.5 * projected_area {
+        return None;
+    }
+
+    let scale_x = large.original_dimensions.0 as f32 / large_width as f32;
+    let scale_y = large.original_dimensions.1 as f32 / large_height as f32;
+    Some((
+        (inliers as f32 / small.keypoints.len() as f32).min(1.0),
+        (
+            (clamped.0 * scale_x).round() as u32,
+            (clamped.1 * scale_y).round() as u32,
+            ((clamped.2 - clamped.0) * scale_x).round() as u32,
+            ((clamped.3 - clamped.1) * scale_y).round() as u32,
+        ),
+    ))
+}
+
+/// Print the found containments to the terminal, separately from the similarity list.
+pub fn print_containments(containments: &[Containment]) {
+    println!("\n=======Contained Images=======");
+    if containments.is_empty() {
+        println!("None found.");
+    }
+    containments.iter().for_each(|containment| {
+        let (x, y, width, height) = containment.rect();
+        println!(
+            "[\"{}\" within \"{}\"]: {} at ({}, {}) {}x{}",
+            containment.contained_name(),
+            containment.container_name(),
+            containment.score(),
+            x,
+            y,
+            width,
+            height
+        )
+    });
+    println!("==============================");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{imageops, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    // Blocks of pseudo-random grey, which have plenty of distinct corners.
+    fn blocks(width: u32, height: u32, seed: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let block = (x / 6).wrapping_mul(7919) ^ (y / 6).wrapping_mul(104729) ^ seed;
+            let grey = (block.wrapping_mul(2654435761) >> 24) as u8;
+            Rgba([grey, grey, grey, 255])
+        })
+    }
+
+    fn features(rgba_image: &RgbaImage, original_dimensions: (u32, u32)) -> ContainmentFeatures {
+        ContainmentFeatures::new(rgba_image, original_dimensions, &ImgsimOptions::default())
+    }
+
+    fn found(small: usize, large: usize, score: f32) -> Found {
+        Found {
+            small,
+            large,
+            score,
+            rect: (0, 0, 1, 1),
+        }
+    }
+
+    #[test]
+    fn crop_found() {
+        let large = blocks(240, 200, 1);
+        let small = imageops::crop_imm(&large, 60, 48, 120, 96).to_image();
+        // The large image was shrunk to half its original size on load
+        let (score, (x, y, width, height)) = containment(
+            &features(&small, (120, 96)),
+            &features(&large, (480, 400)),
+            &ImgsimOptions::default(),
+        )
+        .unwrap();
+        assert!(score > 0.25);
+        assert!((x as i32 - 120).abs() <= 4 && (y as i32 - 96).abs() <= 4);
+        assert!((width as i32 - 240).abs() <= 4 && (height as i32 - 192).abs() <= 4);
+    }
+
+    #[test]
+    fn unrelated_not_found() {
+        let large = blocks(240, 200, 1);
+        let small = blocks(120, 96, 2);
+        assert_eq!(
+            containment(
+                &features(&small, (120, 96)),
+                &features(&large, (240, 200)),
+                &ImgsimOptions::default(),
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn equal_areas_paired_both_ways() {
+        let image = blocks(40, 30, 1);
+        let features = [
+            features(&image, (40, 30)),
+            features(&image, (30, 40)),
+            features(&image, (80, 60)),
+        ];
+        assert_eq!(pairings(&features), vec![(0, 1), (1, 0), (0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn equal_areas_best_kept() {
+        assert_eq!(
+            keep_best(vec![found(0, 1, 0.6), found(2, 0, 0.7), found(1, 0, 0.8)]),
+            vec![found(1, 0, 0.8), found(2, 0, 0.7)]
+        );
+    }
+}
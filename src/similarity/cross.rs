@@ -0,0 +1,531 @@
+#![warn(missing_docs)]
+//! Similarities between two separate lists of images, such as a query image and a directory of images.
+
+use std::{cmp, collections::HashMap, slice};
+
+use crate::{
+    display_names,
+    similarity::algs::{as_option, best_similarities, Pairings},
+    ImageId, ImageSimilarityMatrixNoMatchError, ImgsimImage, ImgsimOptions, Transform,
+};
+
+/// Get the similarity of a single query image to each of the given images.
+///
+/// Only the query's pairings are compared, so this is much faster than [crate::get_similarities] over every image.
+/// If the query was also loaded among the images, it is left out of them rather than matching itself.
+pub fn get_query_similarities(
+    query: &ImgsimImage,
+    images: &[ImgsimImage],
+    imgsim_options: &ImgsimOptions,
+) -> CrossSimilarityMatrix {
+    let matrix = get_cross_similarities(slice::from_ref(query), images, imgsim_options);
+    let candidates: Vec<usize> = (0..images.len())
+        .filter(|index| images[*index].id().path() != query.id().path())
+        .collect();
+    if candidates.len() == images.len() {
+        matrix
+    } else {
+        let names: Vec<String> = images
+            .iter()
+            .map(|image| String::from(image.name()))
+            .collect();
+        matrix.retain_columns(&candidates, &names)
+    }
+}
+
+/// Get the similarity of every image in `rows` to every image in `columns`, without comparing images within either list.
+///
+/// Useful for checking a new batch of images against an existing library.
+pub fn get_cross_similarities(
+    rows: &[ImgsimImage],
+    columns: &[ImgsimImage],
+    imgsim_options: &ImgsimOptions,
+) -> CrossSimilarityMatrix {
+    let (similarities, transforms) = best_similarities(
+        rows,
+        columns,
+        &Pairings::Between(rows.len(), columns.len()),
+        imgsim_options,
+    );
+    CrossSimilarityMatrix::new(rows, columns, similarities, transforms)
+}
+
+// Each display name's index in the list.
+fn name_indices(names: &[String]) -> HashMap<String, usize> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| (name.clone(), index))
+        .collect()
+}
+
+// Each image's identity, indexed by its place in the list, along with a display name no other image in the list shares.
+fn identities(images: &[ImgsimImage]) -> (Vec<ImageId>, Vec<String>) {
+    let ids: Vec<ImageId> = images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| image.id().with_index(index))
+        .collect();
+    let names: Vec<String> = images
+        .iter()
+        .map(|image| String::from(image.name()))
+        .collect();
+    let names = display_names(&ids, &names);
+    (ids, names)
+}
+
+/// A matrix of the similarities between each image of one list (the rows) and each image of another (the columns).
+///
+/// Unlike [crate::ImageSimilarityMatrix], images are never compared to others in the same list.
+#[derive(Debug)]
+pub struct CrossSimilarityMatrix {
+    row_ids: Vec<ImageId>,
+    column_ids: Vec<ImageId>,
+    row_names: Vec<String>,
+    column_names: Vec<String>,
+    row_indices: HashMap<String, usize>,
+    column_indices: HashMap<String, usize>,
+    // Stored row by row. NaN marks a pairing which could not be compared.
+    similarities: Vec<f32>,
+    transforms: HashMap<usize, Transform>,
+}
+impl CrossSimilarityMatrix {
+    /// Builds a [CrossSimilarityMatrix] out of its row and column images, its similarities stored row by row, and the
+    /// transforms which produced them.
+    pub(crate) fn new(
+        rows: &[ImgsimImage],
+        columns: &[ImgsimImage],
+        similarities: Vec<f32>,
+        transforms: HashMap<usize, Transform>,
+    ) -> CrossSimilarityMatrix {
+        let (row_ids, row_names) = identities(rows);
+        let (column_ids, column_names) = identities(columns);
+        CrossSimilarityMatrix::from_ids(
+            (row_ids, row_names),
+            (column_ids, column_names),
+            similarities,
+            transforms,
+        )
+    }
+
+    // Builds a matrix out of each row and column image's identity and unique display name.
+    fn from_ids(
+        (row_ids, row_names): (Vec<ImageId>, Vec<String>),
+        (column_ids, column_names): (Vec<ImageId>, Vec<String>),
+        similarities: Vec<f32>,
+        transforms: HashMap<usize, Transform>,
+    ) -> CrossSimilarityMatrix {
+        let row_indices = name_indices(&row_names);
+        let column_indices = name_indices(&column_names);
+        CrossSimilarityMatrix {
+            row_ids,
+            column_ids,
+            row_names,
+            column_names,
+            row_indices,
+            column_indices,
+            similarities,
+            transforms,
+        }
+    }
+
+    // Keeps only the given columns, in the given order, with `names` being every column image's undisambiguated name.
+    // Display names are worked out again, since a dropped column may have been the only reason for a longer one.
+    fn retain_columns(self, columns: &[usize], names: &[String]) -> CrossSimilarityMatrix {
+        let old_columns = self.column_ids.len();
+        let column_ids: Vec<ImageId> = columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| self.column_ids[*column].with_index(index))
+            .collect();
+        let kept_names: Vec<String> = columns
+            .iter()
+            .map(|column| names[*column].clone())
+            .collect();
+        let column_names = display_names(&column_ids, &kept_names);
+        let similarities = (0..self.row_ids.len())
+            .flat_map(|row| columns.iter().map(move |column| row * old_columns + column))
+            .map(|flat_index| self.similarities[flat_index])
+            .collect();
+        let new_columns: HashMap<usize, usize> = columns
+            .iter()
+            .enumerate()
+            .map(|(new_column, column)| (*column, new_column))
+            .collect();
+        let transforms = self
+            .transforms
+            .iter()
+            .filter_map(|(flat_index, transform)| {
+                let (row, old_column) = (flat_index / old_columns, flat_index % old_columns);
+                new_columns
+                    .get(&old_column)
+                    .map(|column| (row * columns.len() + column, *transform))
+            })
+            .collect();
+        CrossSimilarityMatrix::from_ids(
+            (self.row_ids, self.row_names),
+            (column_ids, column_names),
+            similarities,
+            transforms,
+        )
+    }
+
+    /// Returns the identities of the row images, in order.
+    pub fn row_ids(&self) -> &[ImageId] {
+        &self.row_ids
+    }
+
+    /// Returns the identities of the column images, in order.
+    pub fn column_ids(&self) -> &[ImageId] {
+        &self.column_ids
+    }
+
+    /// Returns the names of the row images, in order.
+    pub fn row_names(&self) -> &[String] {
+        &self.row_names
+    }
+
+    /// Returns the names of the column images, in order.
+    pub fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    /// Returns the index of the row image with the given name.
+    pub fn row_index(&self, name: &str) -> Option<usize> {
+        self.row_indices.get(name).copied()
+    }
+
+    /// Returns the index of the column image with the given name.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.column_indices.get(name).copied()
+    }
+
+    fn flat_index(&self, (row, column): (usize, usize)) -> Option<usize> {
+        if row < self.row_names.len() && column < self.column_names.len() {
+            Some(row * self.column_names.len() + column)
+        } else {
+            None
+        }
+    }
+
+    fn named_flat_index(
+        &self,
+        (row_name, column_name): (&str, &str),
+    ) -> Result<usize, ImageSimilarityMatrixNoMatchError> {
+        match (self.row_index(row_name), self.column_index(column_name)) {
+            (Some(row), Some(column)) => Ok(row * self.column_names.len() + column),
+            _ => Err(ImageSimilarityMatrixNoMatchError("Cannot find entry")),
+        }
+    }
+
+    /// Return the similarity of the row image and column image at the given indices.
+    ///
+    /// Returns [Option::None] if the images could not be compared, or if either index is out of range.
+    pub fn get_by_index(&self, pairing: (usize, usize)) -> Option<f32> {
+        self.flat_index(pairing)
+            .and_then(|flat_index| as_option(self.similarities[flat_index]))
+    }
+
+    /// Return the similarity of the row image and column image with the given identities.
+    pub fn get_by_id(
+        &self,
+        (row_id, column_id): (&ImageId, &ImageId),
+    ) -> Result<Option<f32>, ImageSimilarityMatrixNoMatchError> {
+        if self.row_ids.get(row_id.index()) == Some(row_id)
+            && self.column_ids.get(column_id.index()) == Some(column_id)
+        {
+            Ok(self.get_by_index((row_id.index(), column_id.index())))
+        } else {
+            Err(ImageSimilarityMatrixNoMatchError("Cannot find entry"))
+        }
+    }
+
+    /// Return the similarity of the given row image name and column image name.
+    pub fn get(
+        &self,
+        pairing: (&str, &str),
+    ) -> Result<Option<f32>, ImageSimilarityMatrixNoMatchError> {
+        self.named_flat_index(pairing)
+            .map(|flat_index| as_option(self.similarities[flat_index]))
+    }
+
+    /// Return the [Transform] which, applied to the column image, gave the best similarity to the row image.
+    ///
+    /// This is always [Transform::Identity] unless the similarities were computed with `transform_invariant` enabled.
+    pub fn transform(
+        &self,
+        pairing: (&str, &str),
+    ) -> Result<Transform, ImageSimilarityMatrixNoMatchError> {
+        let flat_index = self.named_flat_index(pairing)?;
+        Ok(self.transform_at(flat_index))
+    }
+
+    fn transform_at(&self, flat_index: usize) -> Transform {
+        *self
+            .transforms
+            .get(&flat_index)
+            .unwrap_or(&Transform::Identity)
+    }
+
+    /// Returns the similarity of the row image at the given index to each column image, in index order.
+    pub fn row(&self, row: usize) -> impl Iterator<Item = Option<f32>> + '_ {
+        (0..self.column_names.len()).map(move |column| self.get_by_index((row, column)))
+    }
+
+    /// Return the `k` column images most similar to the row image with the given name, from most to least similar,
+    /// along with their similarities.
+    ///
+    /// Images which could not be compared to the given image are left out.
+    pub fn nearest(
+        &self,
+        row_name: &str,
+        k: usize,
+    ) -> Result<Vec<(&str, f32)>, ImageSimilarityMatrixNoMatchError> {
+        let row = self
+            .row_index(row_name)
+            .ok_or(ImageSimilarityMatrixNoMatchError("Cannot find entry"))?;
+        Ok(self
+            .nearest_columns(row, k)
+            .into_iter()
+            .map(|(column, similarity)| (self.column_names[column].as_str(), similarity))
+            .collect())
+    }
+
+    // The `k` columns most similar to the given row, as `(column, similarity)`, from most to least similar.
+    fn nearest_columns(&self, row: usize, k: usize) -> Vec<(usize, f32)> {
+        let mut neighbours: Vec<(usize, f32)> = self
+            .row(row)
+            .enumerate()
+            .filter_map(|(column, similarity)| similarity.map(|s| (column, s)))
+            .collect();
+        neighbours.sort_by(|(_, sim_a), (_, sim_b)| sim_b.total_cmp(sim_a));
+        neighbours.truncate(k);
+        neighbours
+    }
+
+    /// Return the most similar column image to each row image, along with its similarity, in row order.
+    ///
+    /// Row images which could not be compared to any column image have no best match.
+    pub fn best_matches(&self) -> Vec<(&str, Option<(&str, f32)>)> {
+        self.row_names
+            .iter()
+            .enumerate()
+            .map(|(row, name)| {
+                (
+                    name.as_str(),
+                    self.nearest_columns(row, 1)
+                        .first()
+                        .map(|(column, similarity)| {
+                            (self.column_names[*column].as_str(), *similarity)
+                        }),
+                )
+            })
+            .collect()
+    }
+
+    // Every pairing as `(row, column, similarity)`, sorted from most to least similar. Pairings which could not be compared come last.
+    fn sorted_pairs(&self) -> Vec<(usize, usize, Option<f32>)> {
+        let columns = self.column_names.len();
+        let mut pairs: Vec<(usize, usize, Option<f32>)> = self
+            .similarities
+            .iter()
+            .enumerate()
+            .map(|(flat_index, similarity)| {
+                (
+                    flat_index / columns,
+                    flat_index % columns,
+                    as_option(*similarity),
+                )
+            })
+            .collect();
+        pairs.sort_by(|(_, _, sim_a), (_, _, sim_b)| match (sim_a, sim_b) {
+            (Some(a), Some(b)) => b.total_cmp(a),
+            (Some(_), None) => cmp::Ordering::Less,
+            (None, Some(_)) => cmp::Ordering::Greater,
+            _ => cmp::Ordering::Equal,
+        });
+        pairs
+    }
+
+    /// Print each row image's `k` most similar column images to the terminal.
+    pub fn print_top(&self, k: usize) {
+        println!("\n======Most-Similar Images======");
+        self.row_names.iter().enumerate().for_each(|(row, name)| {
+            println!("\"{}\":", name);
+            let neighbours = self.nearest_columns(row, k);
+            if neighbours.is_empty() {
+                println!("\tN/A");
+            }
+            neighbours.iter().for_each(|(column, similarity)| {
+                println!(
+                    "\t\"{}\": {}{}",
+                    self.column_names[*column],
+                    similarity,
+                    match self.transform_at(row * self.column_names.len() + column) {
+                        Transform::Identity => String::new(),
+                        transform => format!(" ({:?})", transform),
+                    }
+                )
+            });
+        });
+        println!("===============================");
+    }
+
+    /// Print each row image's most similar column image to the terminal.
+    pub fn print_best(&self) {
+        println!("\n=========Best Matches=========");
+        self.row_names.iter().enumerate().for_each(|(row, name)| {
+            match self.nearest_columns(row, 1).first() {
+                Some((column, similarity)) => println!(
+                    "\"{}\" -> \"{}\": {}{}",
+                    name,
+                    self.column_names[*column],
+                    similarity,
+                    match self.transform_at(row * self.column_names.len() + column) {
+                        Transform::Identity => String::new(),
+                        transform => format!(" ({:?})", transform),
+                    }
+                ),
+                None => println!("\"{}\" -> N/A", name),
+            }
+        });
+        println!("==============================");
+    }
+
+    /// Print every pairing's similarity to the terminal in a sorted, readable manner.
+    pub fn print(&self) {
+        println!("\n======Most-Similar Images======");
+        self.sorted_pairs()
+            .iter()
+            .for_each(|(row, column, similarity)| {
+                println!(
+                    "[\"{}\" & \"{}\"]: {}{}",
+                    self.row_names[*row],
+                    self.column_names[*column],
+                    if let Some(s) = similarity {
+                        s.to_string()
+                    } else {
+                        String::from("N/A")
+                    },
+                    if let Some(transform) = self
+                        .transforms
+                        .get(&(row * self.column_names.len() + column))
+                    {
+                        format!(" ({:?})", transform)
+                    } else {
+                        String::new()
+                    }
+                )
+            });
+        println!("=====Least-Similar Images======");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    // Rows q and r against columns a, b and c. r cannot be compared to b.
+    fn qr_matrix() -> CrossSimilarityMatrix {
+        CrossSimilarityMatrix::from_ids(
+            (
+                vec![
+                    ImageId::new(0, PathBuf::from("q")),
+                    ImageId::new(1, PathBuf::from("r")),
+                ],
+                vec![String::from("q"), String::from("r")],
+            ),
+            (
+                ["a", "b", "c"]
+                    .iter()
+                    .enumerate()
+                    .map(|(index, name)| ImageId::new(index, PathBuf::from(name)))
+                    .collect(),
+                vec![String::from("a"), String::from("b"), String::from("c")],
+            ),
+            vec![0.1, 0.9, 0.5, -0.2, f32::NAN, 0.3],
+            HashMap::from([(1, Transform::FlipHorizontal)]),
+        )
+    }
+
+    #[test]
+    fn pairings_between_order() {
+        assert_eq!(
+            Pairings::Between(2, 3).fill(|i, j| Some((i * 10 + j) as f32)),
+            vec![0.0, 1.0, 2.0, 10.0, 11.0, 12.0]
+        );
+    }
+
+    #[test]
+    fn cross_get() {
+        let matrix = qr_matrix();
+        assert_eq!(matrix.get(("q", "b")).unwrap(), Some(0.9));
+        assert_eq!(matrix.get(("r", "b")).unwrap(), None);
+        assert_eq!(matrix.get_by_index((1, 2)), Some(0.3));
+        assert_eq!(matrix.get_by_index((2, 0)), None);
+        assert!(matrix.get(("a", "q")).is_err());
+        assert_eq!(matrix.column_index("c"), Some(2));
+        assert_eq!(matrix.row_index("a"), None);
+    }
+
+    #[test]
+    fn cross_transform() {
+        let matrix = qr_matrix();
+        assert_eq!(
+            matrix.transform(("q", "b")).unwrap(),
+            Transform::FlipHorizontal
+        );
+        assert_eq!(matrix.transform(("r", "b")).unwrap(), Transform::Identity);
+    }
+
+    #[test]
+    fn cross_nearest() {
+        let matrix = qr_matrix();
+        assert_eq!(
+            matrix.nearest("q", 2).unwrap(),
+            vec![("b", 0.9), ("c", 0.5)]
+        );
+        assert_eq!(
+            matrix.nearest("r", 5).unwrap(),
+            vec![("c", 0.3), ("a", -0.2)]
+        );
+    }
+
+    #[test]
+    fn cross_best_matches() {
+        let mut matrix = qr_matrix();
+        matrix.similarities[3] = f32::NAN;
+        matrix.similarities[5] = f32::NAN;
+        assert_eq!(
+            matrix.best_matches(),
+            vec![("q", Some(("b", 0.9))), ("r", None)]
+        );
+    }
+
+    #[test]
+    fn cross_get_by_id() {
+        let matrix = qr_matrix();
+        let (q, c) = (&matrix.row_ids()[0], &matrix.column_ids()[2]);
+        assert_eq!(matrix.get_by_id((q, c)).unwrap(), Some(0.5));
+        assert!(matrix
+            .get_by_id((q, &ImageId::new(2, PathBuf::from("z"))))
+            .is_err());
+    }
+
+    #[test]
+    fn cross_retain_columns() {
+        let names = vec![String::from("a"), String::from("b"), String::from("c")];
+        let matrix = qr_matrix().retain_columns(&[1, 2], &names);
+        assert_eq!(matrix.column_names(), &names[1..]);
+        assert_eq!(matrix.column_ids()[1], ImageId::new(1, PathBuf::from("c")));
+        assert_eq!(matrix.get(("q", "c")).unwrap(), Some(0.5));
+        assert_eq!(matrix.get(("r", "b")).unwrap(), None);
+        assert_eq!(
+            matrix.transform(("q", "b")).unwrap(),
+            Transform::FlipHorizontal
+        );
+        assert!(matrix.get(("q", "a")).is_err());
+    }
+}
@@ -0,0 +1,429 @@
+#![warn(missing_docs)]
+//! Hierarchical clustering of images by their similarities: <https://en.wikipedia.org/wiki/Hierarchical_clustering>
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::{
+    similarity::grouping::matrix_similarity, DescribeAlg, ImageGroup, ImageSimilarityMatrix,
+    MatchEnumAsStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, EnumIter)]
+/// Denotes how the distance between two groups of images is measured when building a [Dendrogram].
+pub enum Linkage {
+    #[serde(alias = "single")]
+    /// The distance between the two groups' closest images.
+    Single,
+    #[serde(alias = "complete")]
+    /// The distance between the two groups' furthest images.
+    Complete,
+    #[serde(alias = "average")]
+    /// The mean distance between every pairing of the two groups' images.
+    Average,
+}
+impl MatchEnumAsStr for Linkage {
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            Linkage::Single => &["single"],
+            Linkage::Complete => &["complete"],
+            Linkage::Average => &["average"],
+        }
+    }
+}
+impl DescribeAlg for Linkage {
+    fn description(&self) -> &'static str {
+        match self {
+            Linkage::Single => "The distance between the two groups' closest images.",
+            Linkage::Complete => "The distance between the two groups' furthest images.",
+            Linkage::Average => {
+                "The mean distance between every pairing of the two groups' images."
+            }
+        }
+    }
+}
+
+/// A single merge of two nodes within a [Dendrogram].
+///
+/// Nodes `0..n` are the images themselves, in index order. Node `n + k` is the group formed by the `k`th merge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Merge {
+    left: usize,
+    right: usize,
+    height: f32,
+    size: usize,
+}
+impl Merge {
+    /// Returns the first of the two merged nodes.
+    pub fn left(&self) -> usize {
+        self.left
+    }
+
+    /// Returns the second of the two merged nodes.
+    pub fn right(&self) -> usize {
+        self.right
+    }
+
+    /// Returns the distance between the two merged nodes, from 0 (identical) to 1 (opposite).
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    /// Returns the number of images in the merged group.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// A tree of images, built by repeatedly merging the two closest groups of images until only one group remains.
+///
+/// The distance between two images is `(1 - similarity) / 2`, so it ranges from 0 to 1. Images which could not be
+/// compared are treated as being 1 apart.
+#[derive(Debug, Clone)]
+pub struct Dendrogram {
+    names: Vec<String>,
+    merges: Vec<Merge>,
+}
+impl Dendrogram {
+    /// Build a dendrogram out of every image in the matrix using the given linkage.
+    pub fn new(matrix: &ImageSimilarityMatrix, linkage: &Linkage) -> Dendrogram {
+        let len = matrix.len();
+        let mut distances: Vec<f32> = matrix
+            .pairs()
+            .map(|(_, _, similarity)| similarity.map_or(1.0, |s| ((1.0 - s) / 2.0).clamp(0.0, 1.0)))
+            .collect();
+        let packed_index = |i: usize, j: usize| -> usize {
+            let (i, j) = (i.min(j), i.max(j));
+            i * (2 * len - i - 1) / 2 + (j - i - 1)
+        };
+
+        // Nearest-neighbour chain: follow each cluster to its nearest neighbour until two clusters are each other's
+        // nearest neighbour, then merge them. Each merge is stored in the slot of one of the two clusters.
+        let mut active: Vec<bool> = vec![true; len];
+        let mut sizes: Vec<usize> = vec![1; len];
+        let mut chain: Vec<usize> = Vec::with_capacity(len);
+        let mut slot_merges: Vec<(usize, usize, f32)> = Vec::with_capacity(len.saturating_sub(1));
+        while slot_merges.len() + 1 < len {
+            if chain.is_empty() {
+                chain.push(active.iter().position(|a| *a).unwrap());
+            }
+            let (a, b) = loop {
+                let current = *chain.last().unwrap();
+                let previous = chain.len().checked_sub(2).map(|i| chain[i]);
+                // Prefer the previous cluster on ties, so the chain cannot loop
+                let mut nearest = previous;
+                let mut nearest_distance =
+                    previous.map_or(f32::INFINITY, |p| distances[packed_index(current, p)]);
+                for (other, is_active) in active.iter().enumerate() {
+                    if *is_active && other != current {
+                        let distance = distances[packed_index(current, other)];
+                        if distance < nearest_distance {
+                            nearest = Some(other);
+                            nearest_distance = distance;
+                        }
+                    }
+                }
+                let nearest = nearest.unwrap();
+                if Some(nearest) == previous {
+                    break (current, nearest);
+                }
+                chain.push(nearest);
+            };
+            chain.truncate(chain.len() - 2);
+
+            let height = distances[packed_index(a, b)];
+            slot_merges.push((a, b, height));
+            // Lance-Williams update of the merged cluster's distance to every other cluster
+            active[a] = false;
+            for other in 0..len {
+                if active[other] && other != b {
+                    let distance_a = distances[packed_index(a, other)];
+                    let distance_b = distances[packed_index(b, other)];
+                    distances[packed_index(b, other)] = match linkage {
+                        Linkage::Single => distance_a.min(distance_b),
+                        Linkage::Complete => distance_a.max(distance_b),
+                        Linkage::Average => {
+                            (sizes[a] as f32 * distance_a + sizes[b] as f32 * distance_b)
+                                / (sizes[a] + sizes[b]) as f32
+                        }
+                    };
+                }
+            }
+            sizes[b] += sizes[a];
+        }
+
+        // Sort the merges by height and relabel them as nodes
+        slot_merges.sort_by(|(_, _, height_a), (_, _, height_b)| height_a.total_cmp(height_b));
+        let mut parents: Vec<usize> = (0..len).collect();
+        let mut root_nodes: Vec<usize> = (0..len).collect();
+        let mut root_sizes: Vec<usize> = vec![1; len];
+        let merges = slot_merges
+            .iter()
+            .enumerate()
+            .map(|(k, (a, b, height))| {
+                let (root_a, root_b) = (find(&mut parents, *a), find(&mut parents, *b));
+                let (node_a, node_b) = (root_nodes[root_a], root_nodes[root_b]);
+                let size = root_sizes[root_a] + root_sizes[root_b];
+                parents[root_a] = root_b;
+                root_nodes[root_b] = len + k;
+                root_sizes[root_b] = size;
+                Merge {
+                    left: node_a.min(node_b),
+                    right: node_a.max(node_b),
+                    height: *height,
+                    size,
+                }
+            })
+            .collect();
+
+        Dendrogram {
+            names: matrix.names().to_vec(),
+            merges,
+        }
+    }
+
+    /// Returns the names of the images, in index order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns every merge, from lowest to highest.
+    pub fn merges(&self) -> &[Merge] {
+        &self.merges
+    }
+
+    /// Split the images into the groups formed by every merge at or below the given height.
+    ///
+    /// Each group is a list of image indices. Groups are sorted from largest to smallest.
+    pub fn cut_height(&self, height: f32) -> Vec<Vec<usize>> {
+        let merge_count = self
+            .merges
+            .iter()
+            .take_while(|merge| merge.height <= height)
+            .count();
+        self.groups_after(merge_count)
+    }
+
+    /// Split the images into the given number of groups by undoing the highest merges.
+    ///
+    /// Each group is a list of image indices. Groups are sorted from largest to smallest.
+    pub fn cut_count(&self, count: usize) -> Vec<Vec<usize>> {
+        self.groups_after(self.names.len().saturating_sub(count.max(1)))
+    }
+
+    // The groups formed by the first `merge_count` merges.
+    fn groups_after(&self, merge_count: usize) -> Vec<Vec<usize>> {
+        let len = self.names.len();
+        let mut parents: Vec<usize> = (0..len + merge_count).collect();
+        self.merges
+            .iter()
+            .take(merge_count)
+            .enumerate()
+            .for_each(|(k, merge)| {
+                parents[merge.left] = len + k;
+                parents[merge.right] = len + k;
+            });
+        let mut groups: Vec<Vec<usize>> = vec![Vec::new(); len + merge_count];
+        for index in 0..len {
+            let root = find(&mut parents, index);
+            groups[root].push(index);
+        }
+        groups.retain(|group| !group.is_empty());
+        groups.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+        groups
+    }
+
+    /// Export the dendrogram in Newick format: <https://en.wikipedia.org/wiki/Newick_format>
+    ///
+    /// Branch lengths are the difference in height between each node and its parent.
+    pub fn to_newick(&self) -> String {
+        let mut newick = String::new();
+        if let Some(root) = self.root() {
+            self.write_newick(&mut newick, root, None);
+        }
+        newick.push(';');
+        newick
+    }
+
+    fn write_newick(&self, newick: &mut String, node: usize, parent_height: Option<f32>) {
+        match self.children(node) {
+            Some((left, right)) => {
+                newick.push('(');
+                self.write_newick(newick, left, Some(self.height(node)));
+                newick.push(',');
+                self.write_newick(newick, right, Some(self.height(node)));
+                newick.push(')');
+            }
+            None => newick.push_str(&newick_label(&self.names[node])),
+        }
+        if let Some(parent_height) = parent_height {
+            newick.push_str(&format!(":{}", parent_height - self.height(node)));
+        }
+    }
+
+    /// Export the dendrogram as an indented text tree. Each group is labelled with the height at which it was merged.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(root) = self.root() {
+            self.write_text(&mut text, root, "", "");
+        }
+        text
+    }
+
+    fn write_text(&self, text: &mut String, node: usize, first_prefix: &str, prefix: &str) {
+        match self.children(node) {
+            Some((left, right)) => {
+                text.push_str(&format!("{}[{:.3}]\n", first_prefix, self.height(node)));
+                self.write_text(
+                    text,
+                    left,
+                    &format!("{}├── ", prefix),
+                    &format!("{}│   ", prefix),
+                );
+                self.write_text(
+                    text,
+                    right,
+                    &format!("{}└── ", prefix),
+                    &format!("{}    ", prefix),
+                );
+            }
+            None => text.push_str(&format!("{}{}\n", first_prefix, self.names[node])),
+        }
+    }
+
+    fn root(&self) -> Option<usize> {
+        match self.names.len() {
+            0 => None,
+            len => Some(len + self.merges.len() - 1),
+        }
+    }
+
+    fn children(&self, node: usize) -> Option<(usize, usize)> {
+        node.checked_sub(self.names.len())
+            .map(|k| (self.merges[k].left, self.merges[k].right))
+    }
+
+    fn height(&self, node: usize) -> f32 {
+        node.checked_sub(self.names.len())
+            .map_or(0.0, |k| self.merges[k].height)
+    }
+}
+
+fn find(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut current = index;
+    while parents[current] != root {
+        let next = parents[current];
+        parents[current] = root;
+        current = next;
+    }
+    root
+}
+
+// Labels containing Newick punctuation or whitespace are quoted, with any quotes doubled.
+fn newick_label(name: &str) -> String {
+    if name
+        .chars()
+        .any(|c| c.is_whitespace() || "()[]':;,".contains(c))
+    {
+        format!("'{}'", name.replace('\'', "''"))
+    } else {
+        String::from(name)
+    }
+}
+
+/// Turn a cut of the dendrogram into [ImageGroup]s so they can be printed like any other groups.
+pub fn cut_to_groups(matrix: &ImageSimilarityMatrix, cut: &[Vec<usize>]) -> Vec<ImageGroup> {
+    cut.iter()
+        .map(|group| {
+            ImageGroup::new(matrix.ids(), matrix.names(), group, |i, j| {
+                matrix_similarity(matrix, i, j)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // a & b are near-identical, c is close to them, d is far from everything.
+    fn abcd_matrix() -> ImageSimilarityMatrix {
+        let mut matrix = ImageSimilarityMatrix::from_names(
+            ["a", "b", "c", "d"]
+                .iter()
+                .map(|n| String::from(*n))
+                .collect(),
+        );
+        matrix.fill(|i, j| match (i, j) {
+            (0, 1) => Some(0.9),
+            (0, 2) => Some(0.5),
+            (1, 2) => Some(0.3),
+            _ => Some(-0.6),
+        });
+        matrix
+    }
+
+    fn round(val: f32) -> f32 {
+        (val * 1000.0).round() / 1000.0
+    }
+
+    #[test]
+    fn single_merges() {
+        let dendrogram = Dendrogram::new(&abcd_matrix(), &Linkage::Single);
+        let merges: Vec<(usize, usize, f32, usize)> = dendrogram
+            .merges()
+            .iter()
+            .map(|m| (m.left(), m.right(), round(m.height()), m.size()))
+            .collect();
+        assert_eq!(
+            merges,
+            vec![(0, 1, 0.05, 2), (2, 4, 0.25, 3), (3, 5, 0.8, 4)]
+        );
+    }
+
+    #[test]
+    fn complete_and_average_heights() {
+        let complete = Dendrogram::new(&abcd_matrix(), &Linkage::Complete);
+        assert_eq!(round(complete.merges()[1].height()), 0.35);
+        let average = Dendrogram::new(&abcd_matrix(), &Linkage::Average);
+        assert_eq!(round(average.merges()[1].height()), 0.3);
+    }
+
+    #[test]
+    fn cuts() {
+        let dendrogram = Dendrogram::new(&abcd_matrix(), &Linkage::Single);
+        assert_eq!(
+            dendrogram.cut_height(0.1),
+            vec![vec![0, 1], vec![2], vec![3]]
+        );
+        assert_eq!(dendrogram.cut_count(2), vec![vec![0, 1, 2], vec![3]]);
+        assert_eq!(dendrogram.cut_count(1), vec![vec![0, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn newick() {
+        let dendrogram = Dendrogram::new(&abcd_matrix(), &Linkage::Single);
+        let newick = dendrogram.to_newick();
+        assert!(newick.starts_with("(d:0.8,(c:0.25,(a:"));
+        assert!(newick.ends_with(");"));
+    }
+
+    #[test]
+    fn newick_quoted() {
+        assert_eq!(newick_label("it's a (copy).png"), "'it''s a (copy).png'");
+        assert_eq!(newick_label("plain.png"), "plain.png");
+    }
+
+    #[test]
+    fn text_leaves() {
+        let dendrogram = Dendrogram::new(&abcd_matrix(), &Linkage::Single);
+        let text = dendrogram.to_text();
+        assert_eq!(text.lines().count(), 7);
+        assert!(text.starts_with("[0.800]\n├── d\n└── [0.250]\n"));
+    }
+}
@@ -0,0 +1,62 @@
+#![warn(missing_docs)]
+//! Difference hashes (dHash): <https://www.hackerfactor.com/blog/index.php?/archives/529-Kind-of-Like-That.html>
+
+use image::{imageops, RgbaImage};
+
+use crate::metric_index::hamming_distance;
+
+/// The number of bits in a difference hash.
+pub const HASH_BITS: u32 = 64;
+
+/// Return the 64-bit difference hash of the image.
+///
+/// The image is shrunk to a 9 * 8 grayscale thumbnail, and each bit, row by row, is set if a pixel of the thumbnail is
+/// brighter than the pixel to its right. Rescaled, recompressed and slightly recoloured copies share most of their bits.
+pub fn dhash(rgba_image: &RgbaImage) -> u64 {
+    let thumbnail = imageops::resize(
+        &imageops::grayscale(rgba_image),
+        9,
+        8,
+        imageops::FilterType::Triangle,
+    );
+    (0..8)
+        .flat_map(|y| (0..8).map(move |x| (x, y)))
+        .fold(0, |hash, (x, y)| {
+            (hash << 1) | (thumbnail.get_pixel(x, y)[0] > thumbnail.get_pixel(x + 1, y)[0]) as u64
+        })
+}
+
+/// The similarity of two difference hashes, from 1 if they are equal to -1 if every bit differs.
+pub fn hash_similarity(hash_a: u64, hash_b: u64) -> f32 {
+    1.0 - 2.0 * hamming_distance(&hash_a, &hash_b) as f32 / HASH_BITS as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use pretty_assertions::assert_eq;
+
+    // Brightness falls from left to right, or rises if reversed.
+    fn gradient(reversed: bool, offset: u8) -> RgbaImage {
+        RgbaImage::from_fn(90, 80, |x, _| {
+            let grey = if reversed { x as u8 } else { 200 - x as u8 };
+            Rgba([grey + offset, grey + offset, grey + offset, 255])
+        })
+    }
+
+    #[test]
+    fn gradient_hashes() {
+        assert_eq!(dhash(&gradient(false, 0)), u64::MAX);
+        assert_eq!(dhash(&gradient(true, 0)), 0);
+        assert_eq!(hash_similarity(u64::MAX, 0), -1.0);
+    }
+
+    #[test]
+    fn brightened_copy_matches() {
+        let hash = dhash(&gradient(false, 0));
+        assert_eq!(hash_similarity(hash, dhash(&gradient(false, 40))), 1.0);
+        let resized = imageops::resize(&gradient(false, 0), 45, 40, imageops::FilterType::Nearest);
+        assert!(hash_similarity(hash, dhash(&resized)) > 0.9);
+    }
+}
@@ -0,0 +1,131 @@
+#![warn(missing_docs)]
+//! Histogram of oriented gradients (HOG) style shape descriptors: <https://en.wikipedia.org/wiki/Histogram_of_oriented_gradients>
+
+use image::{imageops, GrayImage, RgbaImage};
+use std::f32::consts::PI;
+
+// Sobel gradient (gx, gy) at an interior pixel of a grayscale image.
+fn sobel(gray_image: &GrayImage, x: u32, y: u32) -> (f32, f32) {
+    let p = |dx: i64, dy: i64| -> f32 {
+        gray_image.get_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32)[0] as f32
+    };
+    let gx = (p(1, -1) + 2.0 * p(1, 0) + p(1, 1)) - (p(-1, -1) + 2.0 * p(-1, 0) + p(-1, 1));
+    let gy = (p(-1, 1) + 2.0 * p(0, 1) + p(1, 1)) - (p(-1, -1) + 2.0 * p(0, -1) + p(1, -1));
+    (gx, gy)
+}
+
+/// Build a histogram of gradient orientations for each cell of a `grid_size` * `grid_size` grid laid over a grayscale version of the image.
+///
+/// Orientations are unsigned (0° to 180°), so an outline has the same descriptor whether it is lighter or darker than its surroundings.
+/// Each pixel votes for its orientation bin with its gradient magnitude, and each cell's histogram is L2-normalised.
+/// The histograms are concatenated, row by row, into a single vector of length `grid_size² * orientation_bins`.
+pub fn orientation_histograms(
+    rgba_image: &RgbaImage,
+    grid_size: u32,
+    orientation_bins: usize,
+) -> Vec<f32> {
+    let grid_size = grid_size.max(1);
+    let orientation_bins = orientation_bins.max(1);
+    let gray_image = imageops::grayscale(rgba_image);
+    let (width, height) = gray_image.dimensions();
+
+    let mut histograms = vec![0.0_f32; (grid_size * grid_size) as usize * orientation_bins];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let (gx, gy) = sobel(&gray_image, x, y);
+            let magnitude = (gx.powf(2.0) + gy.powf(2.0)).sqrt();
+            if magnitude == 0.0 {
+                continue;
+            }
+            let orientation = gy.atan2(gx).rem_euclid(PI);
+            let bin = ((orientation / PI) * orientation_bins as f32) as usize % orientation_bins;
+            let cell = (y * grid_size / height) * grid_size + (x * grid_size / width);
+            histograms[cell as usize * orientation_bins + bin] += magnitude;
+        }
+    }
+
+    histograms
+        .chunks_mut(orientation_bins)
+        .for_each(|histogram| {
+            let norm = histogram.iter().map(|v| v.powf(2.0)).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                histogram.iter_mut().for_each(|v| *v /= norm);
+            }
+        });
+    histograms
+}
+
+/// Return the similarity of two L2-normalised orientation histograms, ranging from 0 to 1.
+///
+/// Two cells without any edges are identical; a cell without any edges has no similarity to a cell with edges.
+pub fn cell_similarity(histogram_a: &[f32], histogram_b: &[f32]) -> f32 {
+    let a_empty = histogram_a.iter().all(|v| *v == 0.0);
+    let b_empty = histogram_b.iter().all(|v| *v == 0.0);
+    match (a_empty, b_empty) {
+        (true, true) => 1.0,
+        (false, false) => histogram_a
+            .iter()
+            .zip(histogram_b)
+            .map(|(a, b)| a * b)
+            .sum::<f32>()
+            .min(1.0),
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn vertical_edge() -> RgbaImage {
+        RgbaImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                image::Rgba([0, 0, 0, 255])
+            } else {
+                image::Rgba([255, 255, 255, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn sobel_vertical_edge() {
+        let gray_image = imageops::grayscale(&vertical_edge());
+        let (gx, gy) = sobel(&gray_image, 4, 4);
+        assert_eq!((gx, gy), (1020.0, 0.0));
+    }
+
+    #[test]
+    fn oh_vertical_edge() {
+        // Horizontal gradients all land in the first bin.
+        let histogram = orientation_histograms(&vertical_edge(), 1, 9);
+        assert_eq!(histogram[0], 1.0);
+        assert_eq!(histogram[1..].iter().sum::<f32>(), 0.0);
+    }
+
+    #[test]
+    fn oh_inverted_contrast() {
+        let mut inverted = vertical_edge();
+        imageops::invert(&mut inverted);
+        assert_eq!(
+            orientation_histograms(&vertical_edge(), 2, 9),
+            orientation_histograms(&inverted, 2, 9)
+        );
+    }
+
+    #[test]
+    fn cs_empty() {
+        assert_eq!(cell_similarity(&[0.0, 0.0], &[0.0, 0.0]), 1.0);
+        assert_eq!(cell_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn cs_orthogonal() {
+        assert_eq!(cell_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn cs_same() {
+        assert_eq!(cell_similarity(&[0.0, 1.0, 0.0], &[0.0, 1.0, 0.0]), 1.0);
+    }
+}
@@ -0,0 +1,252 @@
+#![warn(missing_docs)]
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum_macros::EnumIter;
+
+use crate::{
+    similarity::algs::{compute, Pairings},
+    ImageSimilarityMatrix, ImgsimImage, ImgsimOptions, MatchEnumAsStr, SimilarityAlg,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, EnumIter)]
+/// Denotes the way the scores of an ensemble's members are combined into a single similarity.
+pub enum EnsembleCombine {
+    #[serde(alias = "weightedmean", alias = "mean")]
+    /// The weighted mean of every member's score.
+    WeightedMean,
+    #[serde(alias = "min")]
+    /// The lowest of every member's score. Images are only similar if every member agrees.
+    Min,
+    #[serde(alias = "logistic")]
+    /// A logistic regression over every member's score, using weights loaded from a file.
+    Logistic,
+}
+impl MatchEnumAsStr for EnsembleCombine {
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            EnsembleCombine::WeightedMean => &["weightedmean", "mean"],
+            EnsembleCombine::Min => &["min"],
+            EnsembleCombine::Logistic => &["logistic"],
+        }
+    }
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// A single similarity algorithm within an ensemble, along with its weight.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EnsembleMember {
+    alg: SimilarityAlg,
+    #[serde(default = "default_weight")]
+    weight: f32,
+}
+impl EnsembleMember {
+    /// Creates a new [EnsembleMember] out of a similarity algorithm and its weight.
+    pub fn new(alg: SimilarityAlg, weight: f32) -> EnsembleMember {
+        EnsembleMember { alg, weight }
+    }
+
+    /// Returns the similarity algorithm of this member.
+    pub fn alg(&self) -> &SimilarityAlg {
+        &self.alg
+    }
+
+    /// Returns the weight of this member.
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+}
+
+/// Learned logistic regression weights used by [EnsembleCombine::Logistic].
+///
+/// Loaded from the `.toml` file at `logistic_weights_path` in the `[ensemble]` section of config.toml.
+#[derive(Debug, Deserialize)]
+pub struct LogisticWeights {
+    intercept: f32,
+    coefficients: Vec<EnsembleMember>,
+}
+impl LogisticWeights {
+    /// Creates a new [LogisticWeights] out of an intercept and each similarity algorithm's coefficient.
+    pub fn new(intercept: f32, coefficients: Vec<EnsembleMember>) -> LogisticWeights {
+        LogisticWeights {
+            intercept,
+            coefficients,
+        }
+    }
+
+    /// Returns the intercept of the logistic regression.
+    pub fn intercept(&self) -> f32 {
+        self.intercept
+    }
+
+    /// Returns the coefficient for the given similarity algorithm, if there is one.
+    pub fn coefficient(&self, alg: &SimilarityAlg) -> Option<f32> {
+        self.coefficients
+            .iter()
+            .find(|member| member.alg() == alg)
+            .map(|member| member.weight())
+    }
+}
+
+/// Get each image's similarity to every other image by combining the scores of each member of the ensemble.
+pub fn ensemble(images: &[ImgsimImage], imgsim_options: &ImgsimOptions) -> ImageSimilarityMatrix {
+    let similarities = ensemble_similarities(
+        images,
+        images,
+        &Pairings::Within(images.len()),
+        imgsim_options,
+    );
+    ImageSimilarityMatrix::from(images).with_similarities(similarities, HashMap::new())
+}
+
+/// Like [ensemble], but for any set of pairings, where the second image of each pairing is taken from `others`.
+pub(crate) fn ensemble_similarities(
+    images: &[ImgsimImage],
+    others: &[ImgsimImage],
+    pairings: &Pairings,
+    imgsim_options: &ImgsimOptions,
+) -> Vec<f32> {
+    let mut member_similarities: HashMap<SimilarityAlg, Vec<f32>> = HashMap::new();
+    for member in imgsim_options.ensemble_members() {
+        if imgsim_options.debug() || imgsim_options.verbose() {
+            println!("Ensemble: computing {:?} similarities.", member.alg());
+        }
+        member_similarities.insert(
+            *member.alg(),
+            compute(member.alg(), images, others, pairings, imgsim_options),
+        );
+    }
+
+    // Every member's similarities are in the same order, so they can be combined position by position
+    let len = member_similarities.values().next().map_or(0, Vec::len);
+    (0..len)
+        .map(|index| {
+            let scores: Vec<(f32, Option<f32>)> = imgsim_options
+                .ensemble_members()
+                .iter()
+                .map(|member| {
+                    let weight = match imgsim_options.ensemble_combine() {
+                        EnsembleCombine::Logistic => imgsim_options
+                            .logistic_weights()
+                            .as_ref()
+                            .and_then(|weights| weights.coefficient(member.alg()))
+                            .unwrap_or(0.0),
+                        _ => member.weight(),
+                    };
+                    let score = member_similarities[member.alg()][index];
+                    let score = if score.is_nan() { None } else { Some(score) };
+                    (weight, score)
+                })
+                .collect();
+            match imgsim_options.ensemble_combine() {
+                EnsembleCombine::WeightedMean => weighted_mean(&scores),
+                EnsembleCombine::Min => minimum(&scores),
+                EnsembleCombine::Logistic => logistic(
+                    imgsim_options
+                        .logistic_weights()
+                        .as_ref()
+                        .map_or(0.0, |weights| weights.intercept()),
+                    &scores,
+                ),
+            }
+            .unwrap_or(f32::NAN)
+        })
+        .collect()
+}
+
+// Members which could not compare the pair are left out, and the remaining weights are renormalised.
+fn weighted_mean(scores: &[(f32, Option<f32>)]) -> Option<f32> {
+    let (weighted_sum, weight_sum) = scores
+        .iter()
+        .filter_map(|(weight, score)| score.map(|s| (weight * s, *weight)))
+        .fold((0.0, 0.0), |(acc_sum, acc_weight), (sum, weight)| {
+            (acc_sum + sum, acc_weight + weight)
+        });
+    if weight_sum > 0.0 {
+        Some(weighted_sum / weight_sum)
+    } else {
+        None
+    }
+}
+
+fn minimum(scores: &[(f32, Option<f32>)]) -> Option<f32> {
+    scores
+        .iter()
+        .filter_map(|(_, score)| *score)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+// The logistic regression outputs a probability from 0 to 1, which is stretched to match the -1 to 1 range of the other algorithms.
+// Every member must be able to compare the pair, since the regression was fit on all of them.
+fn logistic(intercept: f32, scores: &[(f32, Option<f32>)]) -> Option<f32> {
+    let mut linear = intercept;
+    for (coefficient, score) in scores {
+        linear += coefficient * (*score)?;
+    }
+    Some((2.0 / (1.0 + (-linear).exp())) - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn wm_even() {
+        assert_eq!(
+            weighted_mean(&[(1.0, Some(0.5)), (1.0, Some(-0.5))]),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn wm_weighted() {
+        assert_eq!(
+            weighted_mean(&[(3.0, Some(1.0)), (1.0, Some(-1.0))]),
+            Some(0.5)
+        );
+    }
+
+    #[test]
+    fn wm_skip_none() {
+        assert_eq!(weighted_mean(&[(1.0, Some(0.25)), (5.0, None)]), Some(0.25));
+    }
+
+    #[test]
+    fn wm_all_none() {
+        assert_eq!(weighted_mean(&[(1.0, None), (1.0, None)]), None);
+    }
+
+    #[test]
+    fn min_lowest() {
+        assert_eq!(
+            minimum(&[(1.0, Some(0.7)), (1.0, None), (1.0, Some(-0.2))]),
+            Some(-0.2)
+        );
+    }
+
+    #[test]
+    fn logistic_zero() {
+        assert_eq!(
+            logistic(0.0, &[(2.0, Some(0.0)), (1.0, Some(0.0))]),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn logistic_misc() {
+        let ans = (2.0 / (1.0 + (-(0.5_f32 + 2.0 * 0.3 - 0.4)).exp())) - 1.0;
+        assert_eq!(
+            logistic(0.5, &[(2.0, Some(0.3)), (-1.0, Some(0.4))]),
+            Some(ans)
+        );
+    }
+
+    #[test]
+    fn logistic_none() {
+        assert_eq!(logistic(0.5, &[(2.0, Some(0.3)), (-1.0, None)]), None);
+    }
+}
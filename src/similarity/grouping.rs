@@ -0,0 +1,312 @@
+#![warn(missing_docs)]
+//! Grouping of images into sets of duplicates whose similarity exceeds a threshold.
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::{ImageId, ImageSimilarityMatrix, MatchEnumAsStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, EnumIter)]
+/// Denotes the way images are grouped together once their similarities are known.
+pub enum GroupingMethod {
+    #[serde(
+        alias = "connectedcomponents",
+        alias = "Connectedcomponents",
+        alias = "components"
+    )]
+    /// Images are in the same group if they are linked by a chain of pairings above the threshold. Fast, but a long chain can join two very different images.
+    ConnectedComponents,
+    #[serde(
+        alias = "completelinkage",
+        alias = "Completelinkage",
+        alias = "complete"
+    )]
+    /// Images are only in the same group if every pairing within the group is above the threshold.
+    CompleteLinkage,
+}
+impl MatchEnumAsStr for GroupingMethod {}
+
+/// A set of images whose similarities exceed the grouping threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageGroup {
+    member_ids: Vec<ImageId>,
+    members: Vec<String>,
+    representative: usize,
+}
+impl ImageGroup {
+    /// Builds an [ImageGroup] out of the images at the given indices of `ids` and `names`, choosing its representative
+    /// using the given similarity of two indices.
+    pub(crate) fn new(
+        ids: &[ImageId],
+        names: &[String],
+        group: &[usize],
+        similarity: impl Fn(usize, usize) -> f32,
+    ) -> ImageGroup {
+        let representative = representative(&similarity, group);
+        ImageGroup {
+            member_ids: group.iter().map(|index| ids[*index].clone()).collect(),
+            members: group
+                .iter()
+                .map(|index| String::from(&names[*index]))
+                .collect(),
+            representative: group
+                .iter()
+                .position(|index| *index == representative)
+                .unwrap_or(0),
+        }
+    }
+
+    /// Returns the identities of the images in the group, in the order they were loaded.
+    pub fn member_ids(&self) -> &[ImageId] {
+        &self.member_ids
+    }
+
+    /// Returns the identity of the image most similar to the rest of the group.
+    pub fn representative_id(&self) -> &ImageId {
+        &self.member_ids[self.representative]
+    }
+
+    /// Returns the names of the images in the group, in the order they were loaded.
+    pub fn members(&self) -> &[String] {
+        &self.members
+    }
+
+    /// Returns the name of the image most similar to the rest of the group.
+    pub fn representative(&self) -> &str {
+        &self.members[self.representative]
+    }
+}
+
+/// Group together the images whose pairwise similarity is above `threshold`.
+///
+/// Images which are not similar enough to any other image are left out, so every group has at least two members.
+/// Groups are sorted from largest to smallest.
+pub fn group_images(
+    matrix: &ImageSimilarityMatrix,
+    threshold: f32,
+    grouping_method: &GroupingMethod,
+) -> Vec<ImageGroup> {
+    group_linked(
+        matrix.ids(),
+        matrix.names(),
+        matrix
+            .pairs()
+            .filter(|(_, _, similarity)| similarity.is_some_and(|s| s > threshold))
+            .map(|(i, j, _)| (i, j)),
+        |i, j| matrix_similarity(matrix, i, j),
+        threshold,
+        grouping_method,
+    )
+}
+
+/// Group together the images of `ids` and `names`, given every pairing `links` whose similarity is above `threshold` and the
+/// similarity of any two indices.
+///
+/// This lets groups be found without a full [ImageSimilarityMatrix], such as from a metric index.
+pub(crate) fn group_linked(
+    ids: &[ImageId],
+    names: &[String],
+    links: impl Iterator<Item = (usize, usize)>,
+    similarity: impl Fn(usize, usize) -> f32,
+    threshold: f32,
+    grouping_method: &GroupingMethod,
+) -> Vec<ImageGroup> {
+    let components = connected_components(names.len(), links);
+    let mut groups: Vec<Vec<usize>> = match grouping_method {
+        GroupingMethod::ConnectedComponents => components,
+        // Every pairing in a complete-linkage group is above the threshold, so no group can span two components
+        GroupingMethod::CompleteLinkage => components
+            .into_iter()
+            .flat_map(|component| complete_linkage(&similarity, threshold, component))
+            .collect(),
+    };
+    groups.retain(|group| group.len() > 1);
+    groups.iter_mut().for_each(|group| group.sort_unstable());
+    groups.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+
+    groups
+        .iter()
+        .map(|group| ImageGroup::new(ids, names, group, &similarity))
+        .collect()
+}
+
+/// The similarity of the images at the two given indices of the matrix. Pairings which could not be compared count as entirely dissimilar.
+pub(crate) fn matrix_similarity(matrix: &ImageSimilarityMatrix, i: usize, j: usize) -> f32 {
+    matrix.get_by_index((i, j)).unwrap_or(-1.0)
+}
+
+// Union-find over every pairing above the threshold.
+fn connected_components(
+    len: usize,
+    links: impl Iterator<Item = (usize, usize)>,
+) -> Vec<Vec<usize>> {
+    fn find(parents: &mut [usize], index: usize) -> usize {
+        let mut root = index;
+        while parents[root] != root {
+            root = parents[root];
+        }
+        // Path compression
+        let mut current = index;
+        while parents[current] != root {
+            let next = parents[current];
+            parents[current] = root;
+            current = next;
+        }
+        root
+    }
+
+    let mut parents: Vec<usize> = (0..len).collect();
+    links.for_each(|(i, j)| {
+        let (root_i, root_j) = (find(&mut parents, i), find(&mut parents, j));
+        if root_i != root_j {
+            parents[root_i.max(root_j)] = root_i.min(root_j);
+        }
+    });
+
+    let mut components: Vec<Vec<usize>> = vec![Vec::new(); len];
+    for index in 0..len {
+        let root = find(&mut parents, index);
+        components[root].push(index);
+    }
+    components.retain(|component| !component.is_empty());
+    components
+}
+
+// Repeatedly merge the two groups whose least similar pairing is the most similar, until no such pairing is above the threshold.
+fn complete_linkage(
+    similarity: &impl Fn(usize, usize) -> f32,
+    threshold: f32,
+    members: Vec<usize>,
+) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = members.iter().map(|index| vec![*index]).collect();
+    let mut linkage: Vec<Vec<f32>> = members
+        .iter()
+        .map(|i| members.iter().map(|j| similarity(*i, *j)).collect())
+        .collect();
+    let mut alive: Vec<bool> = vec![true; groups.len()];
+
+    loop {
+        let mut best: Option<(usize, usize, f32)> = None;
+        for a in 0..groups.len() {
+            for b in a + 1..groups.len() {
+                if alive[a]
+                    && alive[b]
+                    && linkage[a][b] > threshold
+                    && best.is_none_or(|(_, _, s)| linkage[a][b] > s)
+                {
+                    best = Some((a, b, linkage[a][b]));
+                }
+            }
+        }
+        let Some((a, b, _)) = best else {
+            break;
+        };
+        let merged = std::mem::take(&mut groups[b]);
+        groups[a].extend(merged);
+        alive[b] = false;
+        let merged_linkage: Vec<f32> = linkage[a]
+            .iter()
+            .zip(linkage[b].iter())
+            .map(|(linkage_a, linkage_b)| linkage_a.min(*linkage_b))
+            .collect();
+        for (k, new_linkage) in merged_linkage.into_iter().enumerate() {
+            linkage[a][k] = new_linkage;
+            linkage[k][a] = new_linkage;
+        }
+    }
+
+    groups.retain(|group| !group.is_empty());
+    groups
+}
+
+// The member with the highest total similarity to the rest of the group. Ties go to the first loaded image.
+fn representative(similarity: &impl Fn(usize, usize) -> f32, group: &[usize]) -> usize {
+    let mut best = (group[0], f32::MIN);
+    for i in group {
+        let total: f32 = group
+            .iter()
+            .filter(|j| *j != i)
+            .map(|j| similarity(*i, *j))
+            .sum();
+        if total > best.1 {
+            best = (*i, total);
+        }
+    }
+    best.0
+}
+
+/// Print the groups of similar images to the terminal.
+pub fn print_groups(groups: &[ImageGroup]) {
+    println!("\n=========Image Groups=========");
+    if groups.is_empty() {
+        println!("None found.");
+    }
+    groups.iter().enumerate().for_each(|(number, group)| {
+        println!(
+            "Group {} ({} image{}, representative \"{}\"):",
+            number + 1,
+            group.members().len(),
+            if group.members().len() == 1 { "" } else { "s" },
+            group.representative()
+        );
+        group
+            .members()
+            .iter()
+            .for_each(|member| println!("\t\"{}\"", member));
+    });
+    println!("==============================");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // a-b-c form a chain where a and c are not similar; d-e are similar; f is on its own.
+    fn chain_matrix() -> ImageSimilarityMatrix {
+        let mut matrix = ImageSimilarityMatrix::from_names(
+            ["a", "b", "c", "d", "e", "f"]
+                .iter()
+                .map(|n| String::from(*n))
+                .collect(),
+        );
+        matrix.fill(|i, j| match (i, j) {
+            (0, 1) => Some(0.9),
+            (1, 2) => Some(0.85),
+            (0, 2) => Some(0.2),
+            (3, 4) => Some(0.95),
+            (4, 5) => None,
+            _ => Some(-0.5),
+        });
+        matrix
+    }
+
+    fn member_names(groups: &[ImageGroup]) -> Vec<Vec<&str>> {
+        groups
+            .iter()
+            .map(|group| group.members().iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn components_chain() {
+        let groups = group_images(&chain_matrix(), 0.8, &GroupingMethod::ConnectedComponents);
+        assert_eq!(
+            member_names(&groups),
+            vec![vec!["a", "b", "c"], vec!["d", "e"]]
+        );
+        assert_eq!(groups[0].representative(), "b");
+    }
+
+    #[test]
+    fn complete_breaks_chain() {
+        let groups = group_images(&chain_matrix(), 0.8, &GroupingMethod::CompleteLinkage);
+        assert_eq!(member_names(&groups), vec![vec!["a", "b"], vec!["d", "e"]]);
+    }
+
+    #[test]
+    fn threshold_exclusive() {
+        let groups = group_images(&chain_matrix(), 0.95, &GroupingMethod::CompleteLinkage);
+        assert_eq!(groups, vec![]);
+    }
+}
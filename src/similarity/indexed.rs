@@ -0,0 +1,499 @@
+#![warn(missing_docs)]
+//! Similarity searches which use a metric index instead of comparing every pairing of images.
+//!
+//! Only algorithms whose similarity is a decreasing function of a metric distance between fixed-size feature vectors
+//! or hashes can be indexed, and the index finds exactly the same pairings as comparing every pairing would. Vectors
+//! are searched with a [VpTree], and hashes, by their Hamming distance, with a [BkTree].
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+use crate::{
+    dhash::{dhash, hash_similarity, HASH_BITS},
+    display_names,
+    edge_orientation::{cell_similarity, orientation_histograms},
+    metric_index::{hamming_distance, BkTree, VpTree},
+    similarity::{
+        algs::{edge_orientation_similarity, texture_similarity},
+        grouping::group_linked,
+    },
+    texture::{chi_square, lbp_histograms},
+    CrossSimilarityMatrix, GroupingMethod, ImageGroup, ImageId, ImgsimImage, ImgsimOptions,
+    SimilarityAlg,
+};
+
+// Distances are computed in f32, so the search radius is widened slightly and every pairing found is checked against
+// its exact similarity.
+const RADIUS_TOLERANCE: f32 = 1e-4;
+
+// An algorithm whose similarity is `1 - 2 * distance² / cells`, where `distance` is a metric.
+enum IndexedAlg {
+    // The square root of the chi-square distance between LBP histograms is a metric.
+    Texture {
+        grid_size: u32,
+    },
+    // For L2-normalised cells, `1 - cell_similarity` is half the squared Euclidean distance between them, once an empty
+    // cell is treated as a unit vector orthogonal to every other cell.
+    EdgeOrientation {
+        grid_size: u32,
+        orientation_bins: usize,
+    },
+}
+impl IndexedAlg {
+    fn from_options(imgsim_options: &ImgsimOptions) -> Option<IndexedAlg> {
+        // Each transform of an image has its own features, so the best of them cannot be found with a single index
+        if imgsim_options.transform_invariant() {
+            return None;
+        }
+        match imgsim_options.similarity_alg() {
+            SimilarityAlg::Texture => Some(IndexedAlg::Texture {
+                grid_size: imgsim_options.texture_grid_size(),
+            }),
+            SimilarityAlg::EdgeOrientation => Some(IndexedAlg::EdgeOrientation {
+                grid_size: imgsim_options.edge_grid_size(),
+                orientation_bins: imgsim_options.orientation_bins().max(1),
+            }),
+            _ => None,
+        }
+    }
+
+    fn extract(&self, image: &ImgsimImage) -> Vec<f32> {
+        match self {
+            IndexedAlg::Texture { grid_size } => lbp_histograms(image.rgba_image(), *grid_size),
+            IndexedAlg::EdgeOrientation {
+                grid_size,
+                orientation_bins,
+            } => orientation_histograms(image.rgba_image(), *grid_size, *orientation_bins),
+        }
+    }
+
+    fn cells(&self) -> f32 {
+        let grid_size = match self {
+            IndexedAlg::Texture { grid_size } => grid_size,
+            IndexedAlg::EdgeOrientation { grid_size, .. } => grid_size,
+        };
+        (grid_size.max(&1) * grid_size.max(&1)) as f32
+    }
+
+    fn distance(&self, features_a: &[f32], features_b: &[f32]) -> f32 {
+        match self {
+            IndexedAlg::Texture { .. } => chi_square(features_a, features_b).max(0.0).sqrt(),
+            IndexedAlg::EdgeOrientation {
+                orientation_bins, ..
+            } => features_a
+                .chunks(*orientation_bins)
+                .zip(features_b.chunks(*orientation_bins))
+                .map(|(cell_a, cell_b)| (1.0 - cell_similarity(cell_a, cell_b)).max(0.0))
+                .sum::<f32>()
+                .sqrt(),
+        }
+    }
+
+    // Computed exactly as the unindexed algorithm does, so both give the same scores.
+    fn similarity(&self, features_a: &[f32], features_b: &[f32]) -> f32 {
+        match self {
+            IndexedAlg::Texture { grid_size } => {
+                texture_similarity(features_a, features_b, *grid_size)
+            }
+            IndexedAlg::EdgeOrientation {
+                orientation_bins, ..
+            } => edge_orientation_similarity(features_a, features_b, *orientation_bins),
+        }
+    }
+
+    // The distance within which pairings may have a similarity above the threshold.
+    fn radius(&self, threshold: f32) -> f32 {
+        ((1.0 - threshold) * self.cells() / 2.0).max(0.0).sqrt() * (1.0 + RADIUS_TOLERANCE)
+            + RADIUS_TOLERANCE
+    }
+}
+
+// The features the chosen algorithm compares, and so the kind of metric index which searches them.
+enum IndexedFeatures {
+    // Feature vectors, searched with a vantage-point tree.
+    Vectors(IndexedAlg),
+    // Difference hashes, whose similarity falls as their Hamming distance rises, searched with a Burkhard-Keller tree.
+    Hashes,
+}
+impl IndexedFeatures {
+    fn from_options(imgsim_options: &ImgsimOptions) -> Option<IndexedFeatures> {
+        match imgsim_options.similarity_alg() {
+            SimilarityAlg::DHash if !imgsim_options.transform_invariant() => {
+                Some(IndexedFeatures::Hashes)
+            }
+            _ => IndexedAlg::from_options(imgsim_options).map(IndexedFeatures::Vectors),
+        }
+    }
+}
+
+// A metric index over the features of a list of images.
+trait FeatureIndex: Sync {
+    type Features: Sync;
+
+    // The features of the given image, which can be compared to those in the index.
+    fn extract(&self, image: &ImgsimImage) -> Self::Features;
+
+    // The features of each indexed image, in the order the images were given.
+    fn items(&self) -> &[Self::Features];
+
+    // Every indexed image whose similarity to the given features may be above the threshold. No other image's is.
+    fn candidates(&self, features: &Self::Features, threshold: f32) -> Vec<usize>;
+
+    // The `k` indexed images most similar to the given features, from most to least similar.
+    fn nearest(&self, features: &Self::Features, k: usize) -> Vec<usize>;
+
+    fn similarity(&self, features_a: &Self::Features, features_b: &Self::Features) -> f32;
+}
+
+type VectorDistance<'a> = Box<dyn Fn(&Vec<f32>, &Vec<f32>) -> f32 + Sync + 'a>;
+
+// Feature vectors, indexed with a vantage-point tree.
+struct VectorIndex<'a> {
+    indexed_alg: &'a IndexedAlg,
+    tree: VpTree<Vec<f32>, VectorDistance<'a>>,
+}
+impl<'a> VectorIndex<'a> {
+    fn new(indexed_alg: &'a IndexedAlg, images: &[ImgsimImage]) -> VectorIndex<'a> {
+        let features: Vec<Vec<f32>> = images
+            .par_iter()
+            .map(|image| indexed_alg.extract(image))
+            .collect();
+        VectorIndex {
+            indexed_alg,
+            tree: VpTree::new(
+                features,
+                Box::new(move |features_a: &Vec<f32>, features_b: &Vec<f32>| {
+                    indexed_alg.distance(features_a, features_b)
+                }),
+            ),
+        }
+    }
+}
+impl FeatureIndex for VectorIndex<'_> {
+    type Features = Vec<f32>;
+
+    fn extract(&self, image: &ImgsimImage) -> Vec<f32> {
+        self.indexed_alg.extract(image)
+    }
+
+    fn items(&self) -> &[Vec<f32>] {
+        self.tree.items()
+    }
+
+    fn candidates(&self, features: &Vec<f32>, threshold: f32) -> Vec<usize> {
+        self.tree
+            .within(features, self.indexed_alg.radius(threshold))
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn nearest(&self, features: &Vec<f32>, k: usize) -> Vec<usize> {
+        self.tree
+            .nearest(features, k)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn similarity(&self, features_a: &Vec<f32>, features_b: &Vec<f32>) -> f32 {
+        self.indexed_alg.similarity(features_a, features_b)
+    }
+}
+
+// Difference hashes, indexed with a Burkhard-Keller tree.
+struct HashIndex {
+    tree: BkTree<u64, fn(&u64, &u64) -> u32>,
+}
+impl HashIndex {
+    fn new(hashes: Vec<u64>) -> HashIndex {
+        HashIndex {
+            tree: BkTree::new(hashes, hamming_distance as fn(&u64, &u64) -> u32),
+        }
+    }
+
+    fn from_images(images: &[ImgsimImage]) -> HashIndex {
+        HashIndex::new(
+            images
+                .par_iter()
+                .map(|image| dhash(image.rgba_image()))
+                .collect(),
+        )
+    }
+}
+impl FeatureIndex for HashIndex {
+    type Features = u64;
+
+    fn extract(&self, image: &ImgsimImage) -> u64 {
+        dhash(image.rgba_image())
+    }
+
+    fn items(&self) -> &[u64] {
+        self.tree.items()
+    }
+
+    fn candidates(&self, hash: &u64, threshold: f32) -> Vec<usize> {
+        // The similarity is `1 - 2 * distance / HASH_BITS`, and distances are whole numbers, so no tolerance is needed
+        let radius =
+            ((1.0 - threshold) * HASH_BITS as f32 / 2.0).clamp(0.0, HASH_BITS as f32) as u32;
+        self.tree
+            .within(hash, radius)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn nearest(&self, hash: &u64, k: usize) -> Vec<usize> {
+        self.tree
+            .nearest(hash, k)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn similarity(&self, hash_a: &u64, hash_b: &u64) -> f32 {
+        hash_similarity(*hash_a, *hash_b)
+    }
+}
+
+/// Return true if the chosen similarity algorithm and settings can be searched with a metric index.
+///
+/// This is the case for the `Texture`, `EdgeOrientation` and `DHash` algorithms, as long as `transform_invariant` is
+/// disabled.
+pub fn supports_index(imgsim_options: &ImgsimOptions) -> bool {
+    IndexedFeatures::from_options(imgsim_options).is_some()
+}
+
+/// Like [crate::group_images], but only the pairings near each image are compared, using a metric index.
+///
+/// Returns [Option::None] if the chosen similarity algorithm cannot be indexed. See [supports_index].
+pub fn group_images_indexed(
+    images: &[ImgsimImage],
+    threshold: f32,
+    grouping_method: &GroupingMethod,
+    imgsim_options: &ImgsimOptions,
+) -> Option<Vec<ImageGroup>> {
+    Some(match IndexedFeatures::from_options(imgsim_options)? {
+        IndexedFeatures::Vectors(indexed_alg) => group_with(
+            &VectorIndex::new(&indexed_alg, images),
+            images,
+            threshold,
+            grouping_method,
+        ),
+        IndexedFeatures::Hashes => group_with(
+            &HashIndex::from_images(images),
+            images,
+            threshold,
+            grouping_method,
+        ),
+    })
+}
+
+// Group the images linked by a similarity above the threshold, using an index over their features to find the links.
+fn group_with(
+    index: &impl FeatureIndex,
+    images: &[ImgsimImage],
+    threshold: f32,
+    grouping_method: &GroupingMethod,
+) -> Vec<ImageGroup> {
+    let features = index.items();
+    let links: Vec<(usize, usize)> = (0..features.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            index
+                .candidates(&features[i], threshold)
+                .into_iter()
+                .filter(move |j| *j > i)
+                .map(move |j| (i, j))
+        })
+        .filter(|(i, j)| index.similarity(&features[*i], &features[*j]) > threshold)
+        .collect();
+
+    let ids: Vec<ImageId> = images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| image.id().with_index(index))
+        .collect();
+    let names: Vec<String> = images
+        .iter()
+        .map(|image| String::from(image.name()))
+        .collect();
+    let names = display_names(&ids, &names);
+    group_linked(
+        &ids,
+        &names,
+        links.into_iter(),
+        |i, j| index.similarity(&features[i], &features[j]),
+        threshold,
+        grouping_method,
+    )
+}
+
+/// Like [crate::get_cross_similarities], but only the `k` column images nearest to each row image are compared, using
+/// a metric index. Every other pairing is left as [Option::None]. A row image which was also loaded among the columns
+/// is never its own neighbour.
+///
+/// Returns [Option::None] if the chosen similarity algorithm cannot be indexed. See [supports_index].
+pub fn get_nearest_similarities(
+    rows: &[ImgsimImage],
+    columns: &[ImgsimImage],
+    k: usize,
+    imgsim_options: &ImgsimOptions,
+) -> Option<CrossSimilarityMatrix> {
+    Some(match IndexedFeatures::from_options(imgsim_options)? {
+        IndexedFeatures::Vectors(indexed_alg) => {
+            nearest_with(&VectorIndex::new(&indexed_alg, columns), rows, columns, k)
+        }
+        IndexedFeatures::Hashes => nearest_with(&HashIndex::from_images(columns), rows, columns, k),
+    })
+}
+
+// Compare each row image to the `k` column images nearest to it, using an index over the columns' features.
+fn nearest_with(
+    index: &impl FeatureIndex,
+    rows: &[ImgsimImage],
+    columns: &[ImgsimImage],
+    k: usize,
+) -> CrossSimilarityMatrix {
+    let column_features = index.items();
+    let nearest: Vec<Vec<(usize, f32)>> = rows
+        .par_iter()
+        .map(|image| {
+            let row_features = index.extract(image);
+            // The image would be its own nearest neighbour if it is also a column, so find one more and leave it out
+            index
+                .nearest(&row_features, k + 1)
+                .into_iter()
+                .filter(|column| columns[*column].id().path() != image.id().path())
+                .take(k)
+                .map(|column| {
+                    (
+                        column,
+                        index.similarity(&row_features, &column_features[column]),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut similarities = vec![f32::NAN; rows.len() * columns.len()];
+    nearest.iter().enumerate().for_each(|(row, neighbours)| {
+        neighbours.iter().for_each(|(column, similarity)| {
+            similarities[row * columns.len() + column] = *similarity;
+        })
+    });
+    CrossSimilarityMatrix::new(rows, columns, similarities, HashMap::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+    use std::{env, fs, path::PathBuf, slice};
+
+    fn normalised(vector: &[f32]) -> Vec<f32> {
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        vector.iter().map(|v| v / norm).collect()
+    }
+
+    #[test]
+    fn edge_distance_matches_similarity() {
+        let indexed_alg = IndexedAlg::EdgeOrientation {
+            grid_size: 2,
+            orientation_bins: 3,
+        };
+        let a: Vec<f32> = [
+            normalised(&[1.0, 0.0, 0.0]),
+            normalised(&[1.0, 1.0, 0.0]),
+            vec![0.0; 3],
+            normalised(&[0.0, 1.0, 2.0]),
+        ]
+        .concat();
+        let b: Vec<f32> = [
+            normalised(&[0.0, 1.0, 0.0]),
+            normalised(&[1.0, 1.0, 0.0]),
+            normalised(&[1.0, 0.0, 0.0]),
+            vec![0.0; 3],
+        ]
+        .concat();
+        let distance = indexed_alg.distance(&a, &b);
+        let expected = 1.0 - 2.0 * distance * distance / indexed_alg.cells();
+        assert!((indexed_alg.similarity(&a, &b) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn texture_distance_matches_similarity() {
+        let indexed_alg = IndexedAlg::Texture { grid_size: 1 };
+        let a = vec![0.5, 0.25, 0.25, 0.0];
+        let b = vec![0.25, 0.25, 0.0, 0.5];
+        let distance = indexed_alg.distance(&a, &b);
+        let expected = 1.0 - 2.0 * distance * distance / indexed_alg.cells();
+        assert!((indexed_alg.similarity(&a, &b) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nearest_excludes_itself() {
+        let image_dir = env::temp_dir().join("imgsim_nearest_excludes_itself");
+        fs::create_dir_all(&image_dir).unwrap();
+        let paths: Vec<PathBuf> = (0..3_u32)
+            .map(|seed| {
+                let path = image_dir.join(format!("{}.png", seed));
+                // Blocks of pseudo-random grey, so that each image has a distinct texture
+                RgbaImage::from_fn(48, 48, |x, y| {
+                    let block = (x / 3).wrapping_mul(7919) ^ (y / 3).wrapping_mul(104729) ^ seed;
+                    let grey = (block.wrapping_mul(2654435761) >> 24) as u8;
+                    Rgba([grey, grey, grey, 255])
+                })
+                .save(&path)
+                .unwrap();
+                path
+            })
+            .collect();
+        let imgsim_options = ImgsimOptions::builder()
+            .input_dir(image_dir.clone())
+            .similarity_alg(SimilarityAlg::Texture)
+            .build()
+            .unwrap();
+        let load = |path: &PathBuf| ImgsimImage::new(path.clone(), &imgsim_options).unwrap();
+        let columns: Vec<ImgsimImage> = paths.iter().map(load).collect();
+        let query = load(&paths[0]);
+        let matrix =
+            get_nearest_similarities(slice::from_ref(&query), &columns, 1, &imgsim_options)
+                .unwrap();
+        fs::remove_dir_all(&image_dir).unwrap();
+        assert_eq!(matrix.get_by_index((0, 0)), None);
+        assert_eq!(matrix.row(0).filter(Option::is_some).count(), 1);
+    }
+
+    #[test]
+    fn radius_covers_threshold() {
+        let indexed_alg = IndexedAlg::Texture { grid_size: 2 };
+        assert_eq!(indexed_alg.radius(1.0), RADIUS_TOLERANCE);
+        let radius = indexed_alg.radius(0.5) - RADIUS_TOLERANCE;
+        let similarity = 1.0 - 2.0 * radius * radius / indexed_alg.cells();
+        assert!(similarity < 0.5 && similarity > 0.49);
+    }
+
+    #[test]
+    fn hash_candidates_match_scan() {
+        let hashes: Vec<u64> = (0..200_u64)
+            .map(|seed| seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (seed % 24))
+            .collect();
+        let index = HashIndex::new(hashes.clone());
+        for threshold in [1.0, 0.75, 0.5, 0.0, -1.0] {
+            let mut candidates: Vec<usize> = index
+                .candidates(&hashes[0], threshold)
+                .into_iter()
+                .filter(|i| index.similarity(&hashes[0], &hashes[*i]) > threshold)
+                .collect();
+            candidates.sort();
+            let expected: Vec<usize> = (0..hashes.len())
+                .filter(|i| hash_similarity(hashes[0], hashes[*i]) > threshold)
+                .collect();
+            assert_eq!(candidates, expected);
+        }
+        let nearest = index.nearest(&hashes[0], 1);
+        assert_eq!(nearest, vec![0]);
+    }
+}
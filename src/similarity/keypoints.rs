@@ -0,0 +1,555 @@
+#![warn(missing_docs)]
+//! ORB-style keypoint detection and matching: <https://en.wikipedia.org/wiki/Oriented_FAST_and_rotated_BRIEF>
+//!
+//! Corners are found with FAST-9 over an image pyramid, oriented by their intensity centroid, and described with
+//! rotated 256-bit BRIEF descriptors. Descriptors are matched with a ratio test, and the matches can optionally be
+//! checked for geometric consistency by fitting a homography with RANSAC.
+
+use image::{imageops, GrayImage, RgbaImage};
+use rand::{rngs::StdRng, seq::index::sample, Rng, SeedableRng};
+
+// Bresenham circle of radius 3 used by FAST, in circular order.
+const FAST_CIRCLE: [(i64, i64); 16] = [
+    (0, -3),
+    (1, -3),
+    (2, -2),
+    (3, -1),
+    (3, 0),
+    (3, 1),
+    (2, 2),
+    (1, 3),
+    (0, 3),
+    (-1, 3),
+    (-2, 2),
+    (-3, 1),
+    (-3, 0),
+    (-3, -1),
+    (-2, -2),
+    (-1, -3),
+];
+// Number of contiguous circle pixels that must all be brighter or darker than the centre.
+const FAST_ARC: usize = 9;
+// Radius of the patch used for orientation and description.
+const PATCH_RADIUS: i64 = 15;
+const DESCRIPTOR_BITS: usize = 256;
+// Each pyramid level is this much smaller than the last.
+const PYRAMID_SCALE: f32 = 1.5;
+// Matches further apart than this many bits are never accepted.
+const MAX_MATCH_DISTANCE: u32 = 64;
+// Fixed seeds keep descriptors and RANSAC results reproducible between runs.
+const PATTERN_SEED: u64 = 0x1337_0b5e;
+const RANSAC_SEED: u64 = 0xdec0_de42;
+
+/// An oriented corner and its binary descriptor.
+#[derive(Debug, Clone)]
+pub struct Keypoint {
+    x: f32,
+    y: f32,
+    angle: f32,
+    descriptor: [u64; 4],
+}
+impl Keypoint {
+    /// Returns the coordinates of the keypoint in the original image.
+    pub fn coords(&self) -> (f32, f32) {
+        (self.x, self.y)
+    }
+
+    /// Returns the orientation of the keypoint in radians.
+    pub fn angle(&self) -> f32 {
+        self.angle
+    }
+
+    /// Returns the 256-bit descriptor of the keypoint.
+    pub fn descriptor(&self) -> &[u64; 4] {
+        &self.descriptor
+    }
+
+    /// Returns the number of differing bits between this keypoint's descriptor and another's.
+    pub fn hamming(&self, other: &Keypoint) -> u32 {
+        self.descriptor
+            .iter()
+            .zip(other.descriptor.iter())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+}
+
+// A pair of points within the patch whose intensities are compared for a single descriptor bit.
+type PointPair = ((f32, f32), (f32, f32));
+
+fn brief_pattern() -> Vec<PointPair> {
+    let mut rng = StdRng::seed_from_u64(PATTERN_SEED);
+    let radius = PATCH_RADIUS as f32 - 1.0;
+    let mut point = || loop {
+        let (x, y) = (
+            rng.gen_range(-radius..=radius),
+            rng.gen_range(-radius..=radius),
+        );
+        if x.powf(2.0) + y.powf(2.0) <= radius.powf(2.0) {
+            return (x, y);
+        }
+    };
+    (0..DESCRIPTOR_BITS).map(|_| (point(), point())).collect()
+}
+
+// FAST-9 corner score of the pixel, or None if it is not a corner.
+fn fast_score(gray_image: &GrayImage, x: u32, y: u32, threshold: u8) -> Option<f32> {
+    let centre = gray_image.get_pixel(x, y)[0] as i16;
+    let threshold = threshold as i16;
+    let circle: Vec<i16> = FAST_CIRCLE
+        .iter()
+        .map(|(dx, dy)| {
+            gray_image.get_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32)[0] as i16
+        })
+        .collect();
+
+    let is_corner = |brighter: bool| {
+        let passes = |value: i16| {
+            if brighter {
+                value > centre + threshold
+            } else {
+                value < centre - threshold
+            }
+        };
+        let mut run = 0;
+        // Go around the circle twice so that arcs wrapping past the start are counted.
+        for i in 0..(2 * circle.len()) {
+            if passes(circle[i % circle.len()]) {
+                run += 1;
+                if run >= FAST_ARC {
+                    return true;
+                }
+            } else {
+                run = 0;
+            }
+        }
+        false
+    };
+
+    if is_corner(true) || is_corner(false) {
+        Some(
+            circle
+                .iter()
+                .map(|value| ((value - centre).abs() - threshold).max(0) as f32)
+                .sum(),
+        )
+    } else {
+        None
+    }
+}
+
+// Orientation of the patch around the pixel, from the patch centre to its intensity centroid.
+fn intensity_centroid_angle(gray_image: &GrayImage, x: u32, y: u32) -> f32 {
+    let (mut m_10, mut m_01) = (0.0_f32, 0.0_f32);
+    for dy in -PATCH_RADIUS..=PATCH_RADIUS {
+        for dx in -PATCH_RADIUS..=PATCH_RADIUS {
+            if dx.pow(2) + dy.pow(2) <= PATCH_RADIUS.pow(2) {
+                let intensity =
+                    gray_image.get_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32)[0] as f32;
+                m_10 += dx as f32 * intensity;
+                m_01 += dy as f32 * intensity;
+            }
+        }
+    }
+    m_01.atan2(m_10)
+}
+
+fn describe(
+    smoothed_image: &GrayImage,
+    x: u32,
+    y: u32,
+    angle: f32,
+    pattern: &[PointPair],
+) -> [u64; 4] {
+    let (sin, cos) = angle.sin_cos();
+    let intensity = |(px, py): (f32, f32)| {
+        let rx = (cos * px - sin * py).round() as i64;
+        let ry = (sin * px + cos * py).round() as i64;
+        smoothed_image.get_pixel((x as i64 + rx) as u32, (y as i64 + ry) as u32)[0]
+    };
+    let mut descriptor = [0_u64; 4];
+    pattern.iter().enumerate().for_each(|(bit, (p_a, p_b))| {
+        if intensity(*p_a) < intensity(*p_b) {
+            descriptor[bit / 64] |= 1 << (bit % 64);
+        }
+    });
+    descriptor
+}
+
+/// Detect up to `max_keypoints` of the strongest oriented FAST corners in the image and compute their descriptors.
+///
+/// Corners are searched for over `pyramid_levels` successively smaller versions of the image so that the same corners
+/// can be found in scaled copies. All coordinates are given relative to the original image.
+pub fn detect_keypoints(
+    rgba_image: &RgbaImage,
+    fast_threshold: u8,
+    max_keypoints: usize,
+    pyramid_levels: usize,
+) -> Vec<Keypoint> {
+    let pattern = brief_pattern();
+    let full_gray_image = imageops::grayscale(rgba_image);
+    let margin = PATCH_RADIUS as u32 + 1;
+
+    let mut scored_keypoints: Vec<(f32, Keypoint)> = Vec::new();
+    for level in 0..pyramid_levels.max(1) {
+        let scale = PYRAMID_SCALE.powi(level as i32);
+        let (width, height) = (
+            (full_gray_image.width() as f32 / scale).round() as u32,
+            (full_gray_image.height() as f32 / scale).round() as u32,
+        );
+        if width <= 2 * margin || height <= 2 * margin {
+            break;
+        }
+        let gray_image = if level == 0 {
+            full_gray_image.clone()
+        } else {
+            imageops::resize(
+                &full_gray_image,
+                width,
+                height,
+                imageops::FilterType::Triangle,
+            )
+        };
+        let smoothed_image = imageops::blur(&gray_image, 2.0);
+
+        // Score every candidate corner, then keep only local maxima.
+        let mut scores = vec![0.0_f32; (width * height) as usize];
+        for y in margin..(height - margin) {
+            for x in margin..(width - margin) {
+                if let Some(score) = fast_score(&gray_image, x, y, fast_threshold) {
+                    scores[(y * width + x) as usize] = score;
+                }
+            }
+        }
+        for y in margin..(height - margin) {
+            for x in margin..(width - margin) {
+                let score = scores[(y * width + x) as usize];
+                if score == 0.0 {
+                    continue;
+                }
+                let is_local_max = (-1..=1_i64).all(|dy| {
+                    (-1..=1_i64).all(|dx| {
+                        let neighbour =
+                            scores[((y as i64 + dy) * width as i64 + (x as i64 + dx)) as usize];
+                        // Break ties towards the top-left so that plateaus keep a single corner.
+                        neighbour < score || (neighbour == score && (dy, dx) >= (0, 0))
+                    })
+                });
+                if is_local_max {
+                    let angle = intensity_centroid_angle(&gray_image, x, y);
+                    scored_keypoints.push((
+                        score,
+                        Keypoint {
+                            x: x as f32 * scale,
+                            y: y as f32 * scale,
+                            angle,
+                            descriptor: describe(&smoothed_image, x, y, angle, &pattern),
+                        },
+                    ));
+                }
+            }
+        }
+    }
+
+    scored_keypoints.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    scored_keypoints.truncate(max_keypoints);
+    scored_keypoints
+        .into_iter()
+        .map(|(_, keypoint)| keypoint)
+        .collect()
+}
+
+/// Match each keypoint in `keypoints_a` to its nearest neighbour in `keypoints_b`.
+///
+/// A match is only kept if its distance is less than `ratio` times the distance of the second-nearest neighbour.
+/// Returns the indices of the matched keypoints.
+pub fn match_keypoints(
+    keypoints_a: &[Keypoint],
+    keypoints_b: &[Keypoint],
+    ratio: f32,
+) -> Vec<(usize, usize)> {
+    keypoints_a
+        .iter()
+        .enumerate()
+        .filter_map(|(i, keypoint_a)| {
+            let mut best: Option<(usize, u32)> = None;
+            let mut second_distance = u32::MAX;
+            for (j, keypoint_b) in keypoints_b.iter().enumerate() {
+                let distance = keypoint_a.hamming(keypoint_b);
+                match best {
+                    Some((_, best_distance)) if distance >= best_distance => {
+                        second_distance = second_distance.min(distance);
+                    }
+                    Some((_, best_distance)) => {
+                        second_distance = best_distance;
+                        best = Some((j, distance));
+                    }
+                    None => best = Some((j, distance)),
+                }
+            }
+            match best {
+                Some((j, distance))
+                    if distance <= MAX_MATCH_DISTANCE
+                        && (second_distance == u32::MAX
+                            || (distance as f32) < ratio * second_distance as f32) =>
+                {
+                    Some((i, j))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// A 3x3 projective transformation, stored row by row with the last element fixed at 1.
+pub type Homography = [f32; 9];
+
+/// Fit a homography mapping the four `from` points onto the four `to` points. Returns None if the points are degenerate.
+pub fn fit_homography(from: &[(f32, f32); 4], to: &[(f32, f32); 4]) -> Option<Homography> {
+    // Each correspondence gives two rows of the 8x8 system A * h = b.
+    let mut system = [[0.0_f64; 9]; 8];
+    for (i, ((x, y), (u, v))) in from.iter().zip(to.iter()).enumerate() {
+        let (x, y, u, v) = (*x as f64, *y as f64, *u as f64, *v as f64);
+        system[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+        system[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+    }
+
+    // Gaussian elimination with partial pivoting
+    for col in 0..8 {
+        let pivot =
+            (col..8).max_by(|a, b| system[*a][col].abs().total_cmp(&system[*b][col].abs()))?;
+        if system[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        system.swap(col, pivot);
+        let pivot_row = system[col];
+        for (row_index, row) in system.iter_mut().enumerate() {
+            if row_index != col {
+                let factor = row[col] / pivot_row[col];
+                row.iter_mut()
+                    .zip(pivot_row.iter())
+                    .skip(col)
+                    .for_each(|(value, pivot_value)| *value -= factor * pivot_value);
+            }
+        }
+    }
+
+    let mut homography = [1.0_f32; 9];
+    for (i, row) in system.iter().enumerate() {
+        homography[i] = (row[8] / row[i]) as f32;
+    }
+    Some(homography)
+}
+
+/// Apply a homography to a point. Returns None if the point is mapped to infinity.
+pub fn project(homography: &Homography, (x, y): (f32, f32)) -> Option<(f32, f32)> {
+    let w = homography[6] * x + homography[7] * y + homography[8];
+    if w.abs() < f32::EPSILON {
+        return None;
+    }
+    Some((
+        (homography[0] * x + homography[1] * y + homography[2]) / w,
+        (homography[3] * x + homography[4] * y + homography[5]) / w,
+    ))
+}
+
+/// Count the largest set of matches consistent with a single homography, found through RANSAC.
+///
+/// A match is an inlier if the homography maps keypoint a to within `threshold` pixels of keypoint b.
+pub fn ransac_inliers(
+    keypoints_a: &[Keypoint],
+    keypoints_b: &[Keypoint],
+    matches: &[(usize, usize)],
+    iterations: usize,
+    threshold: f32,
+) -> usize {
+    ransac_homography(keypoints_a, keypoints_b, matches, iterations, threshold)
+        .map_or(0, |(_, inliers)| inliers)
+}
+
+/// Find the homography mapping image a onto image b which is consistent with the most matches, found through RANSAC.
+///
+/// Returns the homography along with its number of inliers, or None if no homography could be fit.
+pub fn ransac_homography(
+    keypoints_a: &[Keypoint],
+    keypoints_b: &[Keypoint],
+    matches: &[(usize, usize)],
+    iterations: usize,
+    threshold: f32,
+) -> Option<(Homography, usize)> {
+    if matches.len() < 4 {
+        return None;
+    }
+    let mut rng = StdRng::seed_from_u64(RANSAC_SEED);
+    let mut best: Option<(Homography, usize)> = None;
+    for _ in 0..iterations {
+        let chosen = sample(&mut rng, matches.len(), 4);
+        let mut from = [(0.0, 0.0); 4];
+        let mut to = [(0.0, 0.0); 4];
+        chosen.iter().enumerate().for_each(|(k, m)| {
+            from[k] = keypoints_a[matches[m].0].coords();
+            to[k] = keypoints_b[matches[m].1].coords();
+        });
+        if let Some(homography) = fit_homography(&from, &to) {
+            let inliers = matches
+                .iter()
+                .filter(|(i, j)| {
+                    project(&homography, keypoints_a[*i].coords()).is_some_and(|(u, v)| {
+                        let (x_b, y_b) = keypoints_b[*j].coords();
+                        ((u - x_b).powf(2.0) + (v - y_b).powf(2.0)).sqrt() <= threshold
+                    })
+                })
+                .count();
+            if best.is_none_or(|(_, best_inliers)| inliers > best_inliers) {
+                best = Some((homography, inliers));
+            }
+            if inliers == matches.len() {
+                break;
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn keypoint(x: f32, y: f32, descriptor: [u64; 4]) -> Keypoint {
+        Keypoint {
+            x,
+            y,
+            angle: 0.0,
+            descriptor,
+        }
+    }
+
+    fn squares() -> RgbaImage {
+        RgbaImage::from_fn(80, 80, |x, y| {
+            if (20..40).contains(&x) && (20..40).contains(&y)
+                || (45..60).contains(&x) && (30..55).contains(&y)
+            {
+                image::Rgba([255, 255, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn hamming_dist() {
+        let a = keypoint(0.0, 0.0, [0b1011, 0, u64::MAX, 0]);
+        let b = keypoint(0.0, 0.0, [0b0001, 0, 0, 0]);
+        assert_eq!(a.hamming(&b), 66);
+    }
+
+    #[test]
+    fn fast_finds_corner() {
+        let gray_image = imageops::grayscale(&squares());
+        assert!(fast_score(&gray_image, 20, 20, 20).is_some());
+        assert!(fast_score(&gray_image, 30, 30, 20).is_none());
+        assert!(fast_score(&gray_image, 30, 20, 20).is_none());
+    }
+
+    #[test]
+    fn detect_same() {
+        let keypoints_a = detect_keypoints(&squares(), 20, 100, 1);
+        let keypoints_b = detect_keypoints(&squares(), 20, 100, 1);
+        assert!(!keypoints_a.is_empty());
+        assert_eq!(
+            keypoints_a
+                .iter()
+                .map(|k| *k.descriptor())
+                .collect::<Vec<_>>(),
+            keypoints_b
+                .iter()
+                .map(|k| *k.descriptor())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn match_ratio() {
+        let a = vec![keypoint(0.0, 0.0, [0, 0, 0, 0])];
+        // Two equally close neighbours fail the ratio test.
+        let ambiguous = vec![
+            keypoint(0.0, 0.0, [1, 0, 0, 0]),
+            keypoint(0.0, 0.0, [2, 0, 0, 0]),
+        ];
+        assert_eq!(match_keypoints(&a, &ambiguous, 0.8), vec![]);
+        let distinct = vec![
+            keypoint(0.0, 0.0, [1, 0, 0, 0]),
+            keypoint(0.0, 0.0, [u64::MAX, 0, 0, 0]),
+        ];
+        assert_eq!(match_keypoints(&a, &distinct, 0.8), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn homography_translation() {
+        let from = [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0), (10.0, 10.0)];
+        let to = [(5.0, 3.0), (15.0, 3.0), (5.0, 13.0), (15.0, 13.0)];
+        let homography = fit_homography(&from, &to).unwrap();
+        let (u, v) = project(&homography, (7.0, 2.0)).unwrap();
+        assert_eq!(
+            ((u * 1000.0).round(), (v * 1000.0).round()),
+            (12000.0, 5000.0)
+        );
+    }
+
+    #[test]
+    fn homography_degenerate() {
+        let collinear = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
+        assert!(fit_homography(&collinear, &collinear).is_none());
+    }
+
+    #[test]
+    fn ransac_outlier() {
+        let a: Vec<Keypoint> = [
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (0.0, 10.0),
+            (10.0, 10.0),
+            (5.0, 5.0),
+        ]
+        .iter()
+        .map(|(x, y)| keypoint(*x, *y, [0; 4]))
+        .collect();
+        let b: Vec<Keypoint> = [
+            (1.0, 1.0),
+            (11.0, 1.0),
+            (1.0, 11.0),
+            (11.0, 11.0),
+            (40.0, 2.0),
+        ]
+        .iter()
+        .map(|(x, y)| keypoint(*x, *y, [0; 4]))
+        .collect();
+        let matches: Vec<(usize, usize)> = (0..5).map(|i| (i, i)).collect();
+        assert_eq!(ransac_inliers(&a, &b, &matches, 100, 1.0), 4);
+    }
+
+    #[test]
+    fn ransac_homography_scale() {
+        let from = [
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (0.0, 10.0),
+            (10.0, 10.0),
+            (5.0, 5.0),
+        ];
+        let a: Vec<Keypoint> = from.iter().map(|(x, y)| keypoint(*x, *y, [0; 4])).collect();
+        let b: Vec<Keypoint> = from
+            .iter()
+            .map(|(x, y)| keypoint(2.0 * x + 3.0, 2.0 * y, [0; 4]))
+            .collect();
+        let matches: Vec<(usize, usize)> = (0..5).map(|i| (i, i)).collect();
+        let (homography, inliers) = ransac_homography(&a, &b, &matches, 100, 0.5).unwrap();
+        assert_eq!(inliers, 5);
+        let (u, v) = project(&homography, (20.0, 20.0)).unwrap();
+        assert_eq!(
+            ((u * 100.0).round() / 100.0, (v * 100.0).round() / 100.0),
+            (43.0, 40.0)
+        );
+    }
+}
@@ -0,0 +1,372 @@
+#![warn(missing_docs)]
+//! Region adjacency graphs: <https://en.wikipedia.org/wiki/Region_adjacency_graph>
+//!
+//! Each of an image's dominant clusters becomes a node, and clusters which touch are joined by an edge. Two graphs are
+//! compared by matching their nodes to each other, taking into account both the nodes themselves and the neighbours
+//! they are joined to, then measuring how well the matched nodes and edges agree.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    similarity::algs::{avg_colour_sim, proportional_similarity, proportional_similarity_coords},
+    ImgsimImage,
+};
+
+/// A single region of an image.
+#[derive(Debug, Clone)]
+pub struct RegionNode {
+    cluster_id: usize,
+    average_rgba: (u8, u8, u8, u8),
+    size: f32,
+    centroid: (f32, f32),
+}
+impl RegionNode {
+    /// Returns the ID of the cluster this region was built from.
+    pub fn cluster_id(&self) -> usize {
+        self.cluster_id
+    }
+
+    /// Returns the average colour of the region.
+    pub fn average_rgba(&self) -> (u8, u8, u8, u8) {
+        self.average_rgba
+    }
+
+    /// Returns the proportion of the image covered by the region.
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Returns the region's centre of mass as a proportion of the image's width and height.
+    pub fn centroid(&self) -> (f32, f32) {
+        self.centroid
+    }
+}
+
+/// A graph of an image's dominant regions, where regions that touch are joined by an edge.
+#[derive(Debug, Clone)]
+pub struct RegionGraph {
+    nodes: Vec<RegionNode>,
+    // Keyed by node indices (lower, higher). Values are the proportion of all shared border between regions.
+    edges: BTreeMap<(usize, usize), f32>,
+}
+impl RegionGraph {
+    /// Builds a [RegionGraph] out of the image's clusters.
+    ///
+    /// Clusters which constitute less than (`cluster_cutoff` * 100)% of the image's pixels are left out of the graph.
+    pub fn new(image: &ImgsimImage, cluster_cutoff: f32) -> RegionGraph {
+        let (width, height) = image.rgba_image().dimensions();
+        let img_size = width as usize * height as usize;
+
+        let mut node_lookup: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut nodes: Vec<RegionNode> = Vec::new();
+        for (cluster_id, pixels) in image.pixel_clusters() {
+            if pixels.is_empty()
+                || pixels.len() <= (img_size as f32 * cluster_cutoff).round() as usize
+            {
+                continue;
+            }
+            let (sum, sum_x, sum_y) = pixels.iter().fold(
+                ((0_u128, 0_u128, 0_u128, 0_u128), 0_u128, 0_u128),
+                |(sum, sum_x, sum_y), (x, y)| {
+                    let image::Rgba(data) = *image.rgba_image().get_pixel(*x, *y);
+                    (
+                        (
+                            sum.0 + data[0] as u128,
+                            sum.1 + data[1] as u128,
+                            sum.2 + data[2] as u128,
+                            sum.3 + data[3] as u128,
+                        ),
+                        sum_x + *x as u128,
+                        sum_y + *y as u128,
+                    )
+                },
+            );
+            let len = pixels.len() as u128;
+            node_lookup.insert(*cluster_id, nodes.len());
+            nodes.push(RegionNode {
+                cluster_id: *cluster_id,
+                average_rgba: (
+                    (sum.0 / len) as u8,
+                    (sum.1 / len) as u8,
+                    (sum.2 / len) as u8,
+                    (sum.3 / len) as u8,
+                ),
+                size: pixels.len() as f32 / img_size as f32,
+                centroid: (
+                    sum_x as f32 / len as f32 / width as f32,
+                    sum_y as f32 / len as f32 / height as f32,
+                ),
+            });
+        }
+
+        // Count the number of neighbouring pixel pairs between each pair of regions
+        let mut border_counts: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        let cluster_lookup = image.cluster_lookup();
+        for ((x, y), cluster) in cluster_lookup {
+            let Some(node) = node_lookup.get(cluster) else {
+                continue;
+            };
+            for neighbour_coords in [(x + 1, *y), (*x, y + 1)] {
+                if let Some(neighbour_node) = cluster_lookup
+                    .get(&neighbour_coords)
+                    .and_then(|neighbour_cluster| node_lookup.get(neighbour_cluster))
+                {
+                    if neighbour_node != node {
+                        *border_counts
+                            .entry((*node.min(neighbour_node), *node.max(neighbour_node)))
+                            .or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        let total_border: usize = border_counts.values().sum();
+        let edges = border_counts
+            .into_iter()
+            .map(|(key, count)| (key, count as f32 / total_border as f32))
+            .collect();
+
+        RegionGraph { nodes, edges }
+    }
+
+    /// Returns the regions of the graph.
+    pub fn nodes(&self) -> &Vec<RegionNode> {
+        &self.nodes
+    }
+
+    /// Returns the proportion of all shared border between regions that lies between the two given regions.
+    pub fn edge(&self, node_a: usize, node_b: usize) -> Option<f32> {
+        self.edges
+            .get(&(node_a.min(node_b), node_a.max(node_b)))
+            .copied()
+    }
+
+    fn neighbours(&self, node: usize) -> Vec<(usize, f32)> {
+        self.edges
+            .iter()
+            .filter_map(|((a, b), weight)| {
+                if *a == node {
+                    Some((*b, *weight))
+                } else if *b == node {
+                    Some((*a, *weight))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Return the similarity of this graph to another, ranging from -1 to 1. Returns [Option::None] if either graph has no nodes.
+    ///
+    /// Node matches are refined over `iterations` rounds by how well their neighbours match.
+    pub fn similarity(&self, other: &RegionGraph, iterations: usize) -> Option<f32> {
+        if self.nodes.is_empty() || other.nodes.is_empty() {
+            return None;
+        }
+
+        // How alike each pair of nodes is by themselves, from 0 to 1
+        let node_similarities: Vec<Vec<f32>> = self
+            .nodes
+            .iter()
+            .map(|node_a| {
+                other
+                    .nodes
+                    .iter()
+                    .map(|node_b| node_similarity(node_a, node_b))
+                    .collect()
+            })
+            .collect();
+
+        // Refine each pair by how well the nodes' neighbourhoods can be matched
+        let neighbours_a: Vec<Vec<(usize, f32)>> =
+            (0..self.nodes.len()).map(|i| self.neighbours(i)).collect();
+        let neighbours_b: Vec<Vec<(usize, f32)>> = (0..other.nodes.len())
+            .map(|j| other.neighbours(j))
+            .collect();
+        let mut match_similarities = node_similarities.clone();
+        for _ in 0..iterations {
+            match_similarities = (0..self.nodes.len())
+                .map(|i| {
+                    (0..other.nodes.len())
+                        .map(|j| {
+                            let support = neighbourhood_support(
+                                &neighbours_a[i],
+                                &neighbours_b[j],
+                                &match_similarities,
+                            );
+                            0.5 * node_similarities[i][j] + 0.5 * support
+                        })
+                        .collect()
+                })
+                .collect();
+        }
+
+        // Greedily match nodes one-to-one, best pairs first
+        let mut candidates: Vec<(usize, usize)> = (0..self.nodes.len())
+            .flat_map(|i| (0..other.nodes.len()).map(move |j| (i, j)))
+            .collect();
+        candidates.sort_by(|(i_a, j_a), (i_b, j_b)| {
+            match_similarities[*i_b][*j_b].total_cmp(&match_similarities[*i_a][*j_a])
+        });
+        let mut matched_a: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut matched_b: Vec<bool> = vec![false; other.nodes.len()];
+        for (i, j) in candidates {
+            if matched_a[i].is_none() && !matched_b[j] {
+                matched_a[i] = Some(j);
+                matched_b[j] = true;
+            }
+        }
+
+        // Nodes: how alike the matched nodes are, weighted by their size
+        let total_size: f32 = self.nodes.iter().map(|node| node.size).sum::<f32>()
+            + other.nodes.iter().map(|node| node.size).sum::<f32>();
+        let node_score = matched_a
+            .iter()
+            .enumerate()
+            .filter_map(|(i, j)| j.map(|j| (i, j)))
+            .map(|(i, j)| node_similarities[i][j] * (self.nodes[i].size + other.nodes[j].size))
+            .sum::<f32>()
+            / total_size;
+
+        // Edges: how much border the matched nodes share in both graphs, and whether they are arranged in the same direction
+        let edge_score = if self.edges.is_empty() && other.edges.is_empty() {
+            1.0
+        } else {
+            self.edges
+                .iter()
+                .filter_map(|((a, b), weight_a)| {
+                    let (j_a, j_b) = (matched_a[*a]?, matched_a[*b]?);
+                    let weight_b = other.edge(j_a, j_b)?;
+                    Some(
+                        weight_a.min(weight_b)
+                            * direction_agreement(
+                                (&self.nodes[*a], &self.nodes[*b]),
+                                (&other.nodes[j_a], &other.nodes[j_b]),
+                            ),
+                    )
+                })
+                .sum::<f32>()
+        };
+
+        Some((node_score + edge_score) - 1.0)
+    }
+}
+
+// How alike two nodes are by colour, size and location, from 0 to 1.
+fn node_similarity(node_a: &RegionNode, node_b: &RegionNode) -> f32 {
+    let (r_a, g_a, b_a, a_a) = node_a.average_rgba;
+    let (r_b, g_b, b_b, a_b) = node_b.average_rgba;
+    let similarity = (avg_colour_sim(r_a, g_a, b_a, a_a, r_b, g_b, b_b, a_b)
+        + proportional_similarity(node_a.size, node_b.size)
+        + proportional_similarity_coords(&node_a.centroid, &node_b.centroid))
+        / 3.0;
+    (similarity + 1.0) / 2.0
+}
+
+// For each neighbour of node a, find its best-matching neighbour of node b, weighted by border length.
+fn neighbourhood_support(
+    neighbours_a: &[(usize, f32)],
+    neighbours_b: &[(usize, f32)],
+    match_similarities: &[Vec<f32>],
+) -> f32 {
+    match (neighbours_a.is_empty(), neighbours_b.is_empty()) {
+        (true, true) => 1.0,
+        (false, false) => {
+            let total_weight: f32 = neighbours_a.iter().map(|(_, weight)| weight).sum();
+            neighbours_a
+                .iter()
+                .map(|(u, weight)| {
+                    weight
+                        * neighbours_b
+                            .iter()
+                            .map(|(v, _)| match_similarities[*u][*v])
+                            .fold(0.0, f32::max)
+                })
+                .sum::<f32>()
+                / total_weight
+        }
+        _ => 0.0,
+    }
+}
+
+// Whether the offset between two nodes' centroids points the same way in both graphs, from 0 to 1.
+fn direction_agreement(
+    (node_a, node_b): (&RegionNode, &RegionNode),
+    (node_c, node_d): (&RegionNode, &RegionNode),
+) -> f32 {
+    let offset_ab = (
+        node_b.centroid.0 - node_a.centroid.0,
+        node_b.centroid.1 - node_a.centroid.1,
+    );
+    let offset_cd = (
+        node_d.centroid.0 - node_c.centroid.0,
+        node_d.centroid.1 - node_c.centroid.1,
+    );
+    let length = (offset_ab.0.hypot(offset_ab.1)) * (offset_cd.0.hypot(offset_cd.1));
+    if length == 0.0 {
+        return 1.0;
+    }
+    let cosine = (offset_ab.0 * offset_cd.0 + offset_ab.1 * offset_cd.1) / length;
+    (1.0 + cosine) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn node(rgb: (u8, u8, u8), size: f32, centroid: (f32, f32)) -> RegionNode {
+        RegionNode {
+            cluster_id: 0,
+            average_rgba: (rgb.0, rgb.1, rgb.2, 255),
+            size,
+            centroid,
+        }
+    }
+
+    // Three horizontal bands, top to bottom
+    fn bands(colours: [(u8, u8, u8); 3]) -> RegionGraph {
+        RegionGraph {
+            nodes: vec![
+                node(colours[0], 1.0 / 3.0, (0.5, 1.0 / 6.0)),
+                node(colours[1], 1.0 / 3.0, (0.5, 0.5)),
+                node(colours[2], 1.0 / 3.0, (0.5, 5.0 / 6.0)),
+            ],
+            edges: BTreeMap::from([((0, 1), 0.5), ((1, 2), 0.5)]),
+        }
+    }
+
+    const BLUE: (u8, u8, u8) = (30, 60, 220);
+    const GREEN: (u8, u8, u8) = (40, 200, 40);
+    const BROWN: (u8, u8, u8) = (120, 80, 30);
+
+    #[test]
+    fn rg_same() {
+        let graph = bands([BLUE, GREEN, BROWN]);
+        let similarity = graph.similarity(&graph, 3).unwrap();
+        assert_eq!((similarity * 1000.0).round() / 1000.0, 1.0);
+    }
+
+    #[test]
+    fn rg_rearranged() {
+        let graph = bands([BLUE, GREEN, BROWN]);
+        let rearranged = bands([BROWN, BLUE, GREEN]);
+        assert!(graph.similarity(&rearranged, 3).unwrap() < graph.similarity(&graph, 3).unwrap());
+    }
+
+    #[test]
+    fn rg_empty() {
+        let graph = bands([BLUE, GREEN, BROWN]);
+        let empty = RegionGraph {
+            nodes: Vec::new(),
+            edges: BTreeMap::new(),
+        };
+        assert_eq!(graph.similarity(&empty, 3), None);
+    }
+
+    #[test]
+    fn da_opposite() {
+        let (top, bottom) = (node(BLUE, 0.5, (0.5, 0.25)), node(GREEN, 0.5, (0.5, 0.75)));
+        assert_eq!(direction_agreement((&top, &bottom), (&top, &bottom)), 1.0);
+        assert_eq!(direction_agreement((&top, &bottom), (&bottom, &top)), 0.0);
+    }
+}
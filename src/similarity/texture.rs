@@ -0,0 +1,121 @@
+#![warn(missing_docs)]
+//! Local binary pattern (LBP) texture descriptors: <https://en.wikipedia.org/wiki/Local_binary_patterns>
+
+use image::{imageops, RgbaImage};
+
+/// The number of distinct uniform LBP codes for 8 neighbours, plus one bin shared by every non-uniform code.
+pub const LBP_BINS: usize = 59;
+
+// Neighbours in circular order, starting from the top-left.
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+];
+
+// Map each 8-bit LBP code to its histogram bin. Uniform codes (at most two 0/1 transitions
+// around the circle) each get their own bin; every other code shares the last bin.
+fn uniform_bins() -> [usize; 256] {
+    let mut bins = [LBP_BINS - 1; 256];
+    let mut next_bin = 0;
+    for (code, bin) in bins.iter_mut().enumerate() {
+        let code = code as u8;
+        if (code ^ code.rotate_left(1)).count_ones() <= 2 {
+            *bin = next_bin;
+            next_bin += 1;
+        }
+    }
+    bins
+}
+
+/// Build a uniform LBP histogram for each cell of a `grid_size` * `grid_size` grid laid over a grayscale version of the image.
+///
+/// The histograms are normalised and concatenated, row by row, into a single vector of length `grid_size² * LBP_BINS`.
+pub fn lbp_histograms(rgba_image: &RgbaImage, grid_size: u32) -> Vec<f32> {
+    let grid_size = grid_size.max(1);
+    let gray_image = imageops::grayscale(rgba_image);
+    let (width, height) = gray_image.dimensions();
+    let bins = uniform_bins();
+
+    let mut histograms = vec![0.0_f32; (grid_size * grid_size) as usize * LBP_BINS];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let centre = gray_image.get_pixel(x, y)[0];
+            let code = NEIGHBOURS
+                .iter()
+                .enumerate()
+                .fold(0_u8, |code, (bit, (dx, dy))| {
+                    let neighbour =
+                        gray_image.get_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32)[0];
+                    if neighbour >= centre {
+                        code | (1 << bit)
+                    } else {
+                        code
+                    }
+                });
+            let cell = (y * grid_size / height) * grid_size + (x * grid_size / width);
+            histograms[cell as usize * LBP_BINS + bins[code as usize]] += 1.0;
+        }
+    }
+
+    histograms.chunks_mut(LBP_BINS).for_each(|histogram| {
+        let total: f32 = histogram.iter().sum();
+        if total > 0.0 {
+            histogram.iter_mut().for_each(|count| *count /= total);
+        }
+    });
+    histograms
+}
+
+/// Return the chi-square distance between two normalised histograms, ranging from 0 (identical) to 1 (disjoint).
+pub fn chi_square(histogram_a: &[f32], histogram_b: &[f32]) -> f32 {
+    0.5 * histogram_a
+        .iter()
+        .zip(histogram_b)
+        .filter(|(a, b)| *a + *b > 0.0)
+        .map(|(a, b)| (a - b).powf(2.0) / (a + b))
+        .sum::<f32>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn uniform_bin_count() {
+        let bins = uniform_bins();
+        assert_eq!(bins.iter().filter(|bin| **bin < LBP_BINS - 1).count(), 58);
+        assert_eq!(*bins.iter().max().unwrap(), LBP_BINS - 1);
+    }
+
+    #[test]
+    fn lbp_flat() {
+        let flat = RgbaImage::from_pixel(8, 8, image::Rgba([120, 80, 40, 255]));
+        let histogram = lbp_histograms(&flat, 1);
+        // Every neighbour equals the centre, so every pixel has code 255.
+        assert_eq!(histogram[uniform_bins()[255]], 1.0);
+        assert_eq!(histogram.iter().sum::<f32>(), 1.0);
+    }
+
+    #[test]
+    fn lbp_grid_len() {
+        let flat = RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 0, 255]));
+        assert_eq!(lbp_histograms(&flat, 3).len(), 9 * LBP_BINS);
+    }
+
+    #[test]
+    fn chi_square_same() {
+        assert_eq!(chi_square(&[0.25, 0.75, 0.0], &[0.25, 0.75, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn chi_square_disjoint() {
+        assert_eq!(chi_square(&[1.0, 0.0], &[0.0, 1.0]), 1.0);
+    }
+}